@@ -3,13 +3,26 @@ use bevy_kira_audio::prelude::*;
 
 /// This example shows how to load a sound file with applied audio settings.
 /// You can also easily apply settings when playing a sound (see the `settings` example).
+///
+/// Press space to play a sound event, that picks a random variation every time.
 fn main() {
     App::new()
         .add_plugins((DefaultPlugins, AudioPlugin))
         .add_systems(Startup, play_audio)
+        .add_systems(Update, play_sound_event)
         .run();
 }
 
 fn play_audio(asset_server: Res<AssetServer>, audio: Res<Audio>) {
     audio.play(asset_server.load("sounds/loop_with_settings.ogg.ron"));
 }
+
+fn play_sound_event(
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    if input.just_pressed(KeyCode::Space) {
+        audio.play_sound_event(asset_server.load("sounds/plops.sound_event.ron"));
+    }
+}
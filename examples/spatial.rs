@@ -2,6 +2,7 @@ use bevy::input::mouse::MouseMotion;
 use bevy::prelude::*;
 use bevy::window::{CursorGrabMode, CursorOptions, PrimaryWindow};
 use bevy_kira_audio::{SpatialRadius, prelude::*};
+use std::f32::consts::PI;
 
 /// This example demonstrates the basic spatial audio support in `bevy_kira_audio`.
 /// It adds `SpatialAudioPlugin` then spawns entities with `SpatialAudioEmitter`
@@ -50,6 +51,13 @@ fn setup(
         SpatialAudioEmitter {
             instances: vec![elevator_music],
         },
+        // the box is only loud in front of its opening
+        SpatialAudioCone {
+            inner_angle: PI / 2.,
+            outer_angle: PI,
+            outer_gain: -12.,
+            outer_low_pass: None,
+        },
     ));
     // If an emitter has no SpatialRadius, the resource DefaultSpatialRadius is used instead.
     // It defaults to a spatial radius of 25.
@@ -5,33 +5,46 @@ use crate::audio_output::{play_audio_channel, update_instance_states};
 use crate::channel::AudioCommandQue;
 use crate::channel::typed::AudioChannel;
 use crate::instance::AudioInstance;
+use crate::mixer::TypedChannelNames;
+use crate::modulator::{AudioModulator, MissingModulator, ModulatedValue, ModulatorMapping};
+use crate::rng::AudioRng;
+use crate::sound_event::SoundEvent;
 use crate::source::AudioSource;
+use crate::source::custom::CustomAudioSource;
+use crate::voice::{AudioDebounce, VoiceLimit};
 use bevy::app::{App, PreUpdate};
-use bevy::asset::Handle;
+use bevy::asset::{Assets, Handle};
 use bevy::ecs::entity::Entity;
 use bevy::ecs::resource::Resource;
 use bevy::ecs::schedule::IntoScheduleConfigs;
 use bevy::prelude::{PostUpdate, default};
-use kira::sound::EndPosition;
 use kira::sound::static_sound::{StaticSoundData, StaticSoundHandle};
-use kira::{Decibels, Panning, Value};
+use kira::sound::{EndPosition, Region};
+use kira::{Decibels, Panning, PlaybackRate, Value};
 use std::marker::PhantomData;
+use std::ops::RangeInclusive;
 use std::time::Duration;
 use uuid::Uuid;
 
 #[derive(Debug)]
 pub(crate) enum AudioCommand {
-    Play(PlayAudioSettings),
+    Play(Box<PlayAudioSettings>),
     SetVolume(Decibels, Option<AudioTween>),
     SetPanning(Panning, Option<AudioTween>),
     SetPlaybackRate(f64, Option<AudioTween>),
+    SetVolumeModulator(ModulatedValue, Option<AudioTween>),
+    SetPanningModulator(ModulatedValue, Option<AudioTween>),
+    SetPlaybackRateModulator(ModulatedValue, Option<AudioTween>),
     Stop(Option<AudioTween>),
     Pause(Option<AudioTween>),
     Resume(Option<AudioTween>),
+    SetVoiceLimit(Option<VoiceLimit>),
+    SetDebounce(Option<AudioDebounce>),
 }
 
 #[derive(Clone, Default, Debug)]
 pub(crate) struct PartialSoundSettings {
+    pub(crate) looped: bool,
     pub(crate) loop_start: Option<f64>,
     pub(crate) loop_end: Option<f64>,
     pub(crate) volume: Option<Decibels>,
@@ -42,6 +55,24 @@ pub(crate) struct PartialSoundSettings {
     pub(crate) paused: bool,
     pub(crate) fade_in: Option<AudioTween>,
     pub(crate) emitter: Option<Entity>,
+    pub(crate) priority: i32,
+    pub(crate) random_volume: Option<RangeInclusive<f32>>,
+    pub(crate) random_playback_rate: Option<RandomPlaybackRate>,
+    pub(crate) random_start_position: Option<RangeInclusive<f64>>,
+    pub(crate) volume_modulator: Option<ModulatedValue>,
+    pub(crate) playback_rate_modulator: Option<ModulatedValue>,
+    pub(crate) panning_modulator: Option<ModulatedValue>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum RandomPlaybackRate {
+    PlaybackRate(RangeInclusive<f64>),
+    Semitones(RangeInclusive<f64>),
+}
+
+/// Playback rate that changes the pitch of a sound by the given number of semitones
+pub(crate) fn semitones_to_playback_rate(semitones: f64) -> f64 {
+    2f64.powf(semitones / 12.)
 }
 
 /// Different kinds of easing for fade-in and fade-out
@@ -108,7 +139,117 @@ impl From<&AudioTween> for kira::Tween {
 }
 
 impl PartialSoundSettings {
-    pub(crate) fn apply(&self, sound: &mut StaticSoundData) {
+    /// Replace the random ranges with values sampled from them
+    pub(crate) fn randomise(&mut self, rng: &mut AudioRng) {
+        if let Some(volume) = self.random_volume.take() {
+            self.volume = Some(rng.range_f32(&volume).into());
+        }
+        match self.random_playback_rate.take() {
+            Some(RandomPlaybackRate::PlaybackRate(playback_rate)) => {
+                self.playback_rate = Some(rng.range_f64(&playback_rate));
+            }
+            Some(RandomPlaybackRate::Semitones(semitones)) => {
+                self.playback_rate = Some(semitones_to_playback_rate(rng.range_f64(&semitones)));
+            }
+            None => {}
+        }
+        if let Some(start_position) = self.random_start_position.take() {
+            self.start_position = Some(rng.range_f64(&start_position));
+        }
+    }
+
+    fn modulated_values(&self) -> impl Iterator<Item = &ModulatedValue> {
+        [
+            &self.volume_modulator,
+            &self.playback_rate_modulator,
+            &self.panning_modulator,
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    /// Names of the settings that sounds of a [`CustomAudioSource`](crate::CustomAudioSource)
+    /// do not support
+    pub(crate) fn unsupported_by_custom_sources(&self, loopable: bool) -> Vec<&'static str> {
+        [
+            (self.looped && !loopable, "looped"),
+            (
+                self.loop_start.is_some() || self.loop_end.is_some(),
+                "loop region",
+            ),
+            (self.start_position.is_some(), "start position"),
+            (self.reverse.is_some(), "reverse"),
+            (self.volume_modulator.is_some(), "volume modulator"),
+            (
+                self.playback_rate_modulator.is_some(),
+                "playback rate modulator",
+            ),
+            (self.panning_modulator.is_some(), "panning modulator"),
+        ]
+        .into_iter()
+        .filter_map(|(used, name)| used.then_some(name))
+        .collect()
+    }
+
+    /// The first modulator of these settings that failed or is not added to the audio thread yet
+    ///
+    /// Failed modulators are reported before pending ones.
+    pub(crate) fn missing_modulator(
+        &self,
+        modulators: &Assets<AudioModulator>,
+    ) -> Option<MissingModulator> {
+        let mut missing = None;
+        for value in self.modulated_values() {
+            match modulators.get(&value.modulator) {
+                None => missing = Some(MissingModulator::Pending),
+                Some(modulator) if modulator.is_failed() => {
+                    return Some(MissingModulator::Failed);
+                }
+                Some(_) => {}
+            }
+        }
+
+        missing
+    }
+
+    /// Map properties to modulators
+    ///
+    /// This overwrites the fixed values of [`PartialSoundSettings::apply`].
+    pub(crate) fn apply_modulators(
+        &self,
+        sound: &mut StaticSoundData,
+        modulators: &Assets<AudioModulator>,
+    ) {
+        if let Some(volume) = self.volume_modulator.as_ref().and_then(|value| {
+            value
+                .value(modulators, |volume| Decibels(volume as f32))
+                .ok()
+        }) {
+            sound.settings.volume = volume;
+        }
+        if let Some(playback_rate) = self
+            .playback_rate_modulator
+            .as_ref()
+            .and_then(|value| value.value(modulators, PlaybackRate).ok())
+        {
+            sound.settings.playback_rate = playback_rate;
+        }
+        if let Some(panning) = self.panning_modulator.as_ref().and_then(|value| {
+            value
+                .value(modulators, |panning| Panning(panning as f32))
+                .ok()
+        }) {
+            sound.settings.panning = panning;
+        }
+    }
+
+    /// Apply the settings to the sound of an audio source with the given loop region
+    pub(crate) fn apply(&self, sound: &mut StaticSoundData, source_loop_region: Option<Region>) {
+        if self.looped {
+            // a loop region in the settings of the sound wins over the one of the source
+            let loop_region = sound.settings.loop_region.or(source_loop_region);
+            sound.settings.loop_region = Some(loop_region.unwrap_or_default());
+        }
         if let Some(loop_start) = self.loop_start {
             sound
                 .settings
@@ -148,10 +289,18 @@ impl PartialSoundSettings {
     }
 }
 
+/// What a play command plays
+#[derive(Clone, Debug)]
+pub(crate) enum PlaySource {
+    Audio(Handle<AudioSource>),
+    SoundEvent(Handle<SoundEvent>),
+    Custom(Handle<CustomAudioSource>),
+}
+
 #[derive(Clone, Debug)]
 pub struct PlayAudioSettings {
     pub(crate) instance_handle: Handle<AudioInstance>,
-    pub(crate) source: Handle<AudioSource>,
+    pub(crate) source: PlaySource,
     pub(crate) settings: PartialSoundSettings,
 }
 
@@ -168,19 +317,19 @@ impl<'a> From<&mut PlayAudioCommand<'a>> for PlayAudioSettings {
 /// A command for interacting with playing sound.
 pub struct PlayAudioCommand<'a> {
     pub(crate) instance_handle: Handle<AudioInstance>,
-    pub(crate) source: Handle<AudioSource>,
+    pub(crate) source: PlaySource,
     pub(crate) settings: PartialSoundSettings,
     pub(crate) que: &'a dyn AudioCommandQue,
 }
 
 impl Drop for PlayAudioCommand<'_> {
     fn drop(&mut self) {
-        self.que.que(AudioCommand::Play(self.into()));
+        self.que.que(AudioCommand::Play(Box::new(self.into())));
     }
 }
 
 impl<'a> PlayAudioCommand<'a> {
-    pub(crate) fn new(source: Handle<AudioSource>, que: &'a dyn AudioCommandQue) -> Self {
+    pub(crate) fn new(source: PlaySource, que: &'a dyn AudioCommandQue) -> Self {
         let uuid = Uuid::new_v4();
         Self {
             instance_handle: Handle::<AudioInstance>::Uuid(uuid, PhantomData),
@@ -191,8 +340,12 @@ impl<'a> PlayAudioCommand<'a> {
     }
 
     /// Loop the playing sound.
+    ///
+    /// Sounds loop over their whole duration, unless their audio source has a loop region,
+    /// for example from loop points in the metadata of the file. The loop region of an audio
+    /// source is only used by looped sounds.
     pub fn looped(&mut self) -> &mut Self {
-        self.settings.loop_start = Some(0.0);
+        self.settings.looped = true;
 
         self
     }
@@ -221,6 +374,17 @@ impl<'a> PlayAudioCommand<'a> {
     /// Set the volume of the sound in Decibels
     pub fn with_volume(&mut self, volume: impl Into<Decibels>) -> &mut Self {
         self.settings.volume = Some(volume.into());
+        self.settings.random_volume = None;
+        self.settings.volume_modulator = None;
+
+        self
+    }
+
+    /// Set the volume of the sound to a random value in the given range in Decibels.
+    ///
+    /// The value is drawn once from the [`AudioRng`] resource when the command is first run.
+    pub fn with_random_volume(&mut self, volume: RangeInclusive<f32>) -> &mut Self {
+        self.settings.random_volume = Some(volume);
 
         self
     }
@@ -228,6 +392,34 @@ impl<'a> PlayAudioCommand<'a> {
     /// Set the playback rate of the sound.
     pub fn with_playback_rate(&mut self, playback_rate: f64) -> &mut Self {
         self.settings.playback_rate = Some(playback_rate);
+        self.settings.random_playback_rate = None;
+        self.settings.playback_rate_modulator = None;
+
+        self
+    }
+
+    /// Set the playback rate of the sound to a random value in the given range.
+    ///
+    /// The value is drawn once from the [`AudioRng`] resource when the command is first run.
+    pub fn with_random_playback_rate(&mut self, playback_rate: RangeInclusive<f64>) -> &mut Self {
+        self.settings.random_playback_rate = Some(RandomPlaybackRate::PlaybackRate(playback_rate));
+
+        self
+    }
+
+    /// Change the pitch of the sound by the given number of semitones.
+    ///
+    /// This sets the playback rate, so the speed of the sound changes as well.
+    /// Twelve semitones double the playback rate.
+    pub fn with_semitones(&mut self, semitones: f64) -> &mut Self {
+        self.with_playback_rate(semitones_to_playback_rate(semitones))
+    }
+
+    /// Change the pitch of the sound by a random number of semitones in the given range.
+    ///
+    /// The value is drawn once from the [`AudioRng`] resource when the command is first run.
+    pub fn with_random_semitones(&mut self, semitones: RangeInclusive<f64>) -> &mut Self {
+        self.settings.random_playback_rate = Some(RandomPlaybackRate::Semitones(semitones));
 
         self
     }
@@ -235,6 +427,16 @@ impl<'a> PlayAudioCommand<'a> {
     /// Start the sound from the given position in seconds.
     pub fn start_from(&mut self, start_position: f64) -> &mut Self {
         self.settings.start_position = Some(start_position);
+        self.settings.random_start_position = None;
+
+        self
+    }
+
+    /// Start the sound from a random position in the given range in seconds.
+    ///
+    /// The value is drawn once from the [`AudioRng`] resource when the command is first run.
+    pub fn with_random_start_position(&mut self, start_position: RangeInclusive<f64>) -> &mut Self {
+        self.settings.random_start_position = Some(start_position);
 
         self
     }
@@ -246,6 +448,46 @@ impl<'a> PlayAudioCommand<'a> {
     /// while values down to -1.0 pan to the left.
     pub fn with_panning(&mut self, panning: impl Into<Panning>) -> &mut Self {
         self.settings.panning = Some(panning.into());
+        self.settings.panning_modulator = None;
+
+        self
+    }
+
+    /// Map the volume of the sound in Decibels to the output of a modulator.
+    ///
+    /// The sound is played once the modulator was added to the audio thread.
+    pub fn with_volume_modulator(
+        &mut self,
+        modulator: Handle<AudioModulator>,
+        mapping: ModulatorMapping,
+    ) -> &mut Self {
+        self.settings.volume_modulator = Some(ModulatedValue::new(modulator, mapping));
+
+        self
+    }
+
+    /// Map the playback rate of the sound to the output of a modulator.
+    ///
+    /// The sound is played once the modulator was added to the audio thread.
+    pub fn with_playback_rate_modulator(
+        &mut self,
+        modulator: Handle<AudioModulator>,
+        mapping: ModulatorMapping,
+    ) -> &mut Self {
+        self.settings.playback_rate_modulator = Some(ModulatedValue::new(modulator, mapping));
+
+        self
+    }
+
+    /// Map the panning of the sound to the output of a modulator.
+    ///
+    /// The sound is played once the modulator was added to the audio thread.
+    pub fn with_panning_modulator(
+        &mut self,
+        modulator: Handle<AudioModulator>,
+        mapping: ModulatorMapping,
+    ) -> &mut Self {
+        self.settings.panning_modulator = Some(ModulatedValue::new(modulator, mapping));
 
         self
     }
@@ -278,17 +520,31 @@ impl<'a> PlayAudioCommand<'a> {
         self.instance_handle.clone()
     }
     /// Play this sound from the location of the given entity.
-    /// The entity must have a `SpatialAudioEmitter` component.
+    /// The entity must have a `SpatialAudioEmitter` component and the `SpatialAudioPlugin` must be
+    /// added. Otherwise, the sound plays without any spatial effects.
     pub fn with_emitter(&mut self, emitter_entity: Entity) -> &mut Self {
         self.settings.emitter = Some(emitter_entity);
         self
     }
+
+    /// Set the priority of the sound.
+    ///
+    /// When a [`VoiceLimit`] is reached, sounds with a higher priority are never stopped
+    /// to make room for this sound. The default priority is 0.
+    pub fn with_priority(&mut self, priority: i32) -> &mut Self {
+        self.settings.priority = priority;
+
+        self
+    }
 }
 
 pub(crate) enum TweenCommandKind {
     SetVolume(Decibels),
     SetPanning(Panning),
     SetPlaybackRate(f64),
+    SetVolumeModulator(ModulatedValue),
+    SetPanningModulator(ModulatedValue),
+    SetPlaybackRateModulator(ModulatedValue),
     Stop,
     Pause,
     Resume,
@@ -302,6 +558,15 @@ impl TweenCommandKind {
             TweenCommandKind::SetPlaybackRate(playback_rate) => {
                 AudioCommand::SetPlaybackRate(*playback_rate, tween)
             }
+            TweenCommandKind::SetVolumeModulator(value) => {
+                AudioCommand::SetVolumeModulator(value.clone(), tween)
+            }
+            TweenCommandKind::SetPanningModulator(value) => {
+                AudioCommand::SetPanningModulator(value.clone(), tween)
+            }
+            TweenCommandKind::SetPlaybackRateModulator(value) => {
+                AudioCommand::SetPlaybackRateModulator(value.clone(), tween)
+            }
             TweenCommandKind::Stop => AudioCommand::Stop(tween),
             TweenCommandKind::Pause => AudioCommand::Pause(tween),
             TweenCommandKind::Resume => AudioCommand::Resume(tween),
@@ -443,18 +708,19 @@ impl From<StaticSoundHandle> for PlaybackState {
 
 impl From<&StaticSoundHandle> for PlaybackState {
     fn from(sound_handle: &StaticSoundHandle) -> Self {
-        let position = sound_handle.position();
-        match sound_handle.state() {
-            kira::sound::PlaybackState::Playing => PlaybackState::Playing { position },
-            kira::sound::PlaybackState::Paused => PlaybackState::Paused { position },
-            kira::sound::PlaybackState::Stopped => PlaybackState::Stopped,
-            kira::sound::PlaybackState::Pausing => PlaybackState::Pausing { position },
-            kira::sound::PlaybackState::Stopping => PlaybackState::Stopping { position },
-            kira::sound::PlaybackState::WaitingToResume => {
-                PlaybackState::WaitingToResume { position }
-            }
-            kira::sound::PlaybackState::Resuming => PlaybackState::Resuming { position },
-        }
+        playback_state(sound_handle.state(), sound_handle.position())
+    }
+}
+
+pub(crate) fn playback_state(state: kira::sound::PlaybackState, position: f64) -> PlaybackState {
+    match state {
+        kira::sound::PlaybackState::Playing => PlaybackState::Playing { position },
+        kira::sound::PlaybackState::Paused => PlaybackState::Paused { position },
+        kira::sound::PlaybackState::Stopped => PlaybackState::Stopped,
+        kira::sound::PlaybackState::Pausing => PlaybackState::Pausing { position },
+        kira::sound::PlaybackState::Stopping => PlaybackState::Stopping { position },
+        kira::sound::PlaybackState::WaitingToResume => PlaybackState::WaitingToResume { position },
+        kira::sound::PlaybackState::Resuming => PlaybackState::Resuming { position },
     }
 }
 
@@ -487,6 +753,9 @@ pub trait AudioApp {
 
 impl AudioApp for App {
     fn add_audio_channel<T: Resource>(&mut self) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_init::<TypedChannelNames>()
+            .register::<T>();
         self.add_systems(
             PostUpdate,
             play_audio_channel::<T>.in_set(AudioSystemSet::PlayTypedChannels),
@@ -498,3 +767,122 @@ impl AudioApp for App {
         .insert_resource(AudioChannel::<T>::default())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use kira::Frame;
+    use kira::sound::static_sound::StaticSoundSettings;
+    use kira::sound::{PlaybackPosition, Region};
+    use std::sync::Arc;
+
+    #[test]
+    fn twelve_semitones_double_the_playback_rate() {
+        assert_eq!(semitones_to_playback_rate(12.), 2.);
+        assert_eq!(semitones_to_playback_rate(-12.), 0.5);
+        assert_eq!(semitones_to_playback_rate(0.), 1.);
+    }
+
+    fn two_second_sound() -> StaticSoundData {
+        StaticSoundData {
+            sample_rate: 100,
+            frames: Arc::from(vec![Frame::ZERO; 200]),
+            settings: StaticSoundSettings::default(),
+            slice: None,
+        }
+    }
+
+    fn tagged_loop_region() -> Region {
+        Region {
+            start: PlaybackPosition::Samples(50),
+            end: EndPosition::Custom(PlaybackPosition::Samples(150)),
+        }
+    }
+
+    #[test]
+    fn looped_keeps_the_loop_region_of_the_source() {
+        let settings = PartialSoundSettings {
+            looped: true,
+            ..Default::default()
+        };
+        let mut sound = two_second_sound();
+        settings.apply(&mut sound, None);
+        assert!(sound.settings.loop_region.is_some());
+
+        let mut sound = two_second_sound();
+        settings.apply(&mut sound, Some(tagged_loop_region()));
+        let loop_region = sound.settings.loop_region.unwrap();
+        assert!(matches!(loop_region.start, PlaybackPosition::Samples(50)));
+        assert!(matches!(
+            loop_region.end,
+            EndPosition::Custom(PlaybackPosition::Samples(150))
+        ));
+    }
+
+    #[test]
+    fn sources_with_a_loop_region_do_not_loop_without_looped() {
+        let mut sound = two_second_sound();
+        PartialSoundSettings::default().apply(&mut sound, Some(tagged_loop_region()));
+
+        assert!(sound.settings.loop_region.is_none());
+    }
+
+    #[test]
+    fn randomise_replaces_ranges_with_values() {
+        let mut settings = PartialSoundSettings {
+            random_volume: Some(-12.0..=-6.0),
+            random_playback_rate: Some(RandomPlaybackRate::Semitones(12.0..=12.0)),
+            random_start_position: Some(0.5..=1.0),
+            ..Default::default()
+        };
+
+        settings.randomise(&mut AudioRng::from_seed(1));
+
+        assert!((-12.0..=-6.0).contains(&settings.volume.unwrap().0));
+        assert_eq!(settings.playback_rate, Some(2.));
+        assert!((0.5..=1.0).contains(&settings.start_position.unwrap()));
+        assert!(settings.random_volume.is_none());
+        assert!(settings.random_playback_rate.is_none());
+        assert!(settings.random_start_position.is_none());
+    }
+
+    #[test]
+    fn lists_settings_unsupported_by_custom_sources() {
+        let settings = PartialSoundSettings {
+            looped: true,
+            reverse: Some(true),
+            volume: Some(Decibels(-6.)),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            settings.unsupported_by_custom_sources(false),
+            vec!["looped", "reverse"]
+        );
+        assert_eq!(
+            settings.unsupported_by_custom_sources(true),
+            vec!["reverse"]
+        );
+    }
+
+    #[test]
+    fn waits_for_modulators_to_be_added() {
+        let modulators = Assets::<AudioModulator>::default();
+        let settings = PartialSoundSettings {
+            volume_modulator: Some(ModulatedValue::new(
+                Handle::Uuid(Uuid::new_v4(), PhantomData),
+                ModulatorMapping::new((-1., 1.), (-12., 0.)),
+            )),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            settings.missing_modulator(&modulators),
+            Some(MissingModulator::Pending)
+        );
+        assert_eq!(
+            PartialSoundSettings::default().missing_modulator(&modulators),
+            None
+        );
+    }
+}
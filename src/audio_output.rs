@@ -1,6 +1,8 @@
 //! The internal audio systems and resource
 
-use crate::audio::{AudioCommand, AudioCommandResult, AudioTween, PartialSoundSettings, map_tween};
+use crate::audio::{
+    AudioCommand, AudioCommandResult, AudioTween, PartialSoundSettings, PlaySource, map_tween,
+};
 use std::any::TypeId;
 
 use crate::PlaybackState;
@@ -8,18 +10,41 @@ use crate::backend_settings::AudioSettings;
 use crate::channel::dynamic::DynamicAudioChannels;
 use crate::channel::typed::AudioChannel;
 use crate::channel::{Channel, ChannelState};
-use crate::instance::AudioInstance;
+use crate::instance::{AudioInstance, SoundHandle};
+use crate::mixer::ChannelSnapshot;
+use crate::modulator::{
+    AudioModulator, MissingModulator, ModulatedValue, ModulatorKind, ModulatorSettings,
+};
+use crate::parameters::AudioProperty;
+use crate::rng::AudioRng;
+use crate::sound_event::{SoundEvent, VariantSelector};
 use crate::source::AudioSource;
-use bevy::asset::{Assets, Handle};
+use crate::source::custom::{CustomAudioSource, CustomDataHandle, CustomSoundSettings};
+use crate::spatial::SpatialReverbParameters;
+use crate::voice::{
+    AudioDebounce, AudioSourcePolicies, DebounceAction, Voice, VoiceLimit, VoiceSource,
+    VoiceStealing, select_victims,
+};
+use bevy::asset::{Asset, AssetId, AssetServer, Assets, Handle, LoadState};
 use bevy::ecs::change_detection::{NonSendMut, ResMut};
+use bevy::ecs::entity::Entity;
 use bevy::ecs::resource::Resource;
-use bevy::ecs::system::{NonSend, Res};
+use bevy::ecs::system::{NonSend, Res, SystemParam};
 use bevy::ecs::world::{FromWorld, World};
-use bevy::log::warn;
+use bevy::log::{debug, warn};
+use bevy::time::Time;
 use kira::backend::{Backend, DefaultBackend};
-use kira::{AudioManager, Panning};
+use kira::effect::filter::{FilterBuilder, FilterHandle};
+use kira::effect::reverb::{ReverbBuilder, ReverbHandle};
+use kira::modulator::lfo::LfoBuilder;
+use kira::modulator::tweener::TweenerBuilder;
+use kira::sound::static_sound::StaticSoundData;
+use kira::sound::{Region, SoundData};
+use kira::track::{SendTrackBuilder, SendTrackHandle, TrackBuilder, TrackHandle};
+use kira::{AudioManager, Mix, Panning, PlaySoundError};
 use kira::{Decibels, PlaybackRate};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 
 /// Non-send resource that acts as audio output
 ///
@@ -29,6 +54,119 @@ pub(crate) struct AudioOutput<B: Backend = DefaultBackend> {
     manager: Option<AudioManager<B>>,
     instances: HashMap<Channel, Vec<Handle<AudioInstance>>>,
     channels: HashMap<Channel, ChannelState>,
+    channel_tracks: HashMap<Channel, ChannelTrack>,
+    /// Entities with a [`SpatialAudioEmitter`](crate::SpatialAudioEmitter)
+    ///
+    /// `None` if the [`SpatialAudioPlugin`](crate::SpatialAudioPlugin) is not active.
+    spatial_emitters: Option<HashSet<Entity>>,
+    emitter_tracks: HashMap<Entity, EmitterTrack>,
+    new_emitter_instances: HashMap<Entity, Vec<Handle<AudioInstance>>>,
+    spatial_reverb: Option<SpatialReverb>,
+    voice_sequence: u64,
+    /// Number of sounds that can play at a time on each channel and emitter track
+    sound_capacity: usize,
+    /// Queued plays that already stopped another sound to free a slot on the audio thread
+    ///
+    /// They are retried once in the next frame and dropped if the track is still full.
+    stole_voice: HashSet<AssetId<AudioInstance>>,
+    recent_plays: HashMap<(DebounceScope, VoiceSource), RecentPlay>,
+    variant_selectors: HashMap<AssetId<SoundEvent>, VariantSelector>,
+    /// Channel settings from before the first mixer snapshot changed them
+    snapshot_bases: HashMap<Channel, ChannelSnapshot>,
+}
+
+/// Assets and resources used to run audio commands
+pub(crate) struct AudioCommandContext<'a> {
+    pub(crate) asset_server: &'a AssetServer,
+    pub(crate) audio_sources: &'a Assets<AudioSource>,
+    pub(crate) sound_events: &'a Assets<SoundEvent>,
+    pub(crate) audio_instances: &'a mut Assets<AudioInstance>,
+    pub(crate) policies: &'a AudioSourcePolicies,
+    pub(crate) rng: &'a mut AudioRng,
+    pub(crate) modulators: &'a Assets<AudioModulator>,
+    pub(crate) custom_sources: &'a Assets<CustomAudioSource>,
+    /// Time elapsed since the app started
+    pub(crate) now: Duration,
+}
+
+/// Where a debounce was configured
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum DebounceScope {
+    Channel(Channel),
+    Source,
+}
+
+/// The last started play of a debounced audio source
+struct RecentPlay {
+    started: Duration,
+    expires: Duration,
+    instance: Handle<AudioInstance>,
+    volume: f32,
+    merged_plays: u32,
+}
+
+/// Cutoff frequency in Hz at which a low-pass filter does not noticeably change a sound
+pub(crate) const OPEN_LOW_PASS_CUTOFF: f64 = 20_000.;
+
+/// Kira track that the sounds of one channel are routed through
+///
+/// Sounds played from spatial audio emitters use the track of their emitter instead.
+struct ChannelTrack {
+    track: TrackHandle,
+    low_pass: FilterHandle,
+}
+
+/// Kira track that all sounds played from one spatial audio emitter are routed through
+pub(crate) struct EmitterTrack {
+    track: TrackHandle,
+    low_pass: FilterHandle,
+}
+
+impl EmitterTrack {
+    /// Set the cutoff frequency of the low-pass filter on this track in Hz
+    pub(crate) fn set_low_pass_cutoff(&mut self, cutoff: f64, tween: AudioTween) {
+        self.low_pass.set_cutoff(cutoff, tween.into());
+    }
+}
+
+/// Send track with the reverb of [`ReverbZone`](crate::ReverbZone)s
+///
+/// All emitter tracks send to this track.
+struct SpatialReverb {
+    track: SendTrackHandle,
+    reverb: ReverbHandle,
+    parameters: SpatialReverbParameters,
+}
+
+/// A sound that is ready to be played
+enum PreparedSound {
+    /// Sound data and the loop region of its audio source
+    Static(Box<StaticSoundData>, Option<Region>),
+    Custom(CustomAudioSource),
+}
+
+/// A sound that started playing on the audio thread
+struct PlayedSound {
+    handle: SoundHandle,
+    sound: Option<StaticSoundData>,
+    custom: Option<CustomDataHandle>,
+    playback_rate: f64,
+    /// Initial volume in decibels
+    volume: f32,
+}
+
+enum PlayError {
+    SoundLimitReached,
+    Other(String),
+}
+
+impl<E: std::fmt::Debug> From<PlaySoundError<E>> for PlayError {
+    fn from(error: PlaySoundError<E>) -> Self {
+        match error {
+            PlaySoundError::SoundLimitReached => PlayError::SoundLimitReached,
+            error => PlayError::Other(format!("{error:?}")),
+        }
+    }
 }
 
 impl FromWorld for AudioOutput {
@@ -39,15 +177,30 @@ impl FromWorld for AudioOutput {
             warn!("Failed to setup audio: {:?}", setup_error);
         }
 
+        Self::new(manager.ok(), settings.sound_capacity)
+    }
+}
+
+impl<B: Backend> AudioOutput<B> {
+    fn new(manager: Option<AudioManager<B>>, sound_capacity: usize) -> Self {
         Self {
-            manager: manager.ok(),
+            manager,
             instances: HashMap::default(),
             channels: HashMap::default(),
+            channel_tracks: HashMap::default(),
+            spatial_emitters: None,
+            emitter_tracks: HashMap::default(),
+            new_emitter_instances: HashMap::default(),
+            spatial_reverb: None,
+            voice_sequence: 0,
+            sound_capacity,
+            stole_voice: HashSet::default(),
+            recent_plays: HashMap::default(),
+            variant_selectors: HashMap::default(),
+            snapshot_bases: HashMap::default(),
         }
     }
-}
 
-impl<B: Backend> AudioOutput<B> {
     fn stop(
         &mut self,
         channel: &Channel,
@@ -58,7 +211,7 @@ impl<B: Backend> AudioOutput<B> {
             let tween = map_tween(tween);
             for instance in instances {
                 if let Some(mut instance) = audio_instances.get_mut(instance.id()) {
-                    instance.handle.stop(tween);
+                    instance.stop_with(tween);
                 }
             }
         }
@@ -76,9 +229,9 @@ impl<B: Backend> AudioOutput<B> {
             let tween = map_tween(tween);
             for instance in instance_handles.iter_mut() {
                 if let Some(mut instance) = audio_instances.get_mut(instance.id())
-                    && kira::sound::PlaybackState::Playing == instance.handle.state()
+                    && matches!(instance.state(), PlaybackState::Playing { .. })
                 {
-                    instance.handle.pause(tween);
+                    instance.pause_with(tween);
                 }
             }
         }
@@ -103,11 +256,14 @@ impl<B: Backend> AudioOutput<B> {
             let tween = map_tween(tween);
             for instance in instances.iter_mut() {
                 if let Some(mut instance) = audio_instances.get_mut(instance.id())
-                    && (instance.handle.state() == kira::sound::PlaybackState::Paused
-                        || instance.handle.state() == kira::sound::PlaybackState::Pausing
-                        || instance.handle.state() == kira::sound::PlaybackState::Stopping)
+                    && matches!(
+                        instance.state(),
+                        PlaybackState::Paused { .. }
+                            | PlaybackState::Pausing { .. }
+                            | PlaybackState::Stopping { .. }
+                    )
                 {
-                    instance.handle.resume(tween);
+                    instance.resume_with(tween);
                 }
             }
         }
@@ -126,25 +282,158 @@ impl<B: Backend> AudioOutput<B> {
         volume: Decibels,
         tween: &Option<AudioTween>,
     ) {
+        let channel_state = self.channels.entry(channel.clone()).or_default();
+        channel_state.volume = volume;
+        channel_state.volume_modulator = None;
+        let volume = channel_state.volume;
         if let Some(instances) = self.instances.get_mut(channel) {
             let tween = map_tween(tween);
             for instance in instances.iter_mut() {
                 if let Some(mut instance) = audio_instances.get_mut(instance.id()) {
-                    instance.handle.set_volume(volume, tween);
+                    instance.set_volume_with(volume, tween);
                 }
             }
         }
-        if let Some(channel_state) = self.channels.get_mut(channel) {
-            channel_state.volume = volume;
-        } else {
-            let channel_state = ChannelState {
-                volume,
-                ..Default::default()
-            };
-            self.channels.insert(channel.clone(), channel_state);
+    }
+
+    /// Change the volume of all sounds in a channel by the given amount of decibels on top of the channel volume
+    ///
+    /// The ducking is applied to the track of the channel, so it keeps the volume of each instance.
+    pub(crate) fn set_channel_ducking(
+        &mut self,
+        channel: &Channel,
+        ducking: f32,
+        tween: &AudioTween,
+    ) {
+        if let Some(channel_track) = self.channel_track(channel) {
+            channel_track
+                .track
+                .set_volume(Decibels(ducking), tween.into());
+        }
+    }
+
+    /// Set the cutoff frequency of the low-pass filter on the track of a channel in Hz
+    pub(crate) fn set_channel_low_pass_cutoff(
+        &mut self,
+        channel: &Channel,
+        cutoff: f64,
+        tween: &Option<AudioTween>,
+    ) {
+        self.channels
+            .entry(channel.clone())
+            .or_default()
+            .low_pass_cutoff = cutoff;
+        if let Some(channel_track) = self.channel_track(channel) {
+            channel_track.low_pass.set_cutoff(cutoff, map_tween(tween));
+        }
+    }
+
+    /// Set a property of a channel that is driven by a game parameter
+    pub(crate) fn set_channel_property(
+        &mut self,
+        channel: &Channel,
+        property: AudioProperty,
+        value: f32,
+        audio_instances: &mut Assets<AudioInstance>,
+    ) {
+        let tween = None;
+        match property {
+            AudioProperty::Volume => {
+                self.set_volume(channel, audio_instances, Decibels(value), &tween)
+            }
+            AudioProperty::PlaybackRate => {
+                self.set_playback_rate(channel, audio_instances, value as f64, &tween)
+            }
+            AudioProperty::Panning => {
+                self.set_panning(channel, audio_instances, Panning(value), &tween)
+            }
+            AudioProperty::LowPassCutoff => {
+                self.set_channel_low_pass_cutoff(channel, value as f64, &tween)
+            }
+        }
+    }
+
+    /// Transition all channels to the given mix
+    ///
+    /// Channels that are not part of the mix go back to their settings from before the first snapshot.
+    pub(crate) fn apply_mixer_snapshot(
+        &mut self,
+        channels: &HashMap<Channel, ChannelSnapshot>,
+        tween: &AudioTween,
+        audio_instances: &mut Assets<AudioInstance>,
+    ) {
+        let mut affected: Vec<Channel> = self.snapshot_bases.keys().cloned().collect();
+        affected.extend(
+            channels
+                .keys()
+                .filter(|channel| !self.snapshot_bases.contains_key(*channel))
+                .cloned(),
+        );
+        let tween = Some(tween.clone());
+        for channel in affected {
+            let base = *self
+                .snapshot_bases
+                .entry(channel.clone())
+                .or_insert_with(|| {
+                    let state = self.channels.get(&channel);
+                    ChannelSnapshot {
+                        volume: Some(
+                            state
+                                .map_or(ChannelState::default().volume, |state| state.volume)
+                                .0,
+                        ),
+                        panning: Some(state.map_or(0., |state| state.panning.0)),
+                        playback_rate: Some(state.map_or(1., |state| state.playback_rate)),
+                        low_pass_cutoff: Some(
+                            state.map_or(OPEN_LOW_PASS_CUTOFF, |state| state.low_pass_cutoff),
+                        ),
+                    }
+                });
+            let mut target = base;
+            match channels.get(&channel) {
+                Some(snapshot) => {
+                    target.volume = snapshot.volume.or(base.volume);
+                    target.panning = snapshot.panning.or(base.panning);
+                    target.playback_rate = snapshot.playback_rate.or(base.playback_rate);
+                    target.low_pass_cutoff = snapshot.low_pass_cutoff.or(base.low_pass_cutoff);
+                }
+                None => {
+                    self.snapshot_bases.remove(&channel);
+                }
+            }
+            if let Some(volume) = target.volume {
+                self.set_volume(&channel, audio_instances, Decibels(volume), &tween);
+            }
+            if let Some(panning) = target.panning {
+                self.set_panning(&channel, audio_instances, Panning(panning), &tween);
+            }
+            if let Some(playback_rate) = target.playback_rate {
+                self.set_playback_rate(&channel, audio_instances, playback_rate, &tween);
+            }
+            if let Some(cutoff) = target.low_pass_cutoff {
+                self.set_channel_low_pass_cutoff(&channel, cutoff, &tween);
+            }
         }
     }
 
+    /// Whether any sound in the given channel is currently playing
+    pub(crate) fn is_channel_playing(
+        &self,
+        channel: &Channel,
+        audio_instances: &Assets<AudioInstance>,
+    ) -> bool {
+        self.instances.get(channel).is_some_and(|instances| {
+            instances.iter().any(|instance| {
+                audio_instances.get(instance).is_some_and(|instance| {
+                    matches!(
+                        instance.state(),
+                        PlaybackState::Playing { .. } | PlaybackState::Resuming { .. }
+                    )
+                })
+            })
+        })
+    }
+
     fn set_panning(
         &mut self,
         channel: &Channel,
@@ -162,6 +451,7 @@ impl<B: Backend> AudioOutput<B> {
         }
         if let Some(channel_state) = self.channels.get_mut(channel) {
             channel_state.panning = panning;
+            channel_state.panning_modulator = None;
         } else {
             let channel_state = ChannelState {
                 panning,
@@ -182,12 +472,13 @@ impl<B: Backend> AudioOutput<B> {
             let tween = map_tween(tween);
             for instance in instances.iter_mut() {
                 if let Some(mut instance) = audio_instances.get_mut(instance.id()) {
-                    instance.handle.set_playback_rate(playback_rate, tween);
+                    instance.set_playback_rate_with(playback_rate, tween);
                 }
             }
         }
         if let Some(channel_state) = self.channels.get_mut(channel) {
             channel_state.playback_rate = playback_rate;
+            channel_state.playback_rate_modulator = None;
         } else {
             let channel_state = ChannelState {
                 playback_rate,
@@ -197,53 +488,370 @@ impl<B: Backend> AudioOutput<B> {
         }
     }
 
-    fn play(
+    fn set_volume_modulator(
         &mut self,
         channel: &Channel,
-        partial_sound_settings: &PartialSoundSettings,
-        audio_source: &AudioSource,
-        instance_handle: Handle<AudioInstance>,
         audio_instances: &mut Assets<AudioInstance>,
+        modulators: &Assets<AudioModulator>,
+        value: &ModulatedValue,
+        tween: &Option<AudioTween>,
     ) -> AudioCommandResult {
-        let mut sound = audio_source.sound.clone();
-        if let Some(channel_state) = self.channels.get(channel) {
-            channel_state.apply(&mut sound);
-            // This is reverted after pausing the sound handle.
-            // Otherwise the audio thread will start playing the sound before our pause command goes through.
-            if channel_state.paused {
-                sound.settings.playback_rate = kira::Value::Fixed(PlaybackRate(0.0));
+        let volume = match value.value(modulators, |volume| Decibels(volume as f32)) {
+            Ok(volume) => volume,
+            Err(missing) => return missing_modulator_result(missing),
+        };
+        if let Some(instances) = self.instances.get(channel) {
+            let tween = map_tween(tween);
+            for instance in instances {
+                if let Some(mut instance) = audio_instances.get_mut(instance.id()) {
+                    instance.handle.set_volume(volume, tween);
+                }
             }
         }
-        if partial_sound_settings.paused {
-            sound.settings.playback_rate = kira::Value::Fixed(PlaybackRate(0.0));
+        self.channels
+            .entry(channel.clone())
+            .or_default()
+            .volume_modulator = Some(value.clone());
+
+        AudioCommandResult::Ok
+    }
+
+    fn set_panning_modulator(
+        &mut self,
+        channel: &Channel,
+        audio_instances: &mut Assets<AudioInstance>,
+        modulators: &Assets<AudioModulator>,
+        value: &ModulatedValue,
+        tween: &Option<AudioTween>,
+    ) -> AudioCommandResult {
+        let panning = match value.value(modulators, |panning| Panning(panning as f32)) {
+            Ok(panning) => panning,
+            Err(missing) => return missing_modulator_result(missing),
+        };
+        if let Some(instances) = self.instances.get(channel) {
+            let tween = map_tween(tween);
+            for instance in instances {
+                if let Some(mut instance) = audio_instances.get_mut(instance.id()) {
+                    instance.handle.set_panning(panning, tween);
+                }
+            }
+        }
+        self.channels
+            .entry(channel.clone())
+            .or_default()
+            .panning_modulator = Some(value.clone());
+
+        AudioCommandResult::Ok
+    }
+
+    fn set_playback_rate_modulator(
+        &mut self,
+        channel: &Channel,
+        audio_instances: &mut Assets<AudioInstance>,
+        modulators: &Assets<AudioModulator>,
+        value: &ModulatedValue,
+        tween: &Option<AudioTween>,
+    ) -> AudioCommandResult {
+        let playback_rate = match value.value(modulators, PlaybackRate) {
+            Ok(playback_rate) => playback_rate,
+            Err(missing) => return missing_modulator_result(missing),
+        };
+        if let Some(instances) = self.instances.get(channel) {
+            let tween = map_tween(tween);
+            for instance in instances {
+                if let Some(mut instance) = audio_instances.get_mut(instance.id()) {
+                    instance.handle.set_playback_rate(playback_rate, tween);
+                }
+            }
+        }
+        self.channels
+            .entry(channel.clone())
+            .or_default()
+            .playback_rate_modulator = Some(value.clone());
+
+        AudioCommandResult::Ok
+    }
+
+    fn set_voice_limit(&mut self, channel: &Channel, voice_limit: Option<VoiceLimit>) {
+        self.channels
+            .entry(channel.clone())
+            .or_default()
+            .voice_limit = voice_limit;
+    }
+
+    fn set_debounce(&mut self, channel: &Channel, debounce: Option<AudioDebounce>) {
+        self.channels.entry(channel.clone()).or_default().debounce = debounce;
+    }
+
+    /// Debounce configurations that apply to a play of the given source in the given channel
+    fn debounces(
+        &self,
+        channel: &Channel,
+        source: VoiceSource,
+        policies: &AudioSourcePolicies,
+    ) -> Vec<(DebounceScope, AudioDebounce)> {
+        let channel_debounce = self
+            .channels
+            .get(channel)
+            .and_then(|channel_state| channel_state.debounce.clone())
+            .map(|debounce| (DebounceScope::Channel(channel.clone()), debounce));
+        let source_debounce = source
+            .audio_source()
+            .and_then(|source| policies.debounce(source))
+            .cloned()
+            .map(|debounce| (DebounceScope::Source, debounce));
+
+        channel_debounce
+            .into_iter()
+            .chain(source_debounce)
+            .collect()
+    }
+
+    /// Merge or drop a play if it follows a recent play of the same source too closely
+    ///
+    /// Returns `true` if the play should not start a new sound.
+    fn debounce(
+        &mut self,
+        debounces: &[(DebounceScope, AudioDebounce)],
+        source: VoiceSource,
+        now: Duration,
+        audio_instances: &mut Assets<AudioInstance>,
+    ) -> bool {
+        for (scope, debounce) in debounces {
+            let Some(recent_play) = self.recent_plays.get_mut(&(scope.clone(), source)) else {
+                continue;
+            };
+            match debounce.action(now.saturating_sub(recent_play.started)) {
+                Some(DebounceAction::Merge) => {
+                    recent_play.merged_plays += 1;
+                    let volume =
+                        recent_play.volume + debounce.volume_boost(recent_play.merged_plays);
+                    if debounce.volume_boost.is_some()
+                        && let Some(mut instance) = audio_instances.get_mut(&recent_play.instance)
+                    {
+                        instance.set_volume_with(Decibels(volume), kira::Tween::default());
+                    }
+                    return true;
+                }
+                Some(DebounceAction::Drop) => return true,
+                None => {}
+            }
+        }
+
+        false
+    }
+
+    fn play_sound_event(
+        &mut self,
+        channel: &Channel,
+        instance_handle: &Handle<AudioInstance>,
+        partial_sound_settings: &PartialSoundSettings,
+        sound_event_handle: &Handle<SoundEvent>,
+        context: &mut AudioCommandContext,
+    ) -> AudioCommandResult {
+        let Some(sound_event) = context.sound_events.get(sound_event_handle) else {
+            if load_failed(context.asset_server, sound_event_handle) {
+                warn!("Cannot play a sound event that failed to load");
+                return AudioCommandResult::Ok;
+            }
+            // sound event hasn't loaded yet. Add it back to the queue
+            return AudioCommandResult::Retry;
+        };
+        if sound_event.variants.is_empty() {
+            warn!("Cannot play a sound event without variants");
+            return AudioCommandResult::Ok;
         }
-        partial_sound_settings.apply(&mut sound);
-        let sound_handle = self.manager.as_mut().unwrap().play(sound);
-        if let Err(error) = sound_handle {
-            warn!("Failed to play sound due to {:?}", error);
+        if sound_event
+            .variants
+            .iter()
+            .any(|variant| load_failed(context.asset_server, &variant.source))
+        {
+            warn!("Cannot play a sound event with variants that failed to load");
             return AudioCommandResult::Ok;
         }
-        let mut sound_handle = sound_handle.unwrap();
-        if let Some(channel_state) = self.channels.get(channel)
-            && channel_state.paused
+        if !sound_event
+            .variants
+            .iter()
+            .all(|variant| context.audio_sources.contains(&variant.source))
         {
-            sound_handle.pause(kira::Tween::default());
-            let playback_rate = partial_sound_settings
-                .playback_rate
-                .unwrap_or(channel_state.playback_rate);
-            sound_handle.set_playback_rate(playback_rate, kira::Tween::default());
+            // not all variants have loaded yet. Add it back to the queue
+            return AudioCommandResult::Retry;
         }
-        if partial_sound_settings.paused {
-            sound_handle.pause(kira::Tween::default());
-            let playback_rate = partial_sound_settings.playback_rate.unwrap_or(1.0);
-            sound_handle.set_playback_rate(playback_rate, kira::Tween::default());
+        let variant = self
+            .variant_selectors
+            .entry(sound_event_handle.id())
+            .or_default()
+            .select(sound_event, context.rng);
+        let source = sound_event.variants[variant].source.clone();
+        let mut settings = partial_sound_settings.clone();
+        sound_event.randomise(&mut settings, context.rng);
+
+        self.play_audio_source(channel, instance_handle, &settings, &source, context)
+    }
+
+    fn play_audio_source(
+        &mut self,
+        channel: &Channel,
+        instance_handle: &Handle<AudioInstance>,
+        partial_sound_settings: &PartialSoundSettings,
+        source_handle: &Handle<AudioSource>,
+        context: &mut AudioCommandContext,
+    ) -> AudioCommandResult {
+        let Some(audio_source) = context.audio_sources.get(source_handle) else {
+            // audio source hasn't loaded yet. Add it back to the queue
+            return AudioCommandResult::Retry;
+        };
+        let sound = PreparedSound::Static(
+            Box::new(audio_source.sound.clone()),
+            audio_source.loop_region(),
+        );
+        let source = VoiceSource::Audio(source_handle.id());
+
+        self.play(
+            channel,
+            instance_handle,
+            partial_sound_settings,
+            source,
+            sound,
+            context,
+        )
+    }
+
+    fn play_custom_source(
+        &mut self,
+        channel: &Channel,
+        instance_handle: &Handle<AudioInstance>,
+        partial_sound_settings: &PartialSoundSettings,
+        source_handle: &Handle<CustomAudioSource>,
+        context: &mut AudioCommandContext,
+    ) -> AudioCommandResult {
+        let Some(custom_source) = context.custom_sources.get(source_handle) else {
+            // custom source hasn't been added yet. Add it back to the queue
+            return AudioCommandResult::Retry;
+        };
+        let sound = PreparedSound::Custom(custom_source.clone());
+        let source = VoiceSource::Custom(source_handle.id());
+
+        self.play(
+            channel,
+            instance_handle,
+            partial_sound_settings,
+            source,
+            sound,
+            context,
+        )
+    }
+
+    fn play(
+        &mut self,
+        channel: &Channel,
+        instance_handle: &Handle<AudioInstance>,
+        partial_sound_settings: &PartialSoundSettings,
+        source: VoiceSource,
+        sound: PreparedSound,
+        context: &mut AudioCommandContext,
+    ) -> AudioCommandResult {
+        if let Some(missing) = partial_sound_settings.missing_modulator(context.modulators) {
+            return missing_modulator_result(missing);
+        }
+        let audio_instances = &mut *context.audio_instances;
+        let priority = partial_sound_settings.priority;
+        let now = context.now;
+        let debounces = self.debounces(channel, source, context.policies);
+        if self.debounce(&debounces, source, now, audio_instances) {
+            return AudioCommandResult::Ok;
+        }
+        let Some(victims) =
+            self.voice_victims(channel, source, priority, audio_instances, context.policies)
+        else {
+            debug!("Not playing sound, because its voice limit is reached");
+            return AudioCommandResult::Ok;
+        };
+        let played = match sound {
+            PreparedSound::Static(sound, loop_region) => self.play_static(
+                channel,
+                *sound,
+                loop_region,
+                partial_sound_settings,
+                context.modulators,
+            ),
+            PreparedSound::Custom(custom_source) => {
+                self.play_custom(channel, &custom_source, partial_sound_settings)
+            }
+        };
+        let played = match played {
+            Ok(played) => played,
+            Err(PlayError::SoundLimitReached) => {
+                // Steal only once per play. The stopped sound frees its slot on the audio thread
+                // before the next frame.
+                if !self.stole_voice.remove(&instance_handle.id())
+                    && self.steal_voice(
+                        channel,
+                        partial_sound_settings.emitter,
+                        priority,
+                        audio_instances,
+                    )
+                {
+                    self.stole_voice.insert(instance_handle.id());
+                    return AudioCommandResult::Retry;
+                }
+                warn!("Failed to play sound, because the sound capacity is reached");
+                return AudioCommandResult::Ok;
+            }
+            Err(PlayError::Other(error)) => {
+                self.stole_voice.remove(&instance_handle.id());
+                warn!("Failed to play sound due to {}", error);
+                return AudioCommandResult::Ok;
+            }
+        };
+        self.stole_voice.remove(&instance_handle.id());
+        for victim in victims {
+            if let Some(mut instance) = audio_instances.get_mut(victim) {
+                instance.stop_with(AudioTween::default().into());
+            }
+        }
+        self.voice_sequence += 1;
+        let voice = Voice {
+            source,
+            priority,
+            sequence: self.voice_sequence,
+            volume: played.volume,
+            distance: 0.,
+        };
+        let instance_handle = instance_handle.clone();
+        for (scope, debounce) in debounces {
+            self.recent_plays.insert(
+                (scope, source),
+                RecentPlay {
+                    started: now,
+                    expires: now + debounce.duration(),
+                    instance: instance_handle.clone(),
+                    volume: played.volume,
+                    merged_plays: 0,
+                },
+            );
         }
         let _ = audio_instances.insert(
             &instance_handle,
-            AudioInstance {
-                handle: sound_handle,
-            },
+            AudioInstance::new(
+                played.handle,
+                played.sound,
+                played.custom,
+                channel.clone(),
+                partial_sound_settings.emitter,
+                played.playback_rate,
+                voice,
+            ),
         );
+        if let Some(emitter) = partial_sound_settings
+            .emitter
+            .filter(|emitter| self.is_spatial_emitter(*emitter))
+        {
+            self.new_emitter_instances
+                .entry(emitter)
+                .or_default()
+                .push(instance_handle.clone());
+        }
         if let Some(instance_states) = self.instances.get_mut(channel) {
             instance_states.push(instance_handle);
         } else {
@@ -254,11 +862,429 @@ impl<B: Backend> AudioOutput<B> {
         AudioCommandResult::Ok
     }
 
+    fn play_static(
+        &mut self,
+        channel: &Channel,
+        mut sound: StaticSoundData,
+        loop_region: Option<Region>,
+        partial_sound_settings: &PartialSoundSettings,
+        modulators: &Assets<AudioModulator>,
+    ) -> Result<PlayedSound, PlayError> {
+        let channel_paused = if let Some(channel_state) = self.channels.get(channel) {
+            channel_state.apply(&mut sound, modulators);
+            channel_state.paused
+        } else {
+            false
+        };
+        partial_sound_settings.apply(&mut sound, loop_region);
+        partial_sound_settings.apply_modulators(&mut sound, modulators);
+        let instance_sound = sound.clone();
+        let playback_rate_value = sound.settings.playback_rate;
+        let playback_rate = match sound.settings.playback_rate {
+            kira::Value::Fixed(PlaybackRate(playback_rate)) => playback_rate,
+            _ => 1.0,
+        };
+        let volume = match sound.settings.volume {
+            kira::Value::Fixed(Decibels(volume)) => volume,
+            _ => 0.,
+        };
+        let paused = channel_paused || partial_sound_settings.paused;
+        // This is reverted after pausing the sound handle.
+        // Otherwise the audio thread will start playing the sound before our pause command goes through.
+        if paused {
+            sound.settings.playback_rate = kira::Value::Fixed(PlaybackRate(0.0));
+        }
+        let mut sound_handle =
+            self.play_sound_data(channel, partial_sound_settings.emitter, sound)?;
+        if paused {
+            sound_handle.pause(kira::Tween::default());
+            sound_handle.set_playback_rate(playback_rate_value, kira::Tween::default());
+        }
+
+        Ok(PlayedSound {
+            handle: SoundHandle::Static(sound_handle),
+            sound: Some(instance_sound),
+            custom: None,
+            playback_rate,
+            volume,
+        })
+    }
+
+    fn play_custom(
+        &mut self,
+        channel: &Channel,
+        custom_source: &CustomAudioSource,
+        partial_sound_settings: &PartialSoundSettings,
+    ) -> Result<PlayedSound, PlayError> {
+        let channel_state = self.channels.get(channel);
+        let mut unsupported =
+            partial_sound_settings.unsupported_by_custom_sources(custom_source.is_loopable());
+        if channel_state.is_some_and(|state| {
+            state.volume_modulator.is_some()
+                || state.playback_rate_modulator.is_some()
+                || state.panning_modulator.is_some()
+        }) {
+            unsupported.push("channel modulators");
+        }
+        if !unsupported.is_empty() {
+            warn!(
+                "Playing custom audio source without unsupported settings: {}",
+                unsupported.join(", ")
+            );
+        }
+        let volume = partial_sound_settings
+            .volume
+            .unwrap_or_else(|| channel_state.map_or(Decibels::IDENTITY, |state| state.volume));
+        let panning = partial_sound_settings
+            .panning
+            .unwrap_or_else(|| channel_state.map_or(Panning::CENTER, |state| state.panning));
+        let playback_rate = partial_sound_settings
+            .playback_rate
+            .unwrap_or_else(|| channel_state.map_or(1., |state| state.playback_rate));
+        let paused =
+            channel_state.is_some_and(|state| state.paused) || partial_sound_settings.paused;
+        let (data, handle, custom) = custom_source
+            .sound(CustomSoundSettings {
+                volume,
+                panning,
+                playback_rate,
+                paused,
+                fade_in: partial_sound_settings.fade_in.as_ref().map(Into::into),
+                looped: partial_sound_settings.looped,
+            })
+            .map_err(PlayError::Other)?;
+        self.play_sound_data(channel, partial_sound_settings.emitter, data)?;
+
+        Ok(PlayedSound {
+            handle: SoundHandle::Custom(handle),
+            sound: None,
+            custom: Some(custom),
+            playback_rate,
+            volume: volume.0,
+        })
+    }
+
+    /// Play sound data on the track of the given emitter or channel, or on the main track
+    fn play_sound_data<D: SoundData>(
+        &mut self,
+        channel: &Channel,
+        emitter: Option<Entity>,
+        data: D,
+    ) -> Result<D::Handle, PlaySoundError<D::Error>> {
+        if let Some(emitter_track) = emitter.and_then(|emitter| self.emitter_track(emitter)) {
+            emitter_track.track.play(data)
+        } else if let Some(channel_track) = self.channel_track(channel) {
+            channel_track.track.play(data)
+        } else {
+            self.manager.as_mut().unwrap().play(data)
+        }
+    }
+
+    /// Find the instances to stop before a new sound can play without exceeding any voice limit
+    ///
+    /// Returns `None` if the new sound should not be played.
+    fn voice_victims(
+        &self,
+        channel: &Channel,
+        source: VoiceSource,
+        priority: i32,
+        audio_instances: &Assets<AudioInstance>,
+        policies: &AudioSourcePolicies,
+    ) -> Option<Vec<AssetId<AudioInstance>>> {
+        let mut victims = vec![];
+        if let Some(limit) = self
+            .channels
+            .get(channel)
+            .and_then(|channel_state| channel_state.voice_limit)
+        {
+            let (ids, voices): (Vec<_>, Vec<_>) = active_voices(
+                self.instances.get(channel).into_iter().flatten(),
+                audio_instances,
+            )
+            .into_iter()
+            .unzip();
+            let selected = select_victims(&voices, &limit, priority)?;
+            victims.extend(selected.into_iter().map(|index| ids[index]));
+        }
+        if let Some(limit) = source
+            .audio_source()
+            .and_then(|source| policies.voice_limit(source))
+        {
+            let (ids, voices): (Vec<_>, Vec<_>) =
+                active_voices(self.instances.values().flatten(), audio_instances)
+                    .into_iter()
+                    .filter(|(id, voice)| voice.source == source && !victims.contains(id))
+                    .unzip();
+            let selected = select_victims(&voices, limit, priority)?;
+            victims.extend(selected.into_iter().map(|index| ids[index]));
+        }
+
+        Some(victims)
+    }
+
+    /// Stop the oldest of the sounds with the lowest priority below the given one
+    ///
+    /// Only sounds on the track that a sound of the given channel and emitter plays on are
+    /// considered, since stopping sounds on other tracks frees no slot on it.
+    /// Returns `false` if there is no such sound.
+    fn steal_voice(
+        &mut self,
+        channel: &Channel,
+        emitter: Option<Entity>,
+        priority: i32,
+        audio_instances: &mut Assets<AudioInstance>,
+    ) -> bool {
+        let same_track = self.instances.values().flatten().filter(|handle| {
+            audio_instances.get(*handle).is_some_and(|instance| {
+                self.shares_track(channel, emitter, &instance.channel, instance.emitter)
+            })
+        });
+        let (ids, voices): (Vec<_>, Vec<_>) = active_voices(same_track, audio_instances)
+            .into_iter()
+            .unzip();
+        let limit = VoiceLimit::new(voices.len(), VoiceStealing::RejectNew);
+        let Some(&[victim]) = select_victims(&voices, &limit, priority).as_deref() else {
+            return false;
+        };
+        if let Some(mut instance) = audio_instances.get_mut(ids[victim]) {
+            instance.stop_with(kira::Tween {
+                duration: Duration::ZERO,
+                ..Default::default()
+            });
+        }
+
+        true
+    }
+
+    /// Whether sounds of the given channels and emitters play on the same track
+    fn shares_track(
+        &self,
+        channel: &Channel,
+        emitter: Option<Entity>,
+        other_channel: &Channel,
+        other_emitter: Option<Entity>,
+    ) -> bool {
+        let emitter_track = |emitter: Option<Entity>| {
+            emitter.filter(|emitter| self.emitter_tracks.contains_key(emitter))
+        };
+        match (emitter_track(emitter), emitter_track(other_emitter)) {
+            (Some(emitter), Some(other_emitter)) => emitter == other_emitter,
+            (None, None) => channel == other_channel,
+            _ => false,
+        }
+    }
+
+    /// Restart a virtualised instance at its current playback position
+    pub(crate) fn restart_virtualised(&mut self, instance: &mut AudioInstance) {
+        let Some(virtual_playback) = instance.virtual_playback else {
+            return;
+        };
+        let Some(mut sound) = instance.sound.clone() else {
+            return;
+        };
+        // Kira counts the start position of reversed sounds from their end
+        let position = if sound.settings.reverse {
+            sound.duration().as_secs_f64() - virtual_playback.position
+        } else {
+            virtual_playback.position
+        };
+        sound.settings.start_position = position.max(0.).into();
+        sound.settings.playback_rate = instance.playback_rate.into();
+        sound.settings.fade_in_tween = Some(AudioTween::default().into());
+        if self.manager.is_none() {
+            return;
+        }
+        match self.play_sound_data(&instance.channel, instance.emitter, sound) {
+            Ok(mut sound_handle) => {
+                if virtual_playback.paused {
+                    sound_handle.pause(kira::Tween::default());
+                }
+                instance.handle = SoundHandle::Static(sound_handle);
+                instance.virtual_playback = None;
+            }
+            Err(error) => warn!("Failed to restart virtualised sound due to {:?}", error),
+        }
+    }
+
+    /// Add a modulator to the audio thread
+    pub(crate) fn add_modulator(&mut self, settings: ModulatorSettings) -> Option<ModulatorKind> {
+        let manager = self.manager.as_mut()?;
+        let kind = match settings {
+            ModulatorSettings::Lfo(lfo) => manager
+                .add_modulator(
+                    LfoBuilder::new()
+                        .waveform(lfo.waveform)
+                        .frequency(lfo.frequency)
+                        .amplitude(lfo.amplitude)
+                        .offset(lfo.offset)
+                        .starting_phase(lfo.starting_phase),
+                )
+                .map(ModulatorKind::Lfo),
+            ModulatorSettings::Tweener(initial_value) => manager
+                .add_modulator(TweenerBuilder { initial_value })
+                .map(ModulatorKind::Tweener),
+        };
+        match kind {
+            Ok(kind) => Some(kind),
+            Err(error) => {
+                warn!("Failed to add modulator due to {:?}", error);
+                None
+            }
+        }
+    }
+
+    /// Get the track of the given channel, creating it if necessary
+    fn channel_track(&mut self, channel: &Channel) -> Option<&mut ChannelTrack> {
+        if !self.channel_tracks.contains_key(channel) {
+            let manager = self.manager.as_mut()?;
+            let mut builder = TrackBuilder::new().sound_capacity(self.sound_capacity);
+            let low_pass = builder.add_effect(FilterBuilder::new().cutoff(OPEN_LOW_PASS_CUTOFF));
+            match manager.add_sub_track(builder) {
+                Ok(track) => {
+                    self.channel_tracks
+                        .insert(channel.clone(), ChannelTrack { track, low_pass });
+                }
+                Err(error) => {
+                    warn!(
+                        "Failed to create track for audio channel, so its sounds play on the main track. Consider increasing `AudioSettings::sub_track_capacity`. {:?}",
+                        error
+                    );
+                    return None;
+                }
+            }
+        }
+
+        self.channel_tracks.get_mut(channel)
+    }
+
+    /// Remember that the given entity is a spatial audio emitter
+    ///
+    /// Sounds played from other entities are not routed through an emitter track.
+    pub(crate) fn add_spatial_emitter(&mut self, emitter: Entity) {
+        self.spatial_emitters
+            .get_or_insert_with(HashSet::default)
+            .insert(emitter);
+    }
+
+    fn is_spatial_emitter(&self, emitter: Entity) -> bool {
+        self.spatial_emitters
+            .as_ref()
+            .is_some_and(|emitters| emitters.contains(&emitter))
+    }
+
+    /// Get the track of the given spatial audio emitter, creating it if necessary
+    ///
+    /// Returns `None` if the entity is not a spatial audio emitter.
+    pub(crate) fn emitter_track(&mut self, emitter: Entity) -> Option<&mut EmitterTrack> {
+        if !self.is_spatial_emitter(emitter) {
+            return None;
+        }
+        if !self.emitter_tracks.contains_key(&emitter) {
+            let reverb = self.spatial_reverb().map(|reverb| reverb.track.id());
+            let manager = self.manager.as_mut()?;
+            let mut builder = TrackBuilder::new().sound_capacity(self.sound_capacity);
+            if let Some(reverb) = reverb {
+                builder = builder.with_send(reverb, Decibels::IDENTITY);
+            }
+            let low_pass = builder.add_effect(FilterBuilder::new().cutoff(OPEN_LOW_PASS_CUTOFF));
+            match manager.add_sub_track(builder) {
+                Ok(track) => {
+                    self.emitter_tracks
+                        .insert(emitter, EmitterTrack { track, low_pass });
+                }
+                Err(error) => {
+                    warn!(
+                        "Failed to create track for spatial audio emitter, so its sounds play without spatial effects. Consider increasing `AudioSettings::sub_track_capacity`. {:?}",
+                        error
+                    );
+                    return None;
+                }
+            }
+        }
+
+        self.emitter_tracks.get_mut(&emitter)
+    }
+
+    fn spatial_reverb(&mut self) -> Option<&mut SpatialReverb> {
+        if self.spatial_reverb.is_none() {
+            let manager = self.manager.as_mut()?;
+            let parameters = SpatialReverbParameters::SILENT;
+            let mut builder = SendTrackBuilder::new().volume(Decibels(parameters.volume));
+            let reverb = builder.add_effect(
+                ReverbBuilder::new()
+                    .feedback(parameters.feedback)
+                    .damping(parameters.damping)
+                    .mix(Mix::WET),
+            );
+            match manager.add_send_track(builder) {
+                Ok(track) => {
+                    self.spatial_reverb = Some(SpatialReverb {
+                        track,
+                        reverb,
+                        parameters,
+                    });
+                }
+                Err(error) => {
+                    warn!("Failed to create track for spatial reverb: {:?}", error);
+                    return None;
+                }
+            }
+        }
+
+        self.spatial_reverb.as_mut()
+    }
+
+    /// Update the parameters of the spatial reverb
+    ///
+    /// Nothing happens until the first sound was played from a spatial audio emitter.
+    pub(crate) fn set_spatial_reverb(&mut self, parameters: SpatialReverbParameters) {
+        let Some(spatial_reverb) = self.spatial_reverb.as_mut() else {
+            return;
+        };
+        if spatial_reverb.parameters == parameters {
+            return;
+        }
+        let tween = AudioTween::default().into();
+        spatial_reverb
+            .track
+            .set_volume(Decibels(parameters.volume), tween);
+        spatial_reverb
+            .reverb
+            .set_feedback(parameters.feedback, tween);
+        spatial_reverb.reverb.set_damping(parameters.damping, tween);
+        spatial_reverb.parameters = parameters;
+    }
+
+    /// Get the track of the given spatial audio emitter if it exists
+    pub(crate) fn existing_emitter_track(&mut self, emitter: Entity) -> Option<&mut EmitterTrack> {
+        self.emitter_tracks.get_mut(&emitter)
+    }
+
+    /// Remove the track of a spatial audio emitter
+    ///
+    /// All sounds still playing on the track will be stopped.
+    pub(crate) fn remove_emitter_track(&mut self, emitter: Entity) {
+        if let Some(emitters) = self.spatial_emitters.as_mut() {
+            emitters.remove(&emitter);
+        }
+        self.emitter_tracks.remove(&emitter);
+        self.new_emitter_instances.remove(&emitter);
+    }
+
+    /// Take all instances that were played from the given emitter since the last call
+    pub(crate) fn take_new_emitter_instances(
+        &mut self,
+        emitter: Entity,
+    ) -> Vec<Handle<AudioInstance>> {
+        self.new_emitter_instances
+            .remove(&emitter)
+            .unwrap_or_default()
+    }
+
     pub(crate) fn play_channel<T: Resource>(
         &mut self,
-        audio_sources: &Assets<AudioSource>,
         channel: &AudioChannel<T>,
-        audio_instances: &mut Assets<AudioInstance>,
+        context: &mut AudioCommandContext,
     ) {
         if self.manager.is_none() {
             return;
@@ -270,9 +1296,8 @@ impl<B: Backend> AudioOutput<B> {
         let mut commands_to_retry = vec![];
         let mut i = 0;
         while i < len {
-            let audio_command = commands.pop_back().unwrap();
-            let result =
-                self.run_audio_command(&audio_command, audio_sources, audio_instances, &channel);
+            let mut audio_command = commands.pop_back().unwrap();
+            let result = self.run_audio_command(&mut audio_command, &channel, context);
             if let AudioCommand::Stop(_) = audio_command {
                 commands_to_retry.clear();
             }
@@ -288,9 +1313,8 @@ impl<B: Backend> AudioOutput<B> {
 
     pub(crate) fn play_dynamic_channels(
         &mut self,
-        audio_sources: &Assets<AudioSource>,
         channels: &DynamicAudioChannels,
-        audio_instances: &mut Assets<AudioInstance>,
+        context: &mut AudioCommandContext,
     ) {
         if self.manager.is_none() {
             return;
@@ -301,13 +1325,8 @@ impl<B: Backend> AudioOutput<B> {
             let channel = Channel::Dynamic(key.clone());
             let mut i = 0;
             while i < len {
-                let audio_command = commands.pop_back().unwrap();
-                let result = self.run_audio_command(
-                    &audio_command,
-                    audio_sources,
-                    audio_instances,
-                    &channel,
-                );
+                let mut audio_command = commands.pop_back().unwrap();
+                let result = self.run_audio_command(&mut audio_command, &channel, context);
                 if let AudioCommandResult::Retry = result {
                     commands.push_front(audio_command);
                 }
@@ -318,55 +1337,104 @@ impl<B: Backend> AudioOutput<B> {
 
     pub(crate) fn run_audio_command(
         &mut self,
-        audio_command: &AudioCommand,
-        audio_sources: &Assets<AudioSource>,
-        audio_instances: &mut Assets<AudioInstance>,
+        audio_command: &mut AudioCommand,
         channel: &Channel,
+        context: &mut AudioCommandContext,
     ) -> AudioCommandResult {
         match audio_command {
             AudioCommand::Play(play_args) => {
-                if let Some(audio_source) = audio_sources.get(&play_args.source) {
-                    self.play(
+                // Randomised in place, so retries of this command play with the same values
+                play_args.settings.randomise(context.rng);
+                let settings = &play_args.settings;
+                match &play_args.source {
+                    PlaySource::Audio(source) => self.play_audio_source(
                         channel,
-                        &play_args.settings,
-                        audio_source,
-                        play_args.instance_handle.clone(),
-                        audio_instances,
-                    )
-                } else {
-                    // audio source hasn't loaded yet. Add it back to the queue
-                    AudioCommandResult::Retry
+                        &play_args.instance_handle,
+                        settings,
+                        source,
+                        context,
+                    ),
+                    PlaySource::SoundEvent(sound_event) => self.play_sound_event(
+                        channel,
+                        &play_args.instance_handle,
+                        settings,
+                        sound_event,
+                        context,
+                    ),
+                    PlaySource::Custom(custom_source) => self.play_custom_source(
+                        channel,
+                        &play_args.instance_handle,
+                        settings,
+                        custom_source,
+                        context,
+                    ),
                 }
             }
-            AudioCommand::Stop(tween) => self.stop(channel, audio_instances, tween),
+            AudioCommand::Stop(tween) => self.stop(channel, context.audio_instances, tween),
             AudioCommand::Pause(tween) => {
-                self.pause(channel, audio_instances, tween);
+                self.pause(channel, context.audio_instances, tween);
                 AudioCommandResult::Ok
             }
             AudioCommand::Resume(tween) => {
-                self.resume(channel, audio_instances, tween);
+                self.resume(channel, context.audio_instances, tween);
                 AudioCommandResult::Ok
             }
             AudioCommand::SetVolume(volume, tween) => {
-                self.set_volume(channel, audio_instances, *volume, tween);
+                self.set_volume(channel, context.audio_instances, *volume, tween);
                 AudioCommandResult::Ok
             }
             AudioCommand::SetPanning(panning, tween) => {
-                self.set_panning(channel, audio_instances, *panning, tween);
+                self.set_panning(channel, context.audio_instances, *panning, tween);
                 AudioCommandResult::Ok
             }
             AudioCommand::SetPlaybackRate(playback_rate, tween) => {
-                self.set_playback_rate(channel, audio_instances, *playback_rate, tween);
+                self.set_playback_rate(channel, context.audio_instances, *playback_rate, tween);
+                AudioCommandResult::Ok
+            }
+            AudioCommand::SetVolumeModulator(value, tween) => self.set_volume_modulator(
+                channel,
+                context.audio_instances,
+                context.modulators,
+                value,
+                tween,
+            ),
+            AudioCommand::SetPanningModulator(value, tween) => self.set_panning_modulator(
+                channel,
+                context.audio_instances,
+                context.modulators,
+                value,
+                tween,
+            ),
+            AudioCommand::SetPlaybackRateModulator(value, tween) => self
+                .set_playback_rate_modulator(
+                    channel,
+                    context.audio_instances,
+                    context.modulators,
+                    value,
+                    tween,
+                ),
+            AudioCommand::SetVoiceLimit(voice_limit) => {
+                self.set_voice_limit(channel, *voice_limit);
+                AudioCommandResult::Ok
+            }
+            AudioCommand::SetDebounce(debounce) => {
+                self.set_debounce(channel, debounce.clone());
                 AudioCommandResult::Ok
             }
         }
     }
 
-    pub(crate) fn cleanup_stopped_instances(&mut self, instances: &mut Assets<AudioInstance>) {
+    pub(crate) fn cleanup_stopped_instances(
+        &mut self,
+        instances: &mut Assets<AudioInstance>,
+        now: Duration,
+    ) {
+        self.recent_plays
+            .retain(|_, recent_play| recent_play.expires > now);
         for (_, handles) in self.instances.iter_mut() {
             handles.retain(|handle| {
                 if let Some(instance) = instances.get(handle) {
-                    instance.handle.state() != kira::sound::PlaybackState::Stopped
+                    !instance.is_stopped()
                 } else {
                     false
                 }
@@ -375,33 +1443,94 @@ impl<B: Backend> AudioOutput<B> {
     }
 }
 
+/// Voices of all instances that currently occupy a voice on the audio thread
+fn active_voices<'a>(
+    handles: impl Iterator<Item = &'a Handle<AudioInstance>>,
+    audio_instances: &Assets<AudioInstance>,
+) -> Vec<(AssetId<AudioInstance>, Voice)> {
+    handles
+        .filter_map(|handle| {
+            audio_instances
+                .get(handle)
+                .filter(|instance| instance.is_active_voice())
+                .map(|instance| (handle.id(), instance.voice))
+        })
+        .collect()
+}
+
+/// Assets and resources of the systems running audio commands
+#[derive(SystemParam)]
+pub(crate) struct AudioCommandParams<'w> {
+    asset_server: Res<'w, AssetServer>,
+    audio_sources: Option<Res<'w, Assets<AudioSource>>>,
+    sound_events: Res<'w, Assets<SoundEvent>>,
+    audio_instances: ResMut<'w, Assets<AudioInstance>>,
+    policies: Res<'w, AudioSourcePolicies>,
+    rng: ResMut<'w, AudioRng>,
+    modulators: Res<'w, Assets<AudioModulator>>,
+    custom_sources: Res<'w, Assets<CustomAudioSource>>,
+    time: Res<'w, Time>,
+}
+
+impl AudioCommandParams<'_> {
+    /// The context to run audio commands in, once audio sources can be loaded
+    fn context(&mut self) -> Option<AudioCommandContext<'_>> {
+        Some(AudioCommandContext {
+            asset_server: &self.asset_server,
+            audio_sources: self.audio_sources.as_deref()?,
+            sound_events: &self.sound_events,
+            audio_instances: &mut self.audio_instances,
+            policies: &self.policies,
+            rng: &mut self.rng,
+            modulators: &self.modulators,
+            custom_sources: &self.custom_sources,
+            now: self.time.elapsed(),
+        })
+    }
+}
+
+/// Retry commands whose modulator was not added yet and drop those whose modulator failed
+fn missing_modulator_result(missing: MissingModulator) -> AudioCommandResult {
+    match missing {
+        MissingModulator::Pending => AudioCommandResult::Retry,
+        MissingModulator::Failed => {
+            warn!("Dropping audio command, because its modulator failed to be added");
+            AudioCommandResult::Ok
+        }
+    }
+}
+
+/// Whether the asset of the given handle failed to load
+fn load_failed<A: Asset>(asset_server: &AssetServer, handle: &Handle<A>) -> bool {
+    matches!(asset_server.load_state(handle), LoadState::Failed(_))
+}
+
 pub(crate) fn play_dynamic_channels(
     mut audio_output: NonSendMut<AudioOutput>,
     channels: Res<DynamicAudioChannels>,
-    audio_sources: Option<Res<Assets<AudioSource>>>,
-    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    mut params: AudioCommandParams,
 ) {
-    if let Some(audio_sources) = audio_sources {
-        audio_output.play_dynamic_channels(&audio_sources, &channels, &mut audio_instances);
+    if let Some(mut context) = params.context() {
+        audio_output.play_dynamic_channels(&channels, &mut context);
     };
 }
 
 pub(crate) fn play_audio_channel<T: Resource>(
     mut audio_output: NonSendMut<AudioOutput>,
     channel: Res<AudioChannel<T>>,
-    audio_sources: Option<Res<Assets<AudioSource>>>,
-    mut instances: ResMut<Assets<AudioInstance>>,
+    mut params: AudioCommandParams,
 ) {
-    if let Some(audio_sources) = audio_sources {
-        audio_output.play_channel(&audio_sources, &channel, &mut instances);
+    if let Some(mut context) = params.context() {
+        audio_output.play_channel(&channel, &mut context);
     };
 }
 
 pub(crate) fn cleanup_stopped_instances(
     mut audio_output: NonSendMut<AudioOutput>,
     mut instances: ResMut<Assets<AudioInstance>>,
+    time: Res<Time>,
 ) {
-    audio_output.cleanup_stopped_instances(&mut instances);
+    audio_output.cleanup_stopped_instances(&mut instances, time.elapsed());
 }
 
 pub(crate) fn update_instance_states<T: Resource>(
@@ -450,12 +1579,23 @@ mod test {
             .world_mut()
             .remove_resource::<Assets<AudioInstance>>()
             .unwrap();
+        let sound_event_assets = app
+            .world_mut()
+            .remove_resource::<Assets<SoundEvent>>()
+            .unwrap();
+        let modulator_assets = app
+            .world_mut()
+            .remove_resource::<Assets<AudioModulator>>()
+            .unwrap();
+        let custom_source_assets = app
+            .world_mut()
+            .remove_resource::<Assets<CustomAudioSource>>()
+            .unwrap();
 
-        let mut audio_output = AudioOutput {
-            manager: AudioManager::new(AudioManagerSettings::<MockBackend>::default()).ok(),
-            instances: HashMap::default(),
-            channels: HashMap::default(),
-        };
+        let mut audio_output = AudioOutput::new(
+            AudioManager::new(AudioManagerSettings::<MockBackend>::default()).ok(),
+            AudioSettings::default().sound_capacity,
+        );
         let audio_handle_one: Handle<AudioSource> =
             Handle::<AudioSource>::Uuid(Uuid::new_v4(), PhantomData);
         let audio_handle_two: Handle<AudioSource> =
@@ -465,19 +1605,36 @@ mod test {
         channel.play(audio_handle_one.clone());
         channel.play(audio_handle_two.clone());
 
-        audio_output.play_channel(&audio_source_assets, &channel, &mut audio_instance_assets);
+        audio_output.play_channel(
+            &channel,
+            &mut AudioCommandContext {
+                asset_server: app.world().resource::<AssetServer>(),
+                audio_sources: &audio_source_assets,
+                sound_events: &sound_event_assets,
+                audio_instances: &mut audio_instance_assets,
+                policies: &AudioSourcePolicies::default(),
+                rng: &mut AudioRng::from_seed(0),
+                modulators: &modulator_assets,
+                custom_sources: &custom_source_assets,
+                now: Duration::ZERO,
+            },
+        );
 
         let command_one = channel.commands.write().pop_back().unwrap();
         match command_one {
-            AudioCommand::Play(settings) => {
-                assert_eq!(settings.source.id(), audio_handle_one.id())
+            AudioCommand::Play(play_args) => {
+                assert!(
+                    matches!(&play_args.source, PlaySource::Audio(source) if source.id() == audio_handle_one.id())
+                )
             }
             _ => panic!("Wrong audio command"),
         }
         let command_two = channel.commands.write().pop_back().unwrap();
         match command_two {
-            AudioCommand::Play(settings) => {
-                assert_eq!(settings.source.id(), audio_handle_two.id())
+            AudioCommand::Play(play_args) => {
+                assert!(
+                    matches!(&play_args.source, PlaySource::Audio(source) if source.id() == audio_handle_two.id())
+                )
             }
             _ => panic!("Wrong audio command"),
         }
@@ -496,12 +1653,23 @@ mod test {
             .world_mut()
             .remove_resource::<Assets<AudioInstance>>()
             .unwrap();
+        let sound_event_assets = app
+            .world_mut()
+            .remove_resource::<Assets<SoundEvent>>()
+            .unwrap();
+        let modulator_assets = app
+            .world_mut()
+            .remove_resource::<Assets<AudioModulator>>()
+            .unwrap();
+        let custom_source_assets = app
+            .world_mut()
+            .remove_resource::<Assets<CustomAudioSource>>()
+            .unwrap();
 
-        let mut audio_output = AudioOutput {
-            manager: AudioManager::new(AudioManagerSettings::<MockBackend>::default()).ok(),
-            instances: HashMap::default(),
-            channels: HashMap::default(),
-        };
+        let mut audio_output = AudioOutput::new(
+            AudioManager::new(AudioManagerSettings::<MockBackend>::default()).ok(),
+            AudioSettings::default().sound_capacity,
+        );
         let audio_handle_one: Handle<AudioSource> =
             Handle::<AudioSource>::Uuid(Uuid::new_v4(), PhantomData);
         let audio_handle_two: Handle<AudioSource> =
@@ -512,15 +1680,304 @@ mod test {
         channel.stop();
         channel.play(audio_handle_two.clone());
 
-        audio_output.play_channel(&audio_source_assets, &channel, &mut audio_instance_assets);
+        audio_output.play_channel(
+            &channel,
+            &mut AudioCommandContext {
+                asset_server: app.world().resource::<AssetServer>(),
+                audio_sources: &audio_source_assets,
+                sound_events: &sound_event_assets,
+                audio_instances: &mut audio_instance_assets,
+                policies: &AudioSourcePolicies::default(),
+                rng: &mut AudioRng::from_seed(0),
+                modulators: &modulator_assets,
+                custom_sources: &custom_source_assets,
+                now: Duration::ZERO,
+            },
+        );
 
         let command = channel.commands.write().pop_back().unwrap();
         match command {
-            AudioCommand::Play(settings) => {
-                assert_eq!(settings.source.id(), audio_handle_two.id())
+            AudioCommand::Play(play_args) => {
+                assert!(
+                    matches!(&play_args.source, PlaySource::Audio(source) if source.id() == audio_handle_two.id())
+                )
             }
             _ => panic!("Wrong audio command"),
         }
         assert!(channel.commands.write().pop_back().is_none());
     }
+
+    #[test]
+    fn only_spatial_emitters_get_a_track() {
+        let mut audio_output = AudioOutput::new(
+            AudioManager::new(AudioManagerSettings::<MockBackend>::default()).ok(),
+            AudioSettings::default().sound_capacity,
+        );
+        let emitter = Entity::from_raw_u32(1).unwrap();
+
+        assert!(audio_output.emitter_track(emitter).is_none());
+        assert!(audio_output.emitter_tracks.is_empty());
+
+        audio_output.add_spatial_emitter(emitter);
+        assert!(audio_output.emitter_track(emitter).is_some());
+
+        audio_output.remove_emitter_track(emitter);
+        assert!(audio_output.emitter_tracks.is_empty());
+        assert!(audio_output.emitter_track(emitter).is_none());
+    }
+
+    #[test]
+    fn retried_plays_keep_their_random_values() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), AudioPlugin));
+        let world = app.world_mut();
+        let audio_sources = world.remove_resource::<Assets<AudioSource>>().unwrap();
+        let mut audio_instances = world.remove_resource::<Assets<AudioInstance>>().unwrap();
+        let sound_events = world.remove_resource::<Assets<SoundEvent>>().unwrap();
+        let modulators = world.remove_resource::<Assets<AudioModulator>>().unwrap();
+        let custom_sources = world
+            .remove_resource::<Assets<CustomAudioSource>>()
+            .unwrap();
+        let mut audio_output = AudioOutput::new(
+            AudioManager::new(AudioManagerSettings::<MockBackend>::default()).ok(),
+            AudioSettings::default().sound_capacity,
+        );
+        let mut rng = AudioRng::from_seed(0);
+        let channel = AudioChannel::<Audio>::default();
+        channel
+            .play(Handle::<AudioSource>::Uuid(Uuid::new_v4(), PhantomData))
+            .with_random_volume(-20.0..=0.0);
+
+        let mut volumes = vec![];
+        for _ in 0..2 {
+            audio_output.play_channel(
+                &channel,
+                &mut AudioCommandContext {
+                    asset_server: app.world().resource::<AssetServer>(),
+                    audio_sources: &audio_sources,
+                    sound_events: &sound_events,
+                    audio_instances: &mut audio_instances,
+                    policies: &AudioSourcePolicies::default(),
+                    rng: &mut rng,
+                    modulators: &modulators,
+                    custom_sources: &custom_sources,
+                    now: Duration::ZERO,
+                },
+            );
+            match channel.commands.read().back() {
+                Some(AudioCommand::Play(play_args)) => {
+                    assert!(play_args.settings.random_volume.is_none());
+                    volumes.push(play_args.settings.volume.unwrap());
+                }
+                _ => panic!("The play command should be retried"),
+            }
+        }
+
+        assert_eq!(volumes[0], volumes[1]);
+    }
+
+    #[test]
+    fn ducking_keeps_the_volume_of_instances() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), AudioPlugin));
+        let world = app.world_mut();
+        let mut audio_sources = world.remove_resource::<Assets<AudioSource>>().unwrap();
+        let mut audio_instances = world.remove_resource::<Assets<AudioInstance>>().unwrap();
+        let sound_events = world.remove_resource::<Assets<SoundEvent>>().unwrap();
+        let modulators = world.remove_resource::<Assets<AudioModulator>>().unwrap();
+        let custom_sources = world
+            .remove_resource::<Assets<CustomAudioSource>>()
+            .unwrap();
+        let mut audio_output = AudioOutput::new(
+            AudioManager::new(AudioManagerSettings::<MockBackend>::default()).ok(),
+            AudioSettings::default().sound_capacity,
+        );
+        let source = audio_sources.add(AudioSource::from_frames(
+            44_100,
+            vec![kira::Frame::ZERO; 44_100],
+        ));
+        let channel = AudioChannel::<Audio>::default();
+        let instance = channel.play(source).handle();
+        audio_output.play_channel(
+            &channel,
+            &mut AudioCommandContext {
+                asset_server: app.world().resource::<AssetServer>(),
+                audio_sources: &audio_sources,
+                sound_events: &sound_events,
+                audio_instances: &mut audio_instances,
+                policies: &AudioSourcePolicies::default(),
+                rng: &mut AudioRng::from_seed(0),
+                modulators: &modulators,
+                custom_sources: &custom_sources,
+                now: Duration::ZERO,
+            },
+        );
+        audio_instances
+            .get_mut(&instance)
+            .unwrap()
+            .set_decibels(-6., AudioTween::default());
+
+        audio_output.set_channel_ducking(&Channel::typed::<Audio>(), -12., &AudioTween::default());
+
+        assert_eq!(audio_instances.get(&instance).unwrap().voice.volume, -6.);
+        assert!(
+            audio_output
+                .channel_tracks
+                .contains_key(&Channel::typed::<Audio>())
+        );
+    }
+
+    #[derive(Resource)]
+    struct Music;
+
+    #[test]
+    fn stealing_voices_is_limited_to_the_full_track() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), AudioPlugin));
+        let world = app.world_mut();
+        let mut audio_sources = world.remove_resource::<Assets<AudioSource>>().unwrap();
+        let mut audio_instances = world.remove_resource::<Assets<AudioInstance>>().unwrap();
+        let sound_events = world.remove_resource::<Assets<SoundEvent>>().unwrap();
+        let modulators = world.remove_resource::<Assets<AudioModulator>>().unwrap();
+        let custom_sources = world
+            .remove_resource::<Assets<CustomAudioSource>>()
+            .unwrap();
+        let mut audio_output = AudioOutput::new(
+            AudioManager::new(AudioManagerSettings::<MockBackend>::default()).ok(),
+            128,
+        );
+        let source = audio_sources.add(AudioSource::from_frames(
+            44_100,
+            vec![kira::Frame::ZERO; 44_100],
+        ));
+        let channel = AudioChannel::<Audio>::default();
+        let music = AudioChannel::<Music>::default();
+        music.play(source.clone());
+        for _ in 0..128 {
+            channel.play(source.clone()).with_priority(5);
+        }
+        channel.play(source.clone()).with_priority(1);
+        let play = |audio_output: &mut AudioOutput<MockBackend>,
+                    audio_instances: &mut Assets<AudioInstance>| {
+            let mut context = AudioCommandContext {
+                asset_server: app.world().resource::<AssetServer>(),
+                audio_sources: &audio_sources,
+                sound_events: &sound_events,
+                audio_instances,
+                policies: &AudioSourcePolicies::default(),
+                rng: &mut AudioRng::from_seed(0),
+                modulators: &modulators,
+                custom_sources: &custom_sources,
+                now: Duration::ZERO,
+            };
+            audio_output.play_channel(&music, &mut context);
+            audio_output.play_channel(&channel, &mut context);
+        };
+
+        // the sound of the music channel does not play on the full track and is not stolen
+        play(&mut audio_output, &mut audio_instances);
+        assert!(channel.commands.read().is_empty());
+
+        // a sound with a higher priority stops a sound of its own channel and is retried
+        channel.play(source).with_priority(6);
+        play(&mut audio_output, &mut audio_instances);
+        assert_eq!(channel.commands.read().len(), 1);
+        // the mock backend never frees the slot, so the retried play is dropped
+        play(&mut audio_output, &mut audio_instances);
+        assert!(channel.commands.read().is_empty());
+    }
+
+    #[test]
+    fn channel_tracks_use_the_configured_sound_capacity() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), AudioPlugin));
+        let world = app.world_mut();
+        let mut audio_sources = world.remove_resource::<Assets<AudioSource>>().unwrap();
+        let mut audio_instances = world.remove_resource::<Assets<AudioInstance>>().unwrap();
+        let sound_events = world.remove_resource::<Assets<SoundEvent>>().unwrap();
+        let modulators = world.remove_resource::<Assets<AudioModulator>>().unwrap();
+        let custom_sources = world
+            .remove_resource::<Assets<CustomAudioSource>>()
+            .unwrap();
+        let mut audio_output = AudioOutput::new(
+            AudioManager::new(AudioManagerSettings::<MockBackend>::default()).ok(),
+            200,
+        );
+        let source = audio_sources.add(AudioSource::from_frames(
+            44_100,
+            vec![kira::Frame::ZERO; 44_100],
+        ));
+        let channel = AudioChannel::<Audio>::default();
+        for _ in 0..150 {
+            channel.play(source.clone());
+        }
+
+        audio_output.play_channel(
+            &channel,
+            &mut AudioCommandContext {
+                asset_server: app.world().resource::<AssetServer>(),
+                audio_sources: &audio_sources,
+                sound_events: &sound_events,
+                audio_instances: &mut audio_instances,
+                policies: &AudioSourcePolicies::default(),
+                rng: &mut AudioRng::from_seed(0),
+                modulators: &modulators,
+                custom_sources: &custom_sources,
+                now: Duration::ZERO,
+            },
+        );
+
+        assert!(channel.commands.read().is_empty());
+        let track = &audio_output.channel_tracks[&Channel::Typed(TypeId::of::<Audio>())].track;
+        assert_eq!(track.num_sounds(), 150);
+    }
+
+    #[test]
+    fn revived_instances_play_on_their_channel_track() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), AudioPlugin));
+        let world = app.world_mut();
+        let mut audio_sources = world.remove_resource::<Assets<AudioSource>>().unwrap();
+        let mut audio_instances = world.remove_resource::<Assets<AudioInstance>>().unwrap();
+        let sound_events = world.remove_resource::<Assets<SoundEvent>>().unwrap();
+        let modulators = world.remove_resource::<Assets<AudioModulator>>().unwrap();
+        let custom_sources = world
+            .remove_resource::<Assets<CustomAudioSource>>()
+            .unwrap();
+        let mut audio_output = AudioOutput::new(
+            AudioManager::new(AudioManagerSettings::<MockBackend>::default()).ok(),
+            AudioSettings::default().sound_capacity,
+        );
+        let source = audio_sources.add(AudioSource::from_frames(
+            44_100,
+            vec![kira::Frame::ZERO; 44_100],
+        ));
+        let channel = AudioChannel::<Audio>::default();
+        let instance = channel.play(source).handle();
+        audio_output.play_channel(
+            &channel,
+            &mut AudioCommandContext {
+                asset_server: app.world().resource::<AssetServer>(),
+                audio_sources: &audio_sources,
+                sound_events: &sound_events,
+                audio_instances: &mut audio_instances,
+                policies: &AudioSourcePolicies::default(),
+                rng: &mut AudioRng::from_seed(0),
+                modulators: &modulators,
+                custom_sources: &custom_sources,
+                now: Duration::ZERO,
+            },
+        );
+        let mut instance = audio_instances.get_mut(&instance).unwrap();
+        instance.virtualise();
+        assert!(instance.is_virtualised());
+
+        audio_output.restart_virtualised(&mut instance);
+
+        assert!(!instance.is_virtualised());
+        let channel_track = &audio_output.channel_tracks[&Channel::typed::<Audio>()];
+        assert_eq!(channel_track.track.num_sounds(), 2);
+        let manager = audio_output.manager.as_mut().unwrap();
+        assert_eq!(manager.main_track().num_sounds(), 0);
+    }
 }
@@ -1,6 +1,6 @@
 use bevy::ecs::resource::Resource;
 use bevy::utils::default;
-use kira::{AudioManagerSettings, DefaultBackend, track::MainTrackBuilder};
+use kira::{AudioManagerSettings, Capacities, DefaultBackend, track::MainTrackBuilder};
 
 /// This resource is used to configure the audio backend at creation
 ///
@@ -9,13 +9,20 @@ use kira::{AudioManagerSettings, DefaultBackend, track::MainTrackBuilder};
 #[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct AudioSettings {
     /// The maximum number of sounds that can be playing at a time.
+    ///
+    /// The limit applies to every audio channel and spatial audio emitter separately.
     pub sound_capacity: usize,
+    /// The maximum number of mixer tracks that can exist at a time.
+    ///
+    /// Every audio channel and every spatial audio emitter that played a sound uses a track.
+    pub sub_track_capacity: usize,
 }
 
 impl Default for AudioSettings {
     fn default() -> Self {
         Self {
             sound_capacity: 128,
+            sub_track_capacity: 128,
         }
     }
 }
@@ -23,6 +30,10 @@ impl Default for AudioSettings {
 impl From<AudioSettings> for AudioManagerSettings<DefaultBackend> {
     fn from(settings: AudioSettings) -> Self {
         AudioManagerSettings {
+            capacities: Capacities {
+                sub_track_capacity: settings.sub_track_capacity,
+                ..default()
+            },
             main_track_builder: MainTrackBuilder::new().sound_capacity(settings.sound_capacity),
             ..default()
         }
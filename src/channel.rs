@@ -2,24 +2,52 @@ pub mod dynamic;
 pub mod typed;
 
 use crate::audio::{AudioCommand, FadeIn, FadeOut, PlayAudioCommand, TweenCommand};
+use crate::audio_output::OPEN_LOW_PASS_CUTOFF;
 use crate::instance::AudioInstance;
+use crate::modulator::{AudioModulator, ModulatedValue, ModulatorMapping};
+use crate::sound_event::SoundEvent;
+use crate::source::custom::CustomAudioSource;
+use crate::voice::{AudioDebounce, VoiceLimit};
 use crate::{AudioSource, PlaybackState};
-use bevy::asset::Handle;
+use bevy::asset::{Assets, Handle};
+use bevy::ecs::resource::Resource;
 use kira::sound::static_sound::StaticSoundData;
-use kira::{Decibels, Panning, Value};
+use kira::{Decibels, Panning, PlaybackRate, Value};
 use std::any::TypeId;
 
-#[derive(Clone, PartialEq, Eq, Hash)]
+/// Identifies a typed or dynamic audio channel
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Channel {
+    /// A typed channel, identified by the type parameter of its [`AudioChannel`](crate::AudioChannel)
     Typed(TypeId),
+    /// A dynamic channel, identified by its key in [`DynamicAudioChannels`](crate::DynamicAudioChannels)
     Dynamic(String),
 }
 
+impl Channel {
+    /// The typed channel [`AudioChannel<T>`](crate::AudioChannel)
+    pub fn typed<T: Resource>() -> Self {
+        Channel::Typed(TypeId::of::<T>())
+    }
+
+    /// The dynamic channel with the given key
+    pub fn dynamic(key: impl Into<String>) -> Self {
+        Channel::Dynamic(key.into())
+    }
+}
+
 pub(crate) struct ChannelState {
     pub(crate) paused: bool,
     pub(crate) volume: Decibels,
     pub(crate) playback_rate: f64,
     pub(crate) panning: Panning,
+    pub(crate) voice_limit: Option<VoiceLimit>,
+    pub(crate) debounce: Option<AudioDebounce>,
+    /// Cutoff frequency of the low-pass filter on the track of the channel in Hz
+    pub(crate) low_pass_cutoff: f64,
+    pub(crate) volume_modulator: Option<ModulatedValue>,
+    pub(crate) playback_rate_modulator: Option<ModulatedValue>,
+    pub(crate) panning_modulator: Option<ModulatedValue>,
 }
 
 impl Default for ChannelState {
@@ -29,15 +57,41 @@ impl Default for ChannelState {
             volume: 1.0.into(),
             playback_rate: 1.0,
             panning: Panning::CENTER,
+            voice_limit: None,
+            debounce: None,
+            low_pass_cutoff: OPEN_LOW_PASS_CUTOFF,
+            volume_modulator: None,
+            playback_rate_modulator: None,
+            panning_modulator: None,
         }
     }
 }
 
 impl ChannelState {
-    pub(crate) fn apply(&self, sound: &mut StaticSoundData) {
-        sound.settings.volume = Value::Fixed(self.volume);
-        sound.settings.playback_rate = self.playback_rate.into();
-        sound.settings.panning = Value::Fixed(self.panning);
+    pub(crate) fn apply(&self, sound: &mut StaticSoundData, modulators: &Assets<AudioModulator>) {
+        sound.settings.volume = self
+            .volume_modulator
+            .as_ref()
+            .and_then(|value| {
+                value
+                    .value(modulators, |volume| Decibels(volume as f32))
+                    .ok()
+            })
+            .unwrap_or(Value::Fixed(self.volume));
+        sound.settings.playback_rate = self
+            .playback_rate_modulator
+            .as_ref()
+            .and_then(|value| value.value(modulators, PlaybackRate).ok())
+            .unwrap_or(self.playback_rate.into());
+        sound.settings.panning = self
+            .panning_modulator
+            .as_ref()
+            .and_then(|value| {
+                value
+                    .value(modulators, |panning| Panning(panning as f32))
+                    .ok()
+            })
+            .unwrap_or(Value::Fixed(self.panning));
     }
 }
 
@@ -55,6 +109,37 @@ pub trait AudioControl {
     /// ```
     fn play(&self, audio_source: Handle<AudioSource>) -> PlayAudioCommand<'_>;
 
+    /// Play a variant of a sound event
+    ///
+    /// The settings of the returned command are applied before the randomisation of the sound event.
+    ///
+    /// ```
+    /// # use bevy::prelude::*;
+    /// # use bevy_kira_audio::prelude::*;
+    ///
+    /// fn my_system(asset_server: Res<AssetServer>, audio: Res<Audio>) {
+    ///     audio.play_sound_event(asset_server.load("footsteps.sound_event.ron"));
+    /// }
+    /// ```
+    fn play_sound_event(&self, sound_event: Handle<SoundEvent>) -> PlayAudioCommand<'_>;
+
+    /// Play a custom audio source
+    ///
+    /// Settings of the returned command that custom sources do not support are ignored.
+    /// See [`CustomAudioSource`] for details.
+    ///
+    /// ```
+    /// # use bevy::prelude::*;
+    /// # use bevy_kira_audio::prelude::*;
+    /// # fn synthesiser() -> kira::sound::static_sound::StaticSoundData { unimplemented!() }
+    ///
+    /// fn my_system(mut custom_sources: ResMut<Assets<CustomAudioSource>>, audio: Res<Audio>) {
+    ///     let source = custom_sources.add(CustomAudioSource::new(synthesiser));
+    ///     audio.play_custom(source);
+    /// }
+    /// ```
+    fn play_custom(&self, custom_source: Handle<CustomAudioSource>) -> PlayAudioCommand<'_>;
+
     /// Stop all audio
     ///
     /// ```
@@ -133,6 +218,93 @@ pub trait AudioControl {
     /// ```
     fn set_playback_rate(&self, playback_rate: f64) -> TweenCommand<'_, FadeIn>;
 
+    /// Map the volume in Decibels to the output of a modulator
+    ///
+    /// Setting a fixed volume removes the modulation. While the volume is modulated,
+    /// ducking does not apply to the channel.
+    ///
+    /// ```
+    /// # use bevy::prelude::*;
+    /// # use bevy_kira_audio::prelude::*;
+    ///
+    /// fn my_system(mut modulators: ResMut<AudioModulators>, audio: Res<Audio>) {
+    ///     let lfo = modulators.add_lfo(AudioLfo::new(0.5));
+    ///     audio.set_volume_modulator(lfo, ModulatorMapping::new((-1., 1.), (-20., 0.)));
+    /// }
+    /// ```
+    fn set_volume_modulator(
+        &self,
+        modulator: Handle<AudioModulator>,
+        mapping: ModulatorMapping,
+    ) -> TweenCommand<'_, FadeIn>;
+
+    /// Map the panning to the output of a modulator
+    ///
+    /// Setting a fixed panning removes the modulation.
+    ///
+    /// ```
+    /// # use bevy::prelude::*;
+    /// # use bevy_kira_audio::prelude::*;
+    ///
+    /// fn my_system(mut modulators: ResMut<AudioModulators>, audio: Res<Audio>) {
+    ///     let lfo = modulators.add_lfo(AudioLfo::new(0.2));
+    ///     audio.set_panning_modulator(lfo, ModulatorMapping::new((-1., 1.), (-1., 1.)));
+    /// }
+    /// ```
+    fn set_panning_modulator(
+        &self,
+        modulator: Handle<AudioModulator>,
+        mapping: ModulatorMapping,
+    ) -> TweenCommand<'_, FadeIn>;
+
+    /// Map the playback rate to the output of a modulator
+    ///
+    /// Setting a fixed playback rate removes the modulation.
+    ///
+    /// ```
+    /// # use bevy::prelude::*;
+    /// # use bevy_kira_audio::prelude::*;
+    ///
+    /// fn my_system(mut modulators: ResMut<AudioModulators>, audio: Res<Audio>) {
+    ///     let tweener = modulators.add_tweener(0.);
+    ///     audio.set_playback_rate_modulator(tweener, ModulatorMapping::new((0., 1.), (1., 2.)));
+    /// }
+    /// ```
+    fn set_playback_rate_modulator(
+        &self,
+        modulator: Handle<AudioModulator>,
+        mapping: ModulatorMapping,
+    ) -> TweenCommand<'_, FadeIn>;
+
+    /// Limit the number of sounds playing in this channel at the same time
+    ///
+    /// Pass `None` to remove the limit.
+    ///
+    /// ```
+    /// # use bevy::prelude::*;
+    /// # use bevy_kira_audio::prelude::*;
+    ///
+    /// fn my_system(audio: Res<Audio>) {
+    ///     audio.set_voice_limit(Some(VoiceLimit::new(8, VoiceStealing::Quietest)));
+    /// }
+    /// ```
+    fn set_voice_limit(&self, limit: Option<VoiceLimit>);
+
+    /// Merge plays of the same audio source in this channel that happen in quick succession
+    ///
+    /// Pass `None` to stop debouncing.
+    ///
+    /// ```
+    /// # use bevy::prelude::*;
+    /// # use bevy_kira_audio::prelude::*;
+    /// # use std::time::Duration;
+    ///
+    /// fn my_system(audio: Res<Audio>) {
+    ///     audio.set_debounce(Some(AudioDebounce::new(Duration::from_millis(30))));
+    /// }
+    /// ```
+    fn set_debounce(&self, debounce: Option<AudioDebounce>);
+
     /// Get state for a playback instance.
     fn state(&self, instance_handle: &Handle<AudioInstance>) -> PlaybackState;
 
@@ -1,9 +1,12 @@
 use crate::audio::{
-    AudioCommand, FadeIn, FadeOut, PlayAudioCommand, PlayAudioSettings, TweenCommand,
-    TweenCommandKind,
+    AudioCommand, FadeIn, FadeOut, PlayAudioCommand, PlaySource, TweenCommand, TweenCommandKind,
 };
 use crate::channel::AudioCommandQue;
 use crate::instance::AudioInstance;
+use crate::modulator::{AudioModulator, ModulatedValue, ModulatorMapping};
+use crate::sound_event::SoundEvent;
+use crate::source::custom::CustomAudioSource;
+use crate::voice::{AudioDebounce, VoiceLimit};
 use crate::{AudioControl, AudioSource, PlaybackState};
 use bevy::asset::{AssetId, Handle};
 use bevy::ecs::resource::Resource;
@@ -52,7 +55,37 @@ impl<T> AudioControl for AudioChannel<T> {
     /// }
     /// ```
     fn play(&self, audio_source: Handle<AudioSource>) -> PlayAudioCommand<'_> {
-        PlayAudioCommand::new(audio_source, self)
+        PlayAudioCommand::new(PlaySource::Audio(audio_source), self)
+    }
+
+    /// Play a variant of a sound event
+    ///
+    /// ```
+    /// # use bevy::prelude::*;
+    /// # use bevy_kira_audio::prelude::*;
+    ///
+    /// fn my_system(asset_server: Res<AssetServer>, audio: Res<Audio>) {
+    ///     audio.play_sound_event(asset_server.load("footsteps.sound_event.ron"));
+    /// }
+    /// ```
+    fn play_sound_event(&self, sound_event: Handle<SoundEvent>) -> PlayAudioCommand<'_> {
+        PlayAudioCommand::new(PlaySource::SoundEvent(sound_event), self)
+    }
+
+    /// Play a custom audio source
+    ///
+    /// ```
+    /// # use bevy::prelude::*;
+    /// # use bevy_kira_audio::prelude::*;
+    /// # fn synthesiser() -> kira::sound::static_sound::StaticSoundData { unimplemented!() }
+    ///
+    /// fn my_system(mut custom_sources: ResMut<Assets<CustomAudioSource>>, audio: Res<Audio>) {
+    ///     let source = custom_sources.add(CustomAudioSource::new(synthesiser));
+    ///     audio.play_custom(source);
+    /// }
+    /// ```
+    fn play_custom(&self, custom_source: Handle<CustomAudioSource>) -> PlayAudioCommand<'_> {
+        PlayAudioCommand::new(PlaySource::Custom(custom_source), self)
     }
 
     /// Stop all audio
@@ -145,6 +178,101 @@ impl<T> AudioControl for AudioChannel<T> {
         TweenCommand::new(TweenCommandKind::SetPlaybackRate(playback_rate), self)
     }
 
+    /// Map the volume in Decibels to the output of a modulator
+    ///
+    /// ```
+    /// # use bevy::prelude::*;
+    /// # use bevy_kira_audio::prelude::*;
+    ///
+    /// fn my_system(mut modulators: ResMut<AudioModulators>, audio: Res<Audio>) {
+    ///     let lfo = modulators.add_lfo(AudioLfo::new(0.5));
+    ///     audio.set_volume_modulator(lfo, ModulatorMapping::new((-1., 1.), (-20., 0.)));
+    /// }
+    /// ```
+    fn set_volume_modulator(
+        &self,
+        modulator: Handle<AudioModulator>,
+        mapping: ModulatorMapping,
+    ) -> TweenCommand<'_, FadeIn> {
+        TweenCommand::new(
+            TweenCommandKind::SetVolumeModulator(ModulatedValue::new(modulator, mapping)),
+            self,
+        )
+    }
+
+    /// Map the panning to the output of a modulator
+    ///
+    /// ```
+    /// # use bevy::prelude::*;
+    /// # use bevy_kira_audio::prelude::*;
+    ///
+    /// fn my_system(mut modulators: ResMut<AudioModulators>, audio: Res<Audio>) {
+    ///     let lfo = modulators.add_lfo(AudioLfo::new(0.2));
+    ///     audio.set_panning_modulator(lfo, ModulatorMapping::new((-1., 1.), (-1., 1.)));
+    /// }
+    /// ```
+    fn set_panning_modulator(
+        &self,
+        modulator: Handle<AudioModulator>,
+        mapping: ModulatorMapping,
+    ) -> TweenCommand<'_, FadeIn> {
+        TweenCommand::new(
+            TweenCommandKind::SetPanningModulator(ModulatedValue::new(modulator, mapping)),
+            self,
+        )
+    }
+
+    /// Map the playback rate to the output of a modulator
+    ///
+    /// ```
+    /// # use bevy::prelude::*;
+    /// # use bevy_kira_audio::prelude::*;
+    ///
+    /// fn my_system(mut modulators: ResMut<AudioModulators>, audio: Res<Audio>) {
+    ///     let tweener = modulators.add_tweener(0.);
+    ///     audio.set_playback_rate_modulator(tweener, ModulatorMapping::new((0., 1.), (1., 2.)));
+    /// }
+    /// ```
+    fn set_playback_rate_modulator(
+        &self,
+        modulator: Handle<AudioModulator>,
+        mapping: ModulatorMapping,
+    ) -> TweenCommand<'_, FadeIn> {
+        TweenCommand::new(
+            TweenCommandKind::SetPlaybackRateModulator(ModulatedValue::new(modulator, mapping)),
+            self,
+        )
+    }
+
+    /// Limit the number of sounds playing in this channel at the same time
+    ///
+    /// ```
+    /// # use bevy::prelude::*;
+    /// # use bevy_kira_audio::prelude::*;
+    ///
+    /// fn my_system(audio: Res<Audio>) {
+    ///     audio.set_voice_limit(Some(VoiceLimit::new(8, VoiceStealing::Quietest)));
+    /// }
+    /// ```
+    fn set_voice_limit(&self, limit: Option<VoiceLimit>) {
+        self.que(AudioCommand::SetVoiceLimit(limit));
+    }
+
+    /// Merge plays of the same audio source in this channel that happen in quick succession
+    ///
+    /// ```
+    /// # use bevy::prelude::*;
+    /// # use bevy_kira_audio::prelude::*;
+    /// # use std::time::Duration;
+    ///
+    /// fn my_system(audio: Res<Audio>) {
+    ///     audio.set_debounce(Some(AudioDebounce::new(Duration::from_millis(30))));
+    /// }
+    /// ```
+    fn set_debounce(&self, debounce: Option<AudioDebounce>) {
+        self.que(AudioCommand::SetDebounce(debounce));
+    }
+
     /// Get state for a playback instance.
     fn state(&self, instance_handle: &Handle<AudioInstance>) -> PlaybackState {
         self.states
@@ -155,11 +283,9 @@ impl<T> AudioControl for AudioChannel<T> {
                     .read()
                     .iter()
                     .find(|command| match command {
-                        AudioCommand::Play(PlayAudioSettings {
-                            instance_handle: handle,
-                            settings: _,
-                            source: _,
-                        }) => handle.id() == instance_handle.id(),
+                        AudioCommand::Play(play_args) => {
+                            play_args.instance_handle.id() == instance_handle.id()
+                        }
                         _ => false,
                     })
                     .map(|_| PlaybackState::Queued)
@@ -0,0 +1,238 @@
+//! Lower the volume of channels while other channels are playing
+
+use crate::AudioTween;
+use crate::audio_output::AudioOutput;
+use crate::channel::Channel;
+use crate::instance::AudioInstance;
+use bevy::asset::Assets;
+use bevy::ecs::change_detection::{NonSendMut, ResMut};
+use bevy::ecs::resource::Resource;
+use bevy::ecs::system::Res;
+use bevy::platform::collections::HashMap;
+use bevy::time::Time;
+use std::time::Duration;
+
+/// Resource with all ducking rules
+///
+/// A ducking rule lowers the volume of its target channel while any sound is playing in its
+/// trigger channel. Kira does not support sidechaining, so the trigger is based on the playback
+/// state of sounds and not their loudness.
+/// Like the channel volume, ducking does not apply to sounds played from spatial audio emitters.
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_kira_audio::prelude::*;
+/// # use bevy_kira_audio::Channel;
+/// # use std::time::Duration;
+/// #[derive(Resource)]
+/// struct Dialogue;
+///
+/// #[derive(Resource)]
+/// struct Music;
+///
+/// fn duck_music(mut ducking: ResMut<AudioDucking>) {
+///     ducking.add_rule(
+///         DuckingRule::new(Channel::typed::<Dialogue>(), Channel::typed::<Music>(), -12.)
+///             .with_release(Duration::from_secs(1)),
+///     );
+/// }
+/// ```
+#[derive(Resource, Default, Debug)]
+pub struct AudioDucking {
+    rules: Vec<(DuckingRule, DuckingState)>,
+}
+
+impl AudioDucking {
+    /// Add a ducking rule
+    pub fn add_rule(&mut self, rule: DuckingRule) -> &mut Self {
+        self.rules.push((rule, DuckingState::default()));
+
+        self
+    }
+
+    /// Remove all rules with the given trigger and target channels
+    pub fn remove_rules(&mut self, trigger: &Channel, target: &Channel) -> &mut Self {
+        self.rules
+            .retain(|(rule, _)| &rule.trigger != trigger || &rule.target != target);
+
+        self
+    }
+
+    /// Iterate over all ducking rules
+    pub fn rules(&self) -> impl Iterator<Item = &DuckingRule> {
+        self.rules.iter().map(|(rule, _)| rule)
+    }
+
+    /// Whether the given channel is currently ducked by any rule
+    pub fn is_ducked(&self, channel: &Channel) -> bool {
+        self.rules
+            .iter()
+            .any(|(rule, state)| &rule.target == channel && state.ducked)
+    }
+}
+
+/// Lower the volume of a target channel while a trigger channel is playing
+#[derive(Clone, Debug, PartialEq)]
+pub struct DuckingRule {
+    /// The channel that causes the ducking when it plays
+    pub trigger: Channel,
+    /// The channel that gets quieter
+    pub target: Channel,
+    /// Volume change of the target channel in decibels (usually negative)
+    pub amount: f32,
+    /// How long it takes to lower the volume
+    pub attack: Duration,
+    /// How long it takes to restore the volume
+    pub release: Duration,
+    /// How long the volume stays lowered after the trigger channel stopped playing
+    pub hold: Duration,
+}
+
+impl DuckingRule {
+    /// Create a new ducking rule with an attack of 100ms, a release of 500ms, and no hold
+    pub fn new(trigger: Channel, target: Channel, amount: f32) -> Self {
+        DuckingRule {
+            trigger,
+            target,
+            amount,
+            attack: Duration::from_millis(100),
+            release: Duration::from_millis(500),
+            hold: Duration::ZERO,
+        }
+    }
+
+    /// Set how long it takes to lower the volume
+    pub fn with_attack(mut self, attack: Duration) -> Self {
+        self.attack = attack;
+
+        self
+    }
+
+    /// Set how long it takes to restore the volume
+    pub fn with_release(mut self, release: Duration) -> Self {
+        self.release = release;
+
+        self
+    }
+
+    /// Set how long the volume stays lowered after the trigger channel stopped playing
+    pub fn with_hold(mut self, hold: Duration) -> Self {
+        self.hold = hold;
+
+        self
+    }
+}
+
+#[derive(Default, Debug)]
+struct DuckingState {
+    ducked: bool,
+    hold_remaining: Duration,
+}
+
+impl DuckingState {
+    /// Returns `true` if the ducked state changed
+    fn update(&mut self, rule: &DuckingRule, triggered: bool, delta: Duration) -> bool {
+        if triggered {
+            self.hold_remaining = rule.hold;
+            let changed = !self.ducked;
+            self.ducked = true;
+            return changed;
+        }
+        if !self.ducked {
+            return false;
+        }
+        self.hold_remaining = self.hold_remaining.saturating_sub(delta);
+        if self.hold_remaining.is_zero() {
+            self.ducked = false;
+            return true;
+        }
+
+        false
+    }
+}
+
+pub(crate) fn update_ducking(
+    mut audio_output: NonSendMut<AudioOutput>,
+    mut ducking: ResMut<AudioDucking>,
+    audio_instances: Res<Assets<AudioInstance>>,
+    time: Res<Time>,
+) {
+    if ducking.rules.is_empty() {
+        return;
+    }
+    // target channel -> (volume change, tween if changed)
+    let mut targets: HashMap<Channel, (f32, Option<AudioTween>)> = HashMap::default();
+    for (rule, state) in ducking.rules.iter_mut() {
+        let triggered = audio_output.is_channel_playing(&rule.trigger, &audio_instances);
+        let changed = state.update(rule, triggered, time.delta());
+        let (amount, tween) = targets.entry(rule.target.clone()).or_insert((0., None));
+        if state.ducked {
+            *amount = amount.min(rule.amount);
+        }
+        if changed {
+            *tween = Some(AudioTween::linear(if state.ducked {
+                rule.attack
+            } else {
+                rule.release
+            }));
+        }
+    }
+    for (target, (amount, tween)) in targets {
+        if let Some(tween) = tween {
+            audio_output.set_channel_ducking(&target, amount, &tween);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rule() -> DuckingRule {
+        DuckingRule::new(
+            Channel::dynamic("dialogue"),
+            Channel::dynamic("music"),
+            -12.,
+        )
+        .with_hold(Duration::from_millis(200))
+    }
+
+    #[test]
+    fn ducks_while_triggered() {
+        let rule = rule();
+        let mut state = DuckingState::default();
+
+        assert!(state.update(&rule, true, Duration::from_millis(16)));
+        assert!(state.ducked);
+        assert!(!state.update(&rule, true, Duration::from_millis(16)));
+        assert!(state.ducked);
+    }
+
+    #[test]
+    fn holds_before_releasing() {
+        let rule = rule();
+        let mut state = DuckingState::default();
+        state.update(&rule, true, Duration::ZERO);
+
+        assert!(!state.update(&rule, false, Duration::from_millis(150)));
+        assert!(state.ducked);
+        assert!(state.update(&rule, false, Duration::from_millis(100)));
+        assert!(!state.ducked);
+    }
+
+    #[test]
+    fn removes_rules_by_channels() {
+        let mut ducking = AudioDucking::default();
+        ducking.add_rule(rule());
+        ducking.add_rule(DuckingRule::new(
+            Channel::dynamic("dialogue"),
+            Channel::dynamic("ambience"),
+            -6.,
+        ));
+
+        ducking.remove_rules(&Channel::dynamic("dialogue"), &Channel::dynamic("music"));
+
+        assert_eq!(ducking.rules().count(), 1);
+        assert!(!ducking.is_ducked(&Channel::dynamic("ambience")));
+    }
+}
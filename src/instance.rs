@@ -1,33 +1,174 @@
+use crate::audio::playback_state;
+use crate::channel::Channel;
+use crate::modulator::{AudioModulator, ModulatorMapping};
+use crate::source::custom::{CustomDataHandle, CustomSoundHandle};
+#[cfg(feature = "midi")]
+use crate::source::midi::{MidiEvent, MidiHandle};
+use crate::source::synth::SynthHandle;
+#[cfg(feature = "mod")]
+use crate::source::tracker::{TrackerHandle, TrackerPosition};
+use crate::voice::Voice;
 use crate::{AudioTween, PlaybackState};
 use bevy::asset::{Asset, Assets, Handle};
-use kira::sound::static_sound::StaticSoundHandle;
-use kira::{Decibels, Value};
+use bevy::ecs::entity::Entity;
+use kira::sound::static_sound::{StaticSoundData, StaticSoundHandle};
+use kira::sound::{EndPosition, PlaybackPosition};
+use kira::{Decibels, Panning, PlaybackRate, Tween, Value};
 
 #[derive(Asset, bevy::reflect::TypePath)]
 /// Asset for direct audio control
 pub struct AudioInstance {
-    pub(crate) handle: StaticSoundHandle,
+    pub(crate) handle: SoundHandle,
+    /// The static sound played by this instance, used to restart it after virtualisation
+    pub(crate) sound: Option<StaticSoundData>,
+    /// Handle of the sound data played from a [`CustomAudioSource`](crate::CustomAudioSource)
+    pub(crate) custom: Option<CustomDataHandle>,
+    /// The channel this instance was played on
+    pub(crate) channel: Channel,
+    /// The spatial audio emitter this instance was played from
+    pub(crate) emitter: Option<Entity>,
+    pub(crate) playback_rate: f64,
+    pub(crate) virtual_playback: Option<VirtualPlayback>,
+    pub(crate) voice: Voice,
+}
+
+/// Playback of a virtualised instance that is stopped on the audio thread
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct VirtualPlayback {
+    pub(crate) position: f64,
+    pub(crate) paused: bool,
 }
 
 impl AudioInstance {
+    pub(crate) fn new(
+        handle: SoundHandle,
+        sound: Option<StaticSoundData>,
+        custom: Option<CustomDataHandle>,
+        channel: Channel,
+        emitter: Option<Entity>,
+        playback_rate: f64,
+        voice: Voice,
+    ) -> Self {
+        AudioInstance {
+            handle,
+            sound,
+            custom,
+            channel,
+            emitter,
+            playback_rate,
+            virtual_playback: None,
+            voice,
+        }
+    }
+
     /// Pause the audio instance with the given easing
     pub fn pause(&mut self, tween: AudioTween) {
-        self.handle.pause(tween.into());
+        self.pause_with(tween.into());
+    }
+
+    pub(crate) fn pause_with(&mut self, tween: Tween) {
+        match self.virtual_playback.as_mut() {
+            Some(virtual_playback) => virtual_playback.paused = true,
+            None => self.handle.pause(tween),
+        }
     }
 
     /// Resume the audio instance with the given easing
     pub fn resume(&mut self, tween: AudioTween) {
-        self.handle.resume(tween.into());
+        self.resume_with(tween.into());
+    }
+
+    pub(crate) fn resume_with(&mut self, tween: Tween) {
+        match self.virtual_playback.as_mut() {
+            Some(virtual_playback) => virtual_playback.paused = false,
+            None => self.handle.resume(tween),
+        }
     }
 
     /// Stop the audio instance with the given easing
     pub fn stop(&mut self, tween: AudioTween) {
-        self.handle.stop(tween.into());
+        self.stop_with(tween.into());
+    }
+
+    pub(crate) fn stop_with(&mut self, tween: Tween) {
+        self.virtual_playback = None;
+        self.handle.stop(tween);
     }
 
     /// Get the state of the audio instance
+    ///
+    /// Virtualised instances report the position they would be at if they were still playing.
     pub fn state(&self) -> PlaybackState {
-        (&self.handle).into()
+        match self.virtual_playback {
+            Some(VirtualPlayback {
+                position,
+                paused: true,
+            }) => PlaybackState::Paused { position },
+            Some(VirtualPlayback {
+                position,
+                paused: false,
+            }) => PlaybackState::Playing { position },
+            None => playback_state(self.handle.state(), self.handle.position()),
+        }
+    }
+
+    /// Whether the instance is currently virtualised
+    ///
+    /// Virtualised instances are not playing on the audio thread, but their playback position
+    /// keeps advancing. See [`SpatialVirtualisation`](crate::SpatialVirtualisation).
+    pub fn is_virtualised(&self) -> bool {
+        self.virtual_playback.is_some()
+    }
+
+    /// Whether the instance currently occupies a voice on the audio thread
+    pub(crate) fn is_active_voice(&self) -> bool {
+        self.virtual_playback.is_none()
+            && !matches!(
+                self.handle.state(),
+                kira::sound::PlaybackState::Stopping | kira::sound::PlaybackState::Stopped
+            )
+    }
+
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.virtual_playback.is_none()
+            && self.handle.state() == kira::sound::PlaybackState::Stopped
+    }
+
+    /// Stop the sound on the audio thread, but keep track of its playback position
+    ///
+    /// Custom sounds cannot be restarted at a position and are never virtualised.
+    pub(crate) fn virtualise(&mut self) {
+        if self.virtual_playback.is_some() || self.sound.is_none() {
+            return;
+        }
+        let paused = match self.handle.state() {
+            kira::sound::PlaybackState::Playing => false,
+            kira::sound::PlaybackState::Paused => true,
+            _ => return,
+        };
+        self.virtual_playback = Some(VirtualPlayback {
+            position: self.handle.position(),
+            paused,
+        });
+        self.handle.stop(Tween::default());
+    }
+
+    /// Advance the playback position of a virtualised instance
+    ///
+    /// Instances that reach their end while being virtualised are stopped.
+    pub(crate) fn advance_virtual_playback(&mut self, delta: f64) {
+        let (Some(virtual_playback), Some(sound)) =
+            (self.virtual_playback.as_mut(), self.sound.as_ref())
+        else {
+            return;
+        };
+        if virtual_playback.paused {
+            return;
+        }
+        match advance_position(virtual_playback.position, delta * self.playback_rate, sound) {
+            Some(position) => virtual_playback.position = position,
+            None => self.virtual_playback = None,
+        }
     }
 
     /// Change the volume of the audio instance
@@ -35,8 +176,12 @@ impl AudioInstance {
     /// Higher values increase the volume and lower values decrease it.
     /// Setting the volume of a sound to -60dB or lower makes it silent.
     pub fn set_decibels(&mut self, volume: impl Into<Decibels>, tween: AudioTween) {
-        self.handle
-            .set_volume(Value::Fixed(volume.into()), tween.into());
+        self.set_volume_with(volume.into(), tween.into());
+    }
+
+    pub(crate) fn set_volume_with(&mut self, volume: Decibels, tween: Tween) {
+        self.voice.volume = volume.0;
+        self.handle.set_volume(Value::Fixed(volume), tween);
     }
 
     /// Sets the playback rate of the sound.
@@ -44,7 +189,12 @@ impl AudioInstance {
     /// Changing the playback rate will change both the speed
     /// and pitch of the sound.
     pub fn set_playback_rate(&mut self, playback_rate: f64, tween: AudioTween) {
-        self.handle.set_playback_rate(playback_rate, tween.into());
+        self.set_playback_rate_with(playback_rate, tween.into());
+    }
+
+    pub(crate) fn set_playback_rate_with(&mut self, playback_rate: f64, tween: Tween) {
+        self.playback_rate = playback_rate;
+        self.handle.set_playback_rate(playback_rate, tween);
     }
 
     /// Sets the panning of the sound
@@ -57,14 +207,301 @@ impl AudioInstance {
             .set_panning(Value::Fixed(panning.into()), tween.into());
     }
 
+    /// Map the volume of the audio instance in Decibels to the output of a modulator
+    ///
+    /// Does nothing if the modulator failed to be added to the audio thread.
+    pub fn set_volume_modulator(
+        &mut self,
+        modulator: &AudioModulator,
+        mapping: &ModulatorMapping,
+        tween: AudioTween,
+    ) {
+        if let Some(volume) = mapping.value(modulator, |volume| Decibels(volume as f32)) {
+            self.handle.set_volume(volume, tween.into());
+        }
+    }
+
+    /// Map the playback rate of the audio instance to the output of a modulator
+    ///
+    /// Does nothing if the modulator failed to be added to the audio thread.
+    pub fn set_playback_rate_modulator(
+        &mut self,
+        modulator: &AudioModulator,
+        mapping: &ModulatorMapping,
+        tween: AudioTween,
+    ) {
+        if let Some(playback_rate) = mapping.value(modulator, PlaybackRate) {
+            self.handle.set_playback_rate(playback_rate, tween.into());
+        }
+    }
+
+    /// Map the panning of the audio instance to the output of a modulator
+    ///
+    /// Does nothing if the modulator failed to be added to the audio thread.
+    pub fn set_panning_modulator(
+        &mut self,
+        modulator: &AudioModulator,
+        mapping: &ModulatorMapping,
+        tween: AudioTween,
+    ) {
+        if let Some(panning) = mapping.value(modulator, |panning| Panning(panning as f32)) {
+            self.handle.set_panning(panning, tween.into());
+        }
+    }
+
+    /// Get the handle of the sound data played from a [`CustomAudioSource`](crate::CustomAudioSource)
+    ///
+    /// Returns `None` for other sounds or if the handle is of a different type.
+    pub fn custom_handle<H: 'static>(&mut self) -> Option<&mut H> {
+        self.custom.as_mut()?.downcast_mut()
+    }
+
+    /// Tween the frequency of a playing [`Synth`](crate::Synth) in Hz
+    ///
+    /// Does nothing for other sounds.
+    pub fn set_synth_frequency(&mut self, frequency: f64, tween: AudioTween) {
+        if let Some(synth) = self.custom_handle::<SynthHandle>() {
+            synth.set_frequency(frequency, tween);
+        }
+    }
+
+    /// Tween the gain factor of a playing [`Synth`](crate::Synth)
+    ///
+    /// Does nothing for other sounds.
+    pub fn set_synth_gain(&mut self, gain: f32, tween: AudioTween) {
+        if let Some(synth) = self.custom_handle::<SynthHandle>() {
+            synth.set_gain(gain, tween);
+        }
+    }
+
+    /// Play a MIDI event on a playing [`MidiPlayer`](crate::MidiPlayer)
+    ///
+    /// This can be used to play notes from systems for dynamic music. Does nothing for other
+    /// sounds.
+    #[cfg(feature = "midi")]
+    pub fn send_midi(&mut self, event: MidiEvent) {
+        if let Some(midi) = self.custom_handle::<MidiHandle>() {
+            midi.send(event);
+        }
+    }
+
+    /// Set the tempo of a playing [`MidiPlayer`](crate::MidiPlayer) as factor of the tempo of
+    /// its sequence
+    ///
+    /// Does nothing for other sounds.
+    #[cfg(feature = "midi")]
+    pub fn set_midi_tempo(&mut self, tempo: f64) {
+        if let Some(midi) = self.custom_handle::<MidiHandle>() {
+            midi.set_tempo(tempo);
+        }
+    }
+
+    /// Transpose new notes of a playing [`MidiPlayer`](crate::MidiPlayer) by the given number of
+    /// semitones
+    ///
+    /// Does nothing for other sounds.
+    #[cfg(feature = "midi")]
+    pub fn set_midi_transpose(&mut self, semitones: i8) {
+        if let Some(midi) = self.custom_handle::<MidiHandle>() {
+            midi.set_transpose(semitones);
+        }
+    }
+
+    /// Mute or unmute a MIDI channel of a playing [`MidiPlayer`](crate::MidiPlayer)
+    ///
+    /// Does nothing for other sounds.
+    #[cfg(feature = "midi")]
+    pub fn set_midi_channel_muted(&mut self, channel: u8, muted: bool) {
+        if let Some(midi) = self.custom_handle::<MidiHandle>() {
+            midi.set_channel_muted(channel, muted);
+        }
+    }
+
+    /// The order and row of a playing tracker module
+    ///
+    /// Returns `None` for other sounds.
+    #[cfg(feature = "mod")]
+    pub fn tracker_position(&self) -> Option<TrackerPosition> {
+        self.custom
+            .as_ref()?
+            .downcast_ref::<TrackerHandle>()
+            .map(TrackerHandle::position)
+    }
+
+    /// Continue a playing tracker module at the given order and row
+    ///
+    /// Does nothing for other sounds.
+    #[cfg(feature = "mod")]
+    pub fn seek_tracker(&mut self, position: TrackerPosition) {
+        if let Some(tracker) = self.custom_handle::<TrackerHandle>() {
+            tracker.seek(position);
+        }
+    }
+
+    /// Mute or unmute a channel of a playing tracker module
+    ///
+    /// Does nothing for other sounds.
+    #[cfg(feature = "mod")]
+    pub fn set_tracker_channel_muted(&mut self, channel: usize, muted: bool) {
+        if let Some(tracker) = self.custom_handle::<TrackerHandle>() {
+            tracker.set_channel_muted(channel, muted);
+        }
+    }
+
+    /// Whether a playing tracker module starts over once it reaches its end
+    ///
+    /// Does nothing for other sounds.
+    #[cfg(feature = "mod")]
+    pub fn set_tracker_looped(&mut self, looped: bool) {
+        if let Some(tracker) = self.custom_handle::<TrackerHandle>() {
+            tracker.set_looped(looped);
+        }
+    }
+
     /// Sets the playback position to the specified time in seconds.
+    ///
+    /// Sounds from a [`CustomAudioSource`](crate::CustomAudioSource) cannot seek.
     pub fn seek_to(&mut self, position: f64) {
-        self.handle.seek_to(position);
+        match self.virtual_playback.as_mut() {
+            Some(virtual_playback) => virtual_playback.position = position.max(0.),
+            None => self.handle.seek_to(position),
+        }
     }
 
     /// Moves the playback position by the specified amount of time in seconds.
+    ///
+    /// Sounds from a [`CustomAudioSource`](crate::CustomAudioSource) cannot seek.
     pub fn seek_by(&mut self, amount: f64) {
-        self.handle.seek_by(amount);
+        match self.virtual_playback.as_mut() {
+            Some(virtual_playback) => {
+                virtual_playback.position = (virtual_playback.position + amount).max(0.)
+            }
+            None => self.handle.seek_by(amount),
+        }
+    }
+}
+
+/// Handle of a sound playing on the audio thread
+pub(crate) enum SoundHandle {
+    Static(StaticSoundHandle),
+    Custom(CustomSoundHandle),
+}
+
+impl SoundHandle {
+    pub(crate) fn state(&self) -> kira::sound::PlaybackState {
+        match self {
+            SoundHandle::Static(handle) => handle.state(),
+            SoundHandle::Custom(handle) => handle.state(),
+        }
+    }
+
+    pub(crate) fn position(&self) -> f64 {
+        match self {
+            SoundHandle::Static(handle) => handle.position(),
+            SoundHandle::Custom(handle) => handle.position(),
+        }
+    }
+
+    pub(crate) fn pause(&mut self, tween: Tween) {
+        match self {
+            SoundHandle::Static(handle) => handle.pause(tween),
+            SoundHandle::Custom(handle) => handle.pause(tween),
+        }
+    }
+
+    pub(crate) fn resume(&mut self, tween: Tween) {
+        match self {
+            SoundHandle::Static(handle) => handle.resume(tween),
+            SoundHandle::Custom(handle) => handle.resume(tween),
+        }
+    }
+
+    pub(crate) fn stop(&mut self, tween: Tween) {
+        match self {
+            SoundHandle::Static(handle) => handle.stop(tween),
+            SoundHandle::Custom(handle) => handle.stop(tween),
+        }
+    }
+
+    pub(crate) fn set_volume(&mut self, volume: impl Into<Value<Decibels>>, tween: Tween) {
+        match self {
+            SoundHandle::Static(handle) => handle.set_volume(volume, tween),
+            SoundHandle::Custom(handle) => handle.set_volume(volume, tween),
+        }
+    }
+
+    pub(crate) fn set_playback_rate(
+        &mut self,
+        playback_rate: impl Into<Value<PlaybackRate>>,
+        tween: Tween,
+    ) {
+        match self {
+            SoundHandle::Static(handle) => handle.set_playback_rate(playback_rate, tween),
+            SoundHandle::Custom(handle) => handle.set_playback_rate(playback_rate, tween),
+        }
+    }
+
+    pub(crate) fn set_panning(&mut self, panning: impl Into<Value<Panning>>, tween: Tween) {
+        match self {
+            SoundHandle::Static(handle) => handle.set_panning(panning, tween),
+            SoundHandle::Custom(handle) => handle.set_panning(panning, tween),
+        }
+    }
+
+    pub(crate) fn seek_to(&mut self, position: f64) {
+        if let SoundHandle::Static(handle) = self {
+            handle.seek_to(position);
+        }
+    }
+
+    pub(crate) fn seek_by(&mut self, amount: f64) {
+        if let SoundHandle::Static(handle) = self {
+            handle.seek_by(amount);
+        }
+    }
+}
+
+/// Move the position of a sound in seconds like the audio thread would
+///
+/// A negative `amount` or a reversed sound move the position backwards. Returns `None` if the
+/// sound reached its start or end without looping.
+fn advance_position(position: f64, amount: f64, sound: &StaticSoundData) -> Option<f64> {
+    let backwards = amount.is_sign_negative() != sound.settings.reverse;
+    let duration = sound.duration().as_secs_f64();
+    let sample_rate = sound.sample_rate as f64;
+    let loop_region = sound.settings.loop_region.as_ref().map(|loop_region| {
+        let start = position_in_seconds(loop_region.start, sample_rate);
+        let end = match loop_region.end {
+            EndPosition::EndOfAudio => duration,
+            EndPosition::Custom(end) => position_in_seconds(end, sample_rate),
+        };
+        (start, end)
+    });
+    if backwards {
+        let position = position - amount.abs();
+        match loop_region {
+            Some((start, end)) if end > start && position <= start => {
+                Some(end - (start - position) % (end - start))
+            }
+            None if position <= 0. => None,
+            _ => Some(position),
+        }
+    } else {
+        let position = position + amount.abs();
+        match loop_region {
+            Some((start, end)) if end > start && position >= end => {
+                Some(start + (position - start) % (end - start))
+            }
+            None if position >= duration => None,
+            _ => Some(position),
+        }
+    }
+}
+
+fn position_in_seconds(position: PlaybackPosition, sample_rate: f64) -> f64 {
+    match position {
+        PlaybackPosition::Seconds(seconds) => seconds,
+        PlaybackPosition::Samples(samples) => samples as f64 / sample_rate,
     }
 }
 
@@ -86,3 +523,43 @@ impl AudioInstanceAssetsExt for Assets<AudioInstance> {
             .unwrap_or(PlaybackState::Stopped)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::AudioSource;
+    use kira::Frame;
+    use kira::sound::Region;
+
+    fn one_second() -> StaticSoundData {
+        AudioSource::from_frames(10, vec![Frame::ZERO; 10]).sound
+    }
+
+    #[test]
+    fn virtual_playback_moves_forward() {
+        let sound = one_second();
+        assert_eq!(advance_position(0.25, 0.5, &sound), Some(0.75));
+        assert_eq!(advance_position(0.75, 0.5, &sound), None);
+    }
+
+    #[test]
+    fn virtual_playback_of_reversed_sounds_moves_backwards() {
+        let sound = one_second().reverse(true);
+        assert_eq!(advance_position(0.75, 0.5, &sound), Some(0.25));
+        assert_eq!(advance_position(0.25, 0.5, &sound), None);
+
+        let sound = sound.reverse(false);
+        assert_eq!(advance_position(0.75, -0.5, &sound), Some(0.25));
+    }
+
+    #[test]
+    fn virtual_playback_wraps_backwards_in_loop_region() {
+        let sound = one_second().reverse(true).loop_region(Region {
+            start: PlaybackPosition::Seconds(0.2),
+            end: EndPosition::Custom(PlaybackPosition::Seconds(0.6)),
+        });
+        let position = advance_position(0.3, 0.2, &sound).unwrap();
+
+        assert!((position - 0.5).abs() < 1e-9);
+    }
+}
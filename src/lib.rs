@@ -38,9 +38,16 @@ mod audio;
 mod audio_output;
 mod backend_settings;
 mod channel;
+mod ducking;
 mod instance;
+mod mixer;
+mod modulator;
+mod parameters;
+mod rng;
+mod sound_event;
 mod source;
 mod spatial;
+mod voice;
 
 pub use audio::{
     AudioApp, AudioEasing, AudioTween, FadeIn, FadeOut, PlayAudioCommand, PlaybackState,
@@ -49,12 +56,33 @@ pub use audio::{
 pub use backend_settings::AudioSettings;
 use bevy::app::{PostUpdate, PreUpdate};
 use bevy::asset::AssetApp;
-pub use channel::AudioControl;
-pub use source::AudioSource;
+pub use channel::{AudioControl, Channel};
+pub use ducking::{AudioDucking, DuckingRule};
+pub use mixer::{ChannelSnapshot, MixerSnapshot, MixerSnapshots, SnapshotId};
+pub use modulator::{AudioLfo, AudioModulator, AudioModulators, LfoWaveform, ModulatorMapping};
+pub use parameters::{
+    AudioParameters, AudioProperty, GlobalAudioParameters, ParameterMapping, ParameterTarget,
+};
+pub use rng::AudioRng;
+pub use sound_event::{SoundEvent, SoundEventVariant, VariantSelection};
+pub use source::custom::CustomAudioSource;
+#[cfg(feature = "midi")]
+pub use source::midi::{MidiEvent, MidiHandle, MidiPlayer, MidiSequence};
+#[cfg(feature = "midi")]
+pub use source::soundfont::SoundFont;
+pub use source::synth::{
+    AdsrEnvelope, FrequencyModulation, NoiseColor, Synth, SynthHandle, SynthLayer, SynthWaveform,
+};
+#[cfg(feature = "mod")]
+pub use source::tracker::{TrackerHandle, TrackerPosition};
+pub use source::{AudioSource, AudioSourceError};
 pub use spatial::{
-    DefaultSpatialRadius, SpatialAudioEmitter, SpatialAudioPlugin, SpatialAudioReceiver,
-    SpatialRadius,
+    DefaultSpatialLowPass, DefaultSpatialRadius, ReverbZone, ReverbZoneShape, SpatialAudioApp,
+    SpatialAudioCone, SpatialAudioEmitter, SpatialAudioEmitterShape, SpatialAudioMode,
+    SpatialAudioPlugin, SpatialAudioReceiver, SpatialLowPass, SpatialOcclusion,
+    SpatialOcclusionInput, SpatialOcclusionSettings, SpatialRadius, SpatialVirtualisation,
 };
+pub use voice::{AudioDebounce, AudioSourcePolicies, VoiceLimit, VoiceStealing};
 /// Most commonly used types
 pub mod prelude {
     #[doc(hidden)]
@@ -71,13 +99,46 @@ pub mod prelude {
     #[doc(hidden)]
     pub use crate::channel::typed::AudioChannel;
     #[doc(hidden)]
+    pub use crate::ducking::{AudioDucking, DuckingRule};
+    #[doc(hidden)]
     pub use crate::instance::{AudioInstance, AudioInstanceAssetsExt};
     #[doc(hidden)]
-    pub use crate::source::AudioSource;
+    pub use crate::mixer::{ChannelSnapshot, MixerSnapshot, MixerSnapshots};
+    #[doc(hidden)]
+    pub use crate::modulator::{
+        AudioLfo, AudioModulator, AudioModulators, LfoWaveform, ModulatorMapping,
+    };
+    #[doc(hidden)]
+    pub use crate::parameters::{
+        AudioParameters, AudioProperty, GlobalAudioParameters, ParameterMapping, ParameterTarget,
+    };
+    #[doc(hidden)]
+    pub use crate::rng::AudioRng;
+    #[doc(hidden)]
+    pub use crate::sound_event::{SoundEvent, SoundEventVariant, VariantSelection};
+    #[doc(hidden)]
+    #[cfg(feature = "aiff")]
+    pub use crate::source::aiff_loader::*;
+    #[doc(hidden)]
+    pub use crate::source::audio_loader::*;
+    #[doc(hidden)]
+    pub use crate::source::custom::CustomAudioSource;
     #[doc(hidden)]
     #[cfg(feature = "flac")]
     pub use crate::source::flac_loader::*;
     #[doc(hidden)]
+    #[cfg(feature = "midi")]
+    pub use crate::source::midi::{MidiEvent, MidiHandle, MidiPlayer, MidiSequence};
+    #[doc(hidden)]
+    #[cfg(feature = "midi")]
+    pub use crate::source::midi_loader::*;
+    #[doc(hidden)]
+    #[cfg(feature = "settings_loader")]
+    pub use crate::source::mixer_snapshot_loader::*;
+    #[doc(hidden)]
+    #[cfg(feature = "mod")]
+    pub use crate::source::mod_loader::*;
+    #[doc(hidden)]
     #[cfg(feature = "mp3")]
     pub use crate::source::mp3_loader::*;
     #[doc(hidden)]
@@ -87,10 +148,34 @@ pub mod prelude {
     #[cfg(feature = "settings_loader")]
     pub use crate::source::settings_loader::*;
     #[doc(hidden)]
+    #[cfg(feature = "settings_loader")]
+    pub use crate::source::sfx_loader::*;
+    #[doc(hidden)]
+    #[cfg(feature = "settings_loader")]
+    pub use crate::source::sound_event_loader::*;
+    #[doc(hidden)]
+    #[cfg(feature = "midi")]
+    pub use crate::source::soundfont::SoundFont;
+    #[doc(hidden)]
+    pub use crate::source::synth::{
+        AdsrEnvelope, FrequencyModulation, NoiseColor, Synth, SynthHandle, SynthLayer,
+        SynthWaveform,
+    };
+    #[doc(hidden)]
+    #[cfg(feature = "mod")]
+    pub use crate::source::tracker::{TrackerHandle, TrackerPosition};
+    #[doc(hidden)]
     #[cfg(feature = "wav")]
     pub use crate::source::wav_loader::*;
     #[doc(hidden)]
-    pub use crate::spatial::{SpatialAudioEmitter, SpatialAudioPlugin, SpatialAudioReceiver};
+    pub use crate::source::{AudioSource, AudioSourceError};
+    #[doc(hidden)]
+    pub use crate::spatial::{
+        ReverbZone, ReverbZoneShape, SpatialAudioApp, SpatialAudioCone, SpatialAudioEmitter,
+        SpatialAudioEmitterShape, SpatialAudioPlugin, SpatialAudioReceiver,
+    };
+    #[doc(hidden)]
+    pub use crate::voice::{AudioDebounce, AudioSourcePolicies, VoiceLimit, VoiceStealing};
     #[doc(hidden)]
     pub use crate::{Audio, AudioPlugin, MainTrack};
     pub use kira::{
@@ -103,15 +188,32 @@ pub mod prelude {
 }
 
 use crate::audio_output::{AudioOutput, cleanup_stopped_instances, play_dynamic_channels};
+use crate::ducking::update_ducking;
+use crate::mixer::{TypedChannelNames, apply_mixer_snapshots};
+use crate::modulator::add_modulators;
+use crate::parameters::update_audio_parameters;
 
+#[cfg(feature = "aiff")]
+use crate::source::aiff_loader::AiffLoader;
+use crate::source::audio_loader::AudioLoader;
 #[cfg(feature = "flac")]
 use crate::source::flac_loader::FlacLoader;
+#[cfg(feature = "midi")]
+use crate::source::midi_loader::{MidiLoader, SoundFontLoader};
+#[cfg(feature = "settings_loader")]
+use crate::source::mixer_snapshot_loader::MixerSnapshotLoader;
+#[cfg(feature = "mod")]
+use crate::source::mod_loader::ModLoader;
 #[cfg(feature = "mp3")]
 use crate::source::mp3_loader::Mp3Loader;
 #[cfg(feature = "ogg")]
 use crate::source::ogg_loader::OggLoader;
 #[cfg(feature = "settings_loader")]
 use crate::source::settings_loader::SettingsLoader;
+#[cfg(feature = "settings_loader")]
+use crate::source::sfx_loader::SfxLoader;
+#[cfg(feature = "settings_loader")]
+use crate::source::sound_event_loader::SoundEventLoader;
 #[cfg(feature = "wav")]
 use crate::source::wav_loader::WavLoader;
 use bevy::prelude::{App, IntoScheduleConfigs, Plugin, Resource, SystemSet};
@@ -157,7 +259,11 @@ impl Plugin for AudioPlugin {
     fn build(&self, app: &mut App) {
         app.init_non_send::<AudioOutput>()
             .init_asset::<AudioSource>()
-            .init_asset::<AudioInstance>();
+            .init_asset::<AudioInstance>()
+            .init_asset::<SoundEvent>()
+            .init_asset::<MixerSnapshot>()
+            .init_asset::<AudioModulator>()
+            .init_asset::<CustomAudioSource>();
 
         #[cfg(feature = "mp3")]
         app.init_asset_loader::<Mp3Loader>();
@@ -167,15 +273,54 @@ impl Plugin for AudioPlugin {
         app.init_asset_loader::<WavLoader>();
         #[cfg(feature = "flac")]
         app.init_asset_loader::<FlacLoader>();
+        #[cfg(feature = "aiff")]
+        app.init_asset_loader::<AiffLoader>();
+        #[cfg(feature = "mod")]
+        app.init_asset_loader::<ModLoader>();
+        #[cfg(feature = "midi")]
+        app.init_asset::<MidiSequence>()
+            .init_asset::<SoundFont>()
+            .init_asset_loader::<MidiLoader>()
+            .init_asset_loader::<SoundFontLoader>();
+        app.init_asset_loader::<AudioLoader>();
 
         #[cfg(feature = "settings_loader")]
-        app.init_asset_loader::<SettingsLoader>();
+        app.init_asset_loader::<SettingsLoader>()
+            .init_asset_loader::<SfxLoader>()
+            .init_asset_loader::<SoundEventLoader>()
+            .init_asset_loader::<MixerSnapshotLoader>();
 
         app.init_resource::<DynamicAudioChannels>()
+            .init_resource::<AudioSourcePolicies>()
+            .init_resource::<AudioRng>()
+            .init_resource::<AudioDucking>()
+            .init_resource::<MixerSnapshots>()
+            .init_resource::<TypedChannelNames>()
+            .init_resource::<GlobalAudioParameters>()
+            .init_resource::<AudioModulators>()
+            .add_systems(
+                PostUpdate,
+                add_modulators
+                    .before(AudioSystemSet::PlayDynamicChannels)
+                    .before(AudioSystemSet::PlayTypedChannels),
+            )
             .add_systems(
                 PostUpdate,
                 play_dynamic_channels.in_set(AudioSystemSet::PlayDynamicChannels),
             )
+            .add_systems(
+                PostUpdate,
+                (apply_mixer_snapshots, update_ducking)
+                    .after(AudioSystemSet::PlayDynamicChannels)
+                    .after(AudioSystemSet::PlayTypedChannels),
+            )
+            .add_systems(
+                PostUpdate,
+                update_audio_parameters
+                    .in_set(AudioSystemSet::UpdateParameters)
+                    .after(apply_mixer_snapshots)
+                    .after(update_ducking),
+            )
             .add_systems(
                 PreUpdate,
                 cleanup_stopped_instances.in_set(AudioSystemSet::InstanceCleanup),
@@ -193,6 +338,8 @@ pub enum AudioSystemSet {
     PlayDynamicChannels,
     /// Label for systems in [`CoreStage::PostUpdate`] that process audio commands for typed channels
     PlayTypedChannels,
+    /// Label for the system in [`CoreStage::PostUpdate`] that applies game parameters to audio
+    UpdateParameters,
 }
 
 /// The default audio channel
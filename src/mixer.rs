@@ -0,0 +1,381 @@
+//! Mixer snapshots to change the mix of multiple channels at once
+
+use crate::AudioTween;
+use crate::audio_output::AudioOutput;
+use crate::channel::Channel;
+use crate::instance::AudioInstance;
+use bevy::asset::{Asset, AssetServer, Assets, Handle, LoadState};
+use bevy::ecs::change_detection::{NonSendMut, ResMut};
+use bevy::ecs::resource::Resource;
+use bevy::ecs::system::Res;
+use bevy::log::warn;
+use bevy::reflect::TypePath;
+use std::any::TypeId;
+use std::collections::HashMap;
+
+/// A mix of multiple channels
+///
+/// Channels are referenced by name. Typed channels are named after their type
+/// (e.g. `"MainTrack"` for the default channel) and dynamic channels by their key.
+/// A snapshot can change their volume, panning, playback rate, and the cutoff of their low-pass filter.
+///
+/// Apply snapshots with the [`MixerSnapshots`] resource.
+/// With the `settings_loader` feature, snapshots can be loaded from `*.mixer.ron` files:
+/// ```ron
+/// (
+///     channels: {
+///         "Music": (volume: Some(-12.0), low_pass_cutoff: Some(800.0)),
+///         "MainTrack": (volume: Some(-3.0), playback_rate: Some(0.8)),
+///     },
+/// )
+/// ```
+#[derive(Asset, TypePath, Clone, Debug, Default)]
+#[cfg_attr(feature = "settings_loader", derive(serde::Deserialize))]
+#[cfg_attr(feature = "settings_loader", serde(deny_unknown_fields))]
+pub struct MixerSnapshot {
+    /// Settings per channel name
+    pub channels: HashMap<String, ChannelSnapshot>,
+}
+
+impl MixerSnapshot {
+    /// Add the settings of a channel to this snapshot
+    pub fn with_channel(mut self, name: impl Into<String>, channel: ChannelSnapshot) -> Self {
+        self.channels.insert(name.into(), channel);
+
+        self
+    }
+}
+
+/// Settings of one channel in a [`MixerSnapshot`]
+///
+/// Settings that are `None` are not changed by the snapshot.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "settings_loader", derive(serde::Deserialize))]
+#[cfg_attr(feature = "settings_loader", serde(deny_unknown_fields))]
+pub struct ChannelSnapshot {
+    /// Volume in decibels
+    #[cfg_attr(feature = "settings_loader", serde(default))]
+    pub volume: Option<f32>,
+    /// Panning from -1.0 (hard left) to 1.0 (hard right)
+    #[cfg_attr(feature = "settings_loader", serde(default))]
+    pub panning: Option<f32>,
+    /// Playback rate
+    #[cfg_attr(feature = "settings_loader", serde(default))]
+    pub playback_rate: Option<f64>,
+    /// Cutoff frequency of the low-pass filter of the channel in Hz
+    ///
+    /// Like ducking, the filter does not apply to sounds played from spatial audio emitters.
+    #[cfg_attr(feature = "settings_loader", serde(default))]
+    pub low_pass_cutoff: Option<f64>,
+}
+
+impl ChannelSnapshot {
+    /// Set the volume in decibels
+    pub fn with_volume(mut self, volume: f32) -> Self {
+        self.volume = Some(volume);
+
+        self
+    }
+
+    /// Set the panning
+    pub fn with_panning(mut self, panning: f32) -> Self {
+        self.panning = Some(panning);
+
+        self
+    }
+
+    /// Set the playback rate
+    pub fn with_playback_rate(mut self, playback_rate: f64) -> Self {
+        self.playback_rate = Some(playback_rate);
+
+        self
+    }
+
+    /// Set the cutoff frequency of the low-pass filter in Hz
+    pub fn with_low_pass_cutoff(mut self, cutoff: f64) -> Self {
+        self.low_pass_cutoff = Some(cutoff);
+
+        self
+    }
+
+    /// Use the settings of `other` where it has any
+    fn merge(&mut self, other: &ChannelSnapshot) {
+        self.volume = other.volume.or(self.volume);
+        self.panning = other.panning.or(self.panning);
+        self.playback_rate = other.playback_rate.or(self.playback_rate);
+        self.low_pass_cutoff = other.low_pass_cutoff.or(self.low_pass_cutoff);
+    }
+}
+
+/// Identifies a snapshot pushed to [`MixerSnapshots`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SnapshotId(u64);
+
+/// Resource with the stack of active mixer snapshots
+///
+/// Snapshots with a higher priority override the settings of snapshots with a lower priority.
+/// For the same priority, the snapshot pushed last wins. When no snapshot sets a value of a channel
+/// anymore, the channel goes back to the value it had before the first snapshot changed it.
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_kira_audio::prelude::*;
+/// # use std::time::Duration;
+/// fn pause_menu(asset_server: Res<AssetServer>, mut snapshots: ResMut<MixerSnapshots>) {
+///     snapshots.push(
+///         asset_server.load("pause_menu.mixer.ron"),
+///         10,
+///         AudioTween::linear(Duration::from_millis(500)),
+///     );
+/// }
+/// ```
+#[derive(Resource, Default, Debug)]
+pub struct MixerSnapshots {
+    stack: Vec<StackedSnapshot>,
+    next_id: u64,
+    pending_transition: Option<AudioTween>,
+}
+
+#[derive(Debug)]
+struct StackedSnapshot {
+    id: SnapshotId,
+    snapshot: Handle<MixerSnapshot>,
+    priority: i32,
+}
+
+impl MixerSnapshots {
+    /// Push a snapshot with the given priority and transition to the new mix
+    pub fn push(
+        &mut self,
+        snapshot: Handle<MixerSnapshot>,
+        priority: i32,
+        tween: AudioTween,
+    ) -> SnapshotId {
+        let id = SnapshotId(self.next_id);
+        self.next_id += 1;
+        self.stack.push(StackedSnapshot {
+            id,
+            snapshot,
+            priority,
+        });
+        self.pending_transition = Some(tween);
+
+        id
+    }
+
+    /// Remove the snapshot pushed last and transition to the new mix
+    pub fn pop(&mut self, tween: AudioTween) -> Option<Handle<MixerSnapshot>> {
+        let popped = self.stack.pop()?;
+        self.pending_transition = Some(tween);
+
+        Some(popped.snapshot)
+    }
+
+    /// Remove the given snapshot and transition to the new mix
+    pub fn remove(&mut self, id: SnapshotId, tween: AudioTween) -> Option<Handle<MixerSnapshot>> {
+        let index = self.stack.iter().position(|stacked| stacked.id == id)?;
+        self.pending_transition = Some(tween);
+
+        Some(self.stack.remove(index).snapshot)
+    }
+
+    /// Remove all snapshots and transition back to the original mix
+    pub fn clear(&mut self, tween: AudioTween) {
+        self.stack.clear();
+        self.pending_transition = Some(tween);
+    }
+
+    /// Whether there is any active snapshot
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    /// Combine the active snapshots into the settings per channel name
+    ///
+    /// Returns `None` while any snapshot is still loading. Snapshots that failed to load are skipped.
+    fn resolve<'a>(
+        &self,
+        snapshots: impl Fn(&Handle<MixerSnapshot>) -> SnapshotAsset<'a>,
+    ) -> Option<HashMap<String, ChannelSnapshot>> {
+        let mut ordered: Vec<&StackedSnapshot> = self.stack.iter().collect();
+        // stable sort keeps the push order for equal priorities
+        ordered.sort_by_key(|stacked| stacked.priority);
+        let mut channels: HashMap<String, ChannelSnapshot> = HashMap::default();
+        for stacked in ordered {
+            let snapshot = match snapshots(&stacked.snapshot) {
+                SnapshotAsset::Loaded(snapshot) => snapshot,
+                SnapshotAsset::Loading => return None,
+                SnapshotAsset::Failed => continue,
+            };
+            for (name, channel) in snapshot.channels.iter() {
+                channels.entry(name.clone()).or_default().merge(channel);
+            }
+        }
+
+        Some(channels)
+    }
+}
+
+/// State of the asset of a stacked snapshot
+enum SnapshotAsset<'a> {
+    Loaded(&'a MixerSnapshot),
+    Loading,
+    Failed,
+}
+
+/// Names of all typed channels
+#[derive(Resource, Default)]
+pub(crate) struct TypedChannelNames(pub(crate) HashMap<String, TypeId>);
+
+impl TypedChannelNames {
+    pub(crate) fn register<T: 'static>(&mut self) {
+        self.0
+            .insert(short_type_name::<T>().to_owned(), TypeId::of::<T>());
+    }
+
+    /// Typed channels take precedence over dynamic channels with the same name
+    fn channel(&self, name: &str) -> Channel {
+        match self.0.get(name) {
+            Some(type_id) => Channel::Typed(*type_id),
+            None => Channel::Dynamic(name.to_owned()),
+        }
+    }
+}
+
+/// Name of the type without module path or generics
+fn short_type_name<T>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    let name = name.split('<').next().unwrap_or(name);
+
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+pub(crate) fn apply_mixer_snapshots(
+    mut audio_output: NonSendMut<AudioOutput>,
+    mut mixer_snapshots: ResMut<MixerSnapshots>,
+    snapshot_assets: Res<Assets<MixerSnapshot>>,
+    asset_server: Res<AssetServer>,
+    channel_names: Res<TypedChannelNames>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+) {
+    let Some(tween) = mixer_snapshots.pending_transition.clone() else {
+        return;
+    };
+    // wait until all snapshots are loaded
+    let Some(channels) = mixer_snapshots.resolve(|handle| match snapshot_assets.get(handle) {
+        Some(snapshot) => SnapshotAsset::Loaded(snapshot),
+        None if matches!(asset_server.load_state(handle), LoadState::Loading) => {
+            SnapshotAsset::Loading
+        }
+        None => {
+            warn!("Skipping a mixer snapshot that failed to load");
+            SnapshotAsset::Failed
+        }
+    }) else {
+        return;
+    };
+    mixer_snapshots.pending_transition = None;
+    let channels: HashMap<Channel, ChannelSnapshot> = channels
+        .into_iter()
+        .map(|(name, channel)| (channel_names.channel(&name), channel))
+        .collect();
+    audio_output.apply_mixer_snapshot(&channels, &tween, &mut audio_instances);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use uuid::Uuid;
+
+    fn handle() -> Handle<MixerSnapshot> {
+        Handle::Uuid(Uuid::new_v4(), Default::default())
+    }
+
+    #[test]
+    fn higher_priority_snapshots_override_lower_ones() {
+        let (low, high, later) = (handle(), handle(), handle());
+        let mut assets: HashMap<Handle<MixerSnapshot>, MixerSnapshot> = HashMap::default();
+        assets.insert(
+            low.clone(),
+            MixerSnapshot::default().with_channel(
+                "Music",
+                ChannelSnapshot::default()
+                    .with_volume(-6.)
+                    .with_panning(0.5),
+            ),
+        );
+        assets.insert(
+            high.clone(),
+            MixerSnapshot::default()
+                .with_channel("Music", ChannelSnapshot::default().with_volume(-20.)),
+        );
+        assets.insert(
+            later.clone(),
+            MixerSnapshot::default()
+                .with_channel("Music", ChannelSnapshot::default().with_volume(-10.)),
+        );
+        let mut snapshots = MixerSnapshots::default();
+        snapshots.push(high, 10, AudioTween::default());
+        snapshots.push(low, 0, AudioTween::default());
+        snapshots.push(later, 10, AudioTween::default());
+
+        let channels = snapshots
+            .resolve(|handle| SnapshotAsset::Loaded(&assets[handle]))
+            .unwrap();
+
+        assert_eq!(
+            channels.get("Music"),
+            Some(&ChannelSnapshot {
+                volume: Some(-10.),
+                panning: Some(0.5),
+                playback_rate: None,
+                low_pass_cutoff: None,
+            })
+        );
+    }
+
+    #[test]
+    fn waits_for_snapshots_to_load() {
+        let mut snapshots = MixerSnapshots::default();
+        snapshots.push(handle(), 0, AudioTween::default());
+
+        assert!(snapshots.resolve(|_| SnapshotAsset::Loading).is_none());
+    }
+
+    #[test]
+    fn skips_snapshots_that_failed_to_load() {
+        let (failed, loaded) = (handle(), handle());
+        let snapshot = MixerSnapshot::default().with_channel(
+            "Music",
+            ChannelSnapshot::default().with_low_pass_cutoff(500.),
+        );
+        let mut snapshots = MixerSnapshots::default();
+        snapshots.push(failed.clone(), 0, AudioTween::default());
+        snapshots.push(loaded, 0, AudioTween::default());
+
+        let channels = snapshots
+            .resolve(|handle| {
+                if *handle == failed {
+                    SnapshotAsset::Failed
+                } else {
+                    SnapshotAsset::Loaded(&snapshot)
+                }
+            })
+            .unwrap();
+
+        assert_eq!(channels["Music"].low_pass_cutoff, Some(500.));
+    }
+
+    #[test]
+    fn resolves_channel_names() {
+        struct Music;
+        let mut names = TypedChannelNames::default();
+        names.register::<Music>();
+
+        assert_eq!(
+            names.channel("Music"),
+            Channel::Typed(TypeId::of::<Music>())
+        );
+        assert_eq!(names.channel("sfx"), Channel::dynamic("sfx"));
+    }
+}
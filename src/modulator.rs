@@ -0,0 +1,363 @@
+//! Modulators that continuously change properties of sounds on the audio thread
+
+use crate::AudioTween;
+use crate::audio::AudioEasing;
+use crate::audio_output::AudioOutput;
+use bevy::asset::{Asset, Assets, Handle};
+use bevy::ecs::change_detection::{NonSendMut, ResMut};
+use bevy::ecs::resource::Resource;
+use bevy::reflect::TypePath;
+use kira::modulator::ModulatorId;
+use kira::modulator::lfo::LfoHandle;
+use kira::modulator::tweener::TweenerHandle;
+use kira::{Mapping, Value};
+use std::marker::PhantomData;
+use uuid::Uuid;
+
+/// Waveforms of low-frequency oscillators
+pub type LfoWaveform = kira::modulator::lfo::Waveform;
+
+/// Asset for direct control of a modulator
+///
+/// Modulators are either low-frequency oscillators (LFOs) or tweeners. Create them with the
+/// [`AudioModulators`] resource and map their value to the volume, playback rate, or panning
+/// of sounds with a [`ModulatorMapping`]:
+/// - [`PlayAudioCommand::with_volume_modulator`](crate::PlayAudioCommand::with_volume_modulator)
+///   and friends when playing a sound
+/// - [`AudioInstance::set_volume_modulator`](crate::AudioInstance::set_volume_modulator)
+///   and friends for a playing sound
+/// - [`AudioControl::set_volume_modulator`](crate::AudioControl::set_volume_modulator)
+///   and friends for all sounds in a channel
+///
+/// Removing the asset removes the modulator from the audio thread. Values mapped from a removed
+/// modulator stay at their last value. If the modulator could not be added to the audio thread,
+/// the asset is still added, but its setters do nothing and commands mapping values to it are
+/// dropped with a warning.
+#[derive(Asset, TypePath)]
+pub struct AudioModulator {
+    kind: ModulatorKind,
+}
+
+pub(crate) enum ModulatorKind {
+    Lfo(LfoHandle),
+    Tweener(TweenerHandle),
+    /// The modulator could not be added to the audio thread
+    Failed,
+}
+
+impl AudioModulator {
+    pub(crate) fn id(&self) -> Option<ModulatorId> {
+        match &self.kind {
+            ModulatorKind::Lfo(lfo) => Some(lfo.id()),
+            ModulatorKind::Tweener(tweener) => Some(tweener.id()),
+            ModulatorKind::Failed => None,
+        }
+    }
+
+    /// Whether this modulator is a low-frequency oscillator
+    pub fn is_lfo(&self) -> bool {
+        matches!(self.kind, ModulatorKind::Lfo(_))
+    }
+
+    /// Whether this modulator could not be added to the audio thread
+    pub fn is_failed(&self) -> bool {
+        matches!(self.kind, ModulatorKind::Failed)
+    }
+
+    /// Set the waveform of an LFO
+    ///
+    /// Does nothing for tweeners.
+    pub fn set_waveform(&mut self, waveform: LfoWaveform) {
+        if let ModulatorKind::Lfo(lfo) = &mut self.kind {
+            lfo.set_waveform(waveform);
+        }
+    }
+
+    /// Set the frequency of an LFO in Hz
+    ///
+    /// Does nothing for tweeners.
+    pub fn set_frequency(&mut self, frequency: f64, tween: AudioTween) {
+        if let ModulatorKind::Lfo(lfo) = &mut self.kind {
+            lfo.set_frequency(frequency, tween.into());
+        }
+    }
+
+    /// Set the amplitude of an LFO
+    ///
+    /// Does nothing for tweeners.
+    pub fn set_amplitude(&mut self, amplitude: f64, tween: AudioTween) {
+        if let ModulatorKind::Lfo(lfo) = &mut self.kind {
+            lfo.set_amplitude(amplitude, tween.into());
+        }
+    }
+
+    /// Set the offset of an LFO
+    ///
+    /// Does nothing for tweeners.
+    pub fn set_offset(&mut self, offset: f64, tween: AudioTween) {
+        if let ModulatorKind::Lfo(lfo) = &mut self.kind {
+            lfo.set_offset(offset, tween.into());
+        }
+    }
+
+    /// Set the phase of an LFO in cycles (from 0.0 to 1.0)
+    ///
+    /// Does nothing for tweeners.
+    pub fn set_phase(&mut self, phase: f64) {
+        if let ModulatorKind::Lfo(lfo) = &mut self.kind {
+            lfo.set_phase(phase);
+        }
+    }
+
+    /// Tween the value of a tweener to the given target
+    ///
+    /// Does nothing for LFOs.
+    pub fn set(&mut self, target: f64, tween: AudioTween) {
+        if let ModulatorKind::Tweener(tweener) = &mut self.kind {
+            tweener.set(target, tween.into());
+        }
+    }
+}
+
+/// Settings of a low-frequency oscillator
+#[derive(Clone, Debug)]
+pub struct AudioLfo {
+    /// Shape of the oscillation
+    pub waveform: LfoWaveform,
+    /// Oscillations per second
+    pub frequency: f64,
+    /// Factor of the oscillation; the output goes from `offset - amplitude` to `offset + amplitude`
+    pub amplitude: f64,
+    /// Value added to the oscillation
+    pub offset: f64,
+    /// Phase to start from in cycles (from 0.0 to 1.0)
+    pub starting_phase: f64,
+}
+
+impl Default for AudioLfo {
+    fn default() -> Self {
+        AudioLfo {
+            waveform: LfoWaveform::Sine,
+            frequency: 2.,
+            amplitude: 1.,
+            offset: 0.,
+            starting_phase: 0.,
+        }
+    }
+}
+
+impl AudioLfo {
+    /// Create a sine LFO with the given frequency in Hz, oscillating between -1.0 and 1.0
+    pub fn new(frequency: f64) -> Self {
+        AudioLfo {
+            frequency,
+            ..Default::default()
+        }
+    }
+
+    /// Set the waveform
+    pub fn with_waveform(mut self, waveform: LfoWaveform) -> Self {
+        self.waveform = waveform;
+
+        self
+    }
+
+    /// Set the amplitude
+    pub fn with_amplitude(mut self, amplitude: f64) -> Self {
+        self.amplitude = amplitude;
+
+        self
+    }
+
+    /// Set the offset
+    pub fn with_offset(mut self, offset: f64) -> Self {
+        self.offset = offset;
+
+        self
+    }
+
+    /// Set the phase to start from in cycles
+    pub fn with_starting_phase(mut self, starting_phase: f64) -> Self {
+        self.starting_phase = starting_phase;
+
+        self
+    }
+}
+
+/// A modulator waiting to be added to the audio thread
+pub(crate) enum ModulatorSettings {
+    Lfo(AudioLfo),
+    Tweener(f64),
+}
+
+/// Resource to create modulators
+///
+/// The returned handles can be used right away. Commands using a modulator that was not added
+/// to the audio thread yet are retried in the next frame. Commands using a modulator that failed
+/// to be added, for example because there is no audio device, are dropped with a warning.
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_kira_audio::prelude::*;
+/// fn tremolo(
+///     mut modulators: ResMut<AudioModulators>,
+///     asset_server: Res<AssetServer>,
+///     audio: Res<Audio>,
+/// ) {
+///     let lfo = modulators.add_lfo(AudioLfo::new(4.));
+///     audio
+///         .play(asset_server.load("sounds/loop.ogg"))
+///         .with_volume_modulator(lfo, ModulatorMapping::new((-1., 1.), (-12., 0.)));
+/// }
+/// ```
+#[derive(Resource, Default)]
+pub struct AudioModulators {
+    pending: Vec<(Handle<AudioModulator>, ModulatorSettings)>,
+}
+
+impl AudioModulators {
+    /// Add a low-frequency oscillator
+    pub fn add_lfo(&mut self, lfo: AudioLfo) -> Handle<AudioModulator> {
+        self.add(ModulatorSettings::Lfo(lfo))
+    }
+
+    /// Add a tweener starting at the given value
+    ///
+    /// Change its value with [`AudioModulator::set`].
+    pub fn add_tweener(&mut self, initial_value: f64) -> Handle<AudioModulator> {
+        self.add(ModulatorSettings::Tweener(initial_value))
+    }
+
+    fn add(&mut self, settings: ModulatorSettings) -> Handle<AudioModulator> {
+        let handle = Handle::<AudioModulator>::Uuid(Uuid::new_v4(), PhantomData);
+        self.pending.push((handle.clone(), settings));
+
+        handle
+    }
+}
+
+/// Maps the output of a modulator to the value of a property
+///
+/// Modulator outputs in the input range are mapped to the output range with the given easing.
+#[derive(Clone, Debug)]
+pub struct ModulatorMapping {
+    /// Range of modulator outputs
+    pub input_range: (f64, f64),
+    /// Range of property values
+    pub output_range: (f64, f64),
+    /// Easing between the ends of the output range
+    pub easing: AudioEasing,
+}
+
+impl ModulatorMapping {
+    /// Create a linear mapping
+    pub fn new(input_range: (f64, f64), output_range: (f64, f64)) -> Self {
+        ModulatorMapping {
+            input_range,
+            output_range,
+            easing: AudioEasing::Linear,
+        }
+    }
+
+    /// Set the easing of the mapping
+    pub fn with_easing(mut self, easing: AudioEasing) -> Self {
+        self.easing = easing;
+
+        self
+    }
+
+    /// The kira value, if the modulator was added to the audio thread
+    pub(crate) fn value<T>(
+        &self,
+        modulator: &AudioModulator,
+        map: impl Fn(f64) -> T,
+    ) -> Option<Value<T>> {
+        Some(Value::FromModulator {
+            id: modulator.id()?,
+            mapping: Mapping {
+                input_range: self.input_range,
+                output_range: (map(self.output_range.0), map(self.output_range.1)),
+                easing: self.easing,
+            },
+        })
+    }
+}
+
+/// Why the value of a [`ModulatedValue`] is not available
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum MissingModulator {
+    /// The modulator was not added to the audio thread yet
+    Pending,
+    /// The modulator could not be added to the audio thread
+    Failed,
+}
+
+/// A property value mapped from a modulator that might not exist yet
+#[derive(Clone, Debug)]
+pub(crate) struct ModulatedValue {
+    pub(crate) modulator: Handle<AudioModulator>,
+    pub(crate) mapping: ModulatorMapping,
+}
+
+impl ModulatedValue {
+    pub(crate) fn new(modulator: Handle<AudioModulator>, mapping: ModulatorMapping) -> Self {
+        ModulatedValue { modulator, mapping }
+    }
+
+    /// The kira value, if the modulator was added
+    pub(crate) fn value<T>(
+        &self,
+        modulators: &Assets<AudioModulator>,
+        map: impl Fn(f64) -> T,
+    ) -> Result<Value<T>, MissingModulator> {
+        let modulator = modulators
+            .get(&self.modulator)
+            .ok_or(MissingModulator::Pending)?;
+
+        self.mapping
+            .value(modulator, map)
+            .ok_or(MissingModulator::Failed)
+    }
+}
+
+pub(crate) fn add_modulators(
+    mut audio_output: NonSendMut<AudioOutput>,
+    mut modulators: ResMut<AudioModulators>,
+    mut modulator_assets: ResMut<Assets<AudioModulator>>,
+) {
+    for (handle, settings) in modulators.pending.drain(..) {
+        // insert failed modulators as well, so commands using them are not retried forever
+        let kind = audio_output
+            .add_modulator(settings)
+            .unwrap_or(ModulatorKind::Failed);
+        let _ = modulator_assets.insert(&handle, AudioModulator { kind });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reports_pending_and_failed_modulators() {
+        let mut modulators = Assets::<AudioModulator>::default();
+        let handle = Handle::<AudioModulator>::Uuid(Uuid::new_v4(), PhantomData);
+        let value = ModulatedValue::new(handle.clone(), ModulatorMapping::new((0., 1.), (0., 1.)));
+
+        assert_eq!(
+            value.value(&modulators, |x| x),
+            Err(MissingModulator::Pending)
+        );
+
+        let _ = modulators.insert(
+            &handle,
+            AudioModulator {
+                kind: ModulatorKind::Failed,
+            },
+        );
+        assert_eq!(
+            value.value(&modulators, |x| x),
+            Err(MissingModulator::Failed)
+        );
+    }
+}
@@ -0,0 +1,500 @@
+//! Game parameters that drive properties of channels, instances and emitters
+
+use crate::AudioTween;
+use crate::audio_output::AudioOutput;
+use crate::channel::Channel;
+use crate::instance::AudioInstance;
+use crate::spatial::SpatialAudioEmitter;
+use bevy::asset::{Assets, Handle};
+use bevy::ecs::change_detection::{NonSendMut, ResMut};
+use bevy::ecs::component::Component;
+use bevy::ecs::resource::Resource;
+use bevy::ecs::system::{Query, Res};
+use bevy::log::warn;
+use bevy::math::curve::Curve;
+use bevy::platform::collections::HashMap;
+use bevy::time::Time;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Changes smaller than this are not applied to the audio
+const CHANGE_THRESHOLD: f32 = 0.0001;
+
+/// Resource with global game parameters and their mappings
+///
+/// Game parameters are named values like `"speed"` or `"health"`. Mappings turn them into
+/// properties of channels or audio instances using a curve. Mapped properties are updated every
+/// frame, optionally smoothed over time.
+/// Mappings on channels overwrite changes of the channel volume, panning, or playback rate done
+/// through channel commands or [`MixerSnapshots`](crate::MixerSnapshots).
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_kira_audio::prelude::*;
+/// # use bevy_kira_audio::Channel;
+/// # use std::time::Duration;
+/// #[derive(Resource)]
+/// struct Engine;
+///
+/// fn setup(mut parameters: ResMut<GlobalAudioParameters>) {
+///     parameters.add_mapping(
+///         ParameterMapping::new(
+///             "speed",
+///             ParameterTarget::Channel(Channel::typed::<Engine>(), AudioProperty::PlaybackRate),
+///             EasingCurve::new(0.8, 2.0, EaseFunction::QuadraticIn),
+///         )
+///         .with_smoothing(Duration::from_millis(200)),
+///     );
+/// }
+///
+/// fn update_speed(mut parameters: ResMut<GlobalAudioParameters>) {
+///     parameters.set("speed", 0.5);
+/// }
+/// ```
+#[derive(Resource, Default, Debug)]
+pub struct GlobalAudioParameters {
+    parameters: ParameterSet,
+}
+
+impl GlobalAudioParameters {
+    /// Set the value of a game parameter
+    pub fn set(&mut self, parameter: impl Into<String>, value: f32) -> &mut Self {
+        self.parameters.values.insert(parameter.into(), value);
+
+        self
+    }
+
+    /// Get the value of a game parameter
+    pub fn get(&self, parameter: &str) -> Option<f32> {
+        self.parameters.values.get(parameter).copied()
+    }
+
+    /// Add a mapping from a game parameter to an audio property
+    ///
+    /// Mappings to [`ParameterTarget::Emitter`] and to unsupported properties are rejected with a
+    /// warning.
+    pub fn add_mapping(&mut self, mapping: ParameterMapping) -> &mut Self {
+        if let ParameterTarget::Emitter(_) = mapping.target {
+            warn!(
+                "Ignoring global mapping of {:?} to an emitter; add it to the AudioParameters of the emitter instead",
+                mapping.parameter
+            );
+            return self;
+        }
+        self.parameters.add_mapping(mapping);
+
+        self
+    }
+
+    /// Remove all mappings of the given game parameter
+    pub fn remove_mappings(&mut self, parameter: &str) -> &mut Self {
+        self.parameters.remove_mappings(parameter);
+
+        self
+    }
+}
+
+/// Component with game parameters of an entity and their mappings
+///
+/// Parameters that are not set on the entity fall back to the [`GlobalAudioParameters`].
+/// Mappings with a [`ParameterTarget::Emitter`] change the sounds of the [`SpatialAudioEmitter`]
+/// on the same entity.
+#[derive(Component, Default, Debug)]
+pub struct AudioParameters {
+    parameters: ParameterSet,
+    pub(crate) emitter: EmitterModulation,
+}
+
+impl AudioParameters {
+    /// Set the value of a game parameter
+    pub fn with_parameter(mut self, parameter: impl Into<String>, value: f32) -> Self {
+        self.set(parameter, value);
+
+        self
+    }
+
+    /// Add a mapping from a game parameter to an audio property
+    pub fn with_mapping(mut self, mapping: ParameterMapping) -> Self {
+        self.add_mapping(mapping);
+
+        self
+    }
+
+    /// Set the value of a game parameter
+    pub fn set(&mut self, parameter: impl Into<String>, value: f32) -> &mut Self {
+        self.parameters.values.insert(parameter.into(), value);
+
+        self
+    }
+
+    /// Get the value of a game parameter set on this entity
+    pub fn get(&self, parameter: &str) -> Option<f32> {
+        self.parameters.values.get(parameter).copied()
+    }
+
+    /// Add a mapping from a game parameter to an audio property
+    ///
+    /// Mappings to unsupported properties are rejected with a warning.
+    pub fn add_mapping(&mut self, mapping: ParameterMapping) -> &mut Self {
+        self.parameters.add_mapping(mapping);
+
+        self
+    }
+
+    /// Remove all mappings of the given game parameter
+    pub fn remove_mappings(&mut self, parameter: &str) -> &mut Self {
+        self.parameters.remove_mappings(parameter);
+
+        self
+    }
+}
+
+/// Maps a game parameter to an audio property
+#[derive(Clone)]
+pub struct ParameterMapping {
+    /// Name of the game parameter
+    pub parameter: String,
+    /// The property to change
+    pub target: ParameterTarget,
+    /// Curve from the parameter value to the property value
+    ///
+    /// Parameter values outside the domain of the curve are clamped to it.
+    pub curve: Arc<dyn Curve<f32> + Send + Sync>,
+    /// How long it takes to follow roughly two thirds of a change
+    ///
+    /// Zero applies changes immediately.
+    pub smoothing: Duration,
+}
+
+impl ParameterMapping {
+    /// Create a new mapping without smoothing
+    pub fn new(
+        parameter: impl Into<String>,
+        target: ParameterTarget,
+        curve: impl Curve<f32> + Send + Sync + 'static,
+    ) -> Self {
+        ParameterMapping {
+            parameter: parameter.into(),
+            target,
+            curve: Arc::new(curve),
+            smoothing: Duration::ZERO,
+        }
+    }
+
+    /// Smooth changes of the property over time
+    pub fn with_smoothing(mut self, smoothing: Duration) -> Self {
+        self.smoothing = smoothing;
+
+        self
+    }
+}
+
+impl Debug for ParameterMapping {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ParameterMapping")
+            .field("parameter", &self.parameter)
+            .field("target", &self.target)
+            .field("domain", &self.curve.domain())
+            .field("smoothing", &self.smoothing)
+            .finish()
+    }
+}
+
+/// What a [`ParameterMapping`] changes
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParameterTarget {
+    /// A property of all sounds in a channel
+    ///
+    /// [`AudioProperty::LowPassCutoff`] sets the low-pass filter on the track of the channel,
+    /// which does not apply to sounds played from spatial audio emitters.
+    Channel(Channel, AudioProperty),
+    /// A property of a single audio instance
+    ///
+    /// Map the gain of a layer in a layered sound to the [`AudioProperty::Volume`] of the
+    /// instance playing that layer. Instances do not have filters, so mappings to
+    /// [`AudioProperty::LowPassCutoff`] are rejected.
+    Instance(Handle<AudioInstance>, AudioProperty),
+    /// A property of the spatial audio emitter on the entity with the [`AudioParameters`]
+    ///
+    /// The volume is added to the spatial volume and the low-pass cutoff is combined with the
+    /// other low-pass filters of the emitter. The playback rate is set on all sounds of the
+    /// emitter, including sounds played later. The panning is controlled by spatial audio,
+    /// so mappings to [`AudioProperty::Panning`] are rejected.
+    Emitter(AudioProperty),
+}
+
+impl ParameterTarget {
+    /// Whether the target has the property
+    fn is_supported(&self) -> bool {
+        !matches!(
+            self,
+            ParameterTarget::Instance(_, AudioProperty::LowPassCutoff)
+                | ParameterTarget::Emitter(AudioProperty::Panning)
+        )
+    }
+}
+
+/// A property of sounds that can be driven by game parameters
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AudioProperty {
+    /// Volume in decibels
+    Volume,
+    /// Playback rate
+    PlaybackRate,
+    /// Panning from -1.0 (hard left) to 1.0 (hard right)
+    Panning,
+    /// Cutoff frequency of a low-pass filter in Hz
+    LowPassCutoff,
+}
+
+/// Combined emitter properties of all [`ParameterTarget::Emitter`] mappings of an entity
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct EmitterModulation {
+    /// Volume offset in decibels
+    pub(crate) volume: f32,
+    pub(crate) low_pass_cutoff: Option<f64>,
+    pub(crate) playback_rate: Option<f64>,
+}
+
+#[derive(Default, Debug)]
+struct ParameterSet {
+    values: HashMap<String, f32>,
+    mappings: Vec<MappingState>,
+}
+
+impl ParameterSet {
+    fn add_mapping(&mut self, mapping: ParameterMapping) {
+        if !mapping.target.is_supported() {
+            warn!(
+                "Ignoring mapping of {:?} to unsupported target {:?}",
+                mapping.parameter, mapping.target
+            );
+            return;
+        }
+        self.mappings.push(MappingState::new(mapping));
+    }
+
+    fn remove_mappings(&mut self, parameter: &str) {
+        self.mappings
+            .retain(|state| state.mapping.parameter != parameter);
+    }
+}
+
+#[derive(Debug)]
+struct MappingState {
+    mapping: ParameterMapping,
+    current: Option<f32>,
+    applied: Option<f32>,
+}
+
+impl MappingState {
+    fn new(mapping: ParameterMapping) -> Self {
+        MappingState {
+            mapping,
+            current: None,
+            applied: None,
+        }
+    }
+
+    /// Move the property value towards the mapped parameter value
+    ///
+    /// Returns the new property value if it changed noticeably since it was last applied.
+    fn update(&mut self, parameter: f32, delta: Duration) -> Option<f32> {
+        let target = self.mapping.curve.sample_clamped(parameter);
+        let current = match self.current {
+            Some(current) if !self.mapping.smoothing.is_zero() => {
+                let factor =
+                    1. - (-delta.as_secs_f32() / self.mapping.smoothing.as_secs_f32()).exp();
+                current + (target - current) * factor
+            }
+            _ => target,
+        };
+        self.current = Some(current);
+        if self
+            .applied
+            .is_some_and(|applied| (applied - current).abs() < CHANGE_THRESHOLD)
+        {
+            return None;
+        }
+        self.applied = Some(current);
+
+        Some(current)
+    }
+}
+
+fn apply_mapping(
+    target: &ParameterTarget,
+    value: f32,
+    audio_output: &mut AudioOutput,
+    audio_instances: &mut Assets<AudioInstance>,
+) {
+    match target {
+        ParameterTarget::Channel(channel, property) => {
+            audio_output.set_channel_property(channel, *property, value, audio_instances);
+        }
+        ParameterTarget::Instance(handle, property) => {
+            let Some(mut instance) = audio_instances.get_mut(handle) else {
+                return;
+            };
+            match property {
+                AudioProperty::Volume => instance.set_decibels(value, AudioTween::default()),
+                AudioProperty::PlaybackRate => {
+                    instance.set_playback_rate(value as f64, AudioTween::default())
+                }
+                AudioProperty::Panning => instance.set_panning(value, AudioTween::default()),
+                // rejected when the mapping is added
+                AudioProperty::LowPassCutoff => (),
+            }
+        }
+        ParameterTarget::Emitter(_) => (),
+    }
+}
+
+pub(crate) fn update_audio_parameters(
+    mut audio_output: NonSendMut<AudioOutput>,
+    mut global: ResMut<GlobalAudioParameters>,
+    mut entities: Query<(&mut AudioParameters, Option<&SpatialAudioEmitter>)>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    time: Res<Time>,
+) {
+    let delta = time.delta();
+    let global = &mut global.parameters;
+    for state in global.mappings.iter_mut() {
+        let Some(parameter) = global.values.get(&state.mapping.parameter).copied() else {
+            continue;
+        };
+        if let Some(value) = state.update(parameter, delta) {
+            apply_mapping(
+                &state.mapping.target,
+                value,
+                &mut audio_output,
+                &mut audio_instances,
+            );
+        }
+    }
+    for (mut parameters, emitter) in entities.iter_mut() {
+        let parameters = parameters.as_mut();
+        let mut modulation = EmitterModulation::default();
+        for state in parameters.parameters.mappings.iter_mut() {
+            let name = &state.mapping.parameter;
+            let Some(parameter) = parameters
+                .parameters
+                .values
+                .get(name)
+                .or_else(|| global.values.get(name))
+                .copied()
+            else {
+                continue;
+            };
+            let changed = state.update(parameter, delta);
+            let ParameterTarget::Emitter(property) = state.mapping.target else {
+                if let Some(value) = changed {
+                    apply_mapping(
+                        &state.mapping.target,
+                        value,
+                        &mut audio_output,
+                        &mut audio_instances,
+                    );
+                }
+                continue;
+            };
+            let current = state.current.unwrap_or_default();
+            match property {
+                AudioProperty::Volume => modulation.volume += current,
+                AudioProperty::LowPassCutoff => {
+                    let cutoff = current as f64;
+                    modulation.low_pass_cutoff = Some(
+                        modulation
+                            .low_pass_cutoff
+                            .map_or(cutoff, |other| other.min(cutoff)),
+                    );
+                }
+                AudioProperty::PlaybackRate => {
+                    modulation.playback_rate = Some(current as f64);
+                    if let (Some(value), Some(emitter)) = (changed, emitter) {
+                        for instance in emitter.instances.iter() {
+                            if let Some(mut instance) = audio_instances.get_mut(instance) {
+                                instance.set_playback_rate(value as f64, AudioTween::default());
+                            }
+                        }
+                    }
+                }
+                AudioProperty::Panning => (),
+            }
+        }
+        parameters.emitter = modulation;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bevy::math::curve::{FunctionCurve, Interval};
+
+    fn mapping(smoothing: Duration) -> MappingState {
+        MappingState::new(
+            ParameterMapping::new(
+                "speed",
+                ParameterTarget::Emitter(AudioProperty::Volume),
+                FunctionCurve::new(Interval::UNIT, |speed| speed * -20.),
+            )
+            .with_smoothing(smoothing),
+        )
+    }
+
+    #[test]
+    fn maps_parameters_through_the_curve() {
+        let mut state = mapping(Duration::ZERO);
+
+        assert_eq!(state.update(0.5, Duration::from_millis(16)), Some(-10.));
+        // values outside the domain are clamped
+        assert_eq!(state.update(3., Duration::from_millis(16)), Some(-20.));
+    }
+
+    #[test]
+    fn does_not_reapply_unchanged_values() {
+        let mut state = mapping(Duration::ZERO);
+
+        assert!(state.update(0.5, Duration::from_millis(16)).is_some());
+        assert!(state.update(0.5, Duration::from_millis(16)).is_none());
+    }
+
+    #[test]
+    fn smooths_changes_over_time() {
+        let mut state = mapping(Duration::from_millis(100));
+        state.update(0., Duration::from_millis(16));
+
+        let value = state.update(1., Duration::from_millis(100)).unwrap();
+
+        let expected = -20. * (1. - (-1f32).exp());
+        assert!((value - expected).abs() < 0.001);
+        for _ in 0..100 {
+            state.update(1., Duration::from_millis(100));
+        }
+        assert!((state.current.unwrap() + 20.).abs() < 0.001);
+    }
+
+    #[test]
+    fn rejects_mappings_to_unsupported_properties() {
+        let curve = FunctionCurve::new(Interval::UNIT, |value| value);
+        let mut parameters = AudioParameters::default()
+            .with_mapping(ParameterMapping::new(
+                "speed",
+                ParameterTarget::Emitter(AudioProperty::Panning),
+                curve.clone(),
+            ))
+            .with_mapping(ParameterMapping::new(
+                "speed",
+                ParameterTarget::Emitter(AudioProperty::PlaybackRate),
+                curve.clone(),
+            ));
+        parameters.add_mapping(ParameterMapping::new(
+            "speed",
+            ParameterTarget::Instance(Handle::default(), AudioProperty::LowPassCutoff),
+            curve,
+        ));
+
+        assert_eq!(parameters.parameters.mappings.len(), 1);
+    }
+}
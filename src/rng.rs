@@ -0,0 +1,103 @@
+//! Random numbers for varying audio playback
+
+use bevy::ecs::resource::Resource;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::ops::RangeInclusive;
+
+/// Random number generator used for randomised playback
+///
+/// This resource is used for [`SoundEvent`](crate::SoundEvent)s and the random ranges of
+/// [`PlayAudioCommand`](crate::PlayAudioCommand). By default, it is seeded randomly.
+/// Insert a seeded generator to make randomised playback reproducible:
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_kira_audio::prelude::*;
+/// # let mut app = App::new();
+/// app.insert_resource(AudioRng::from_seed(42));
+/// ```
+#[derive(Resource, Clone, Debug)]
+pub struct AudioRng {
+    state: u64,
+}
+
+impl Default for AudioRng {
+    fn default() -> Self {
+        AudioRng::from_seed(RandomState::new().build_hasher().finish())
+    }
+}
+
+impl AudioRng {
+    /// Create a generator with the given seed
+    ///
+    /// Generators with the same seed produce the same sequence of random numbers.
+    pub fn from_seed(seed: u64) -> Self {
+        AudioRng { state: seed }
+    }
+
+    /// Reset the generator to the given seed
+    pub fn reseed(&mut self, seed: u64) {
+        self.state = seed;
+    }
+
+    /// Next random number (SplitMix64)
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Random number in `[0, 1)`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Random index in `[0, len)`
+    pub(crate) fn index(&mut self, len: usize) -> usize {
+        ((self.next_f64() * len as f64) as usize).min(len.saturating_sub(1))
+    }
+
+    /// Random number in the given range
+    pub fn range_f64(&mut self, range: &RangeInclusive<f64>) -> f64 {
+        range.start() + (range.end() - range.start()) * self.next_f64()
+    }
+
+    /// Random number in the given range
+    pub fn range_f32(&mut self, range: &RangeInclusive<f32>) -> f32 {
+        self.range_f64(&(*range.start() as f64..=*range.end() as f64)) as f32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn same_seed_gives_same_numbers() {
+        let mut first = AudioRng::from_seed(42);
+        let mut second = AudioRng::from_seed(42);
+
+        for _ in 0..10 {
+            assert_eq!(first.next_u64(), second.next_u64());
+        }
+
+        second.reseed(42);
+        let mut first = AudioRng::from_seed(42);
+        assert_eq!(first.next_u64(), second.next_u64());
+    }
+
+    #[test]
+    fn numbers_are_within_range() {
+        let mut rng = AudioRng::from_seed(3);
+
+        for _ in 0..100 {
+            assert!((0.0..1.0).contains(&rng.next_f64()));
+            assert!((-6.0..=-3.0).contains(&rng.range_f32(&(-6.0..=-3.0))));
+            assert!(rng.index(3) < 3);
+        }
+        assert_eq!(rng.range_f64(&(2.0..=2.0)), 2.0);
+    }
+}
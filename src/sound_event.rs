@@ -0,0 +1,291 @@
+//! Sound events that pick one of multiple audio sources every time they are played
+
+use crate::audio::PartialSoundSettings;
+use crate::rng::AudioRng;
+use crate::source::AudioSource;
+use bevy::asset::{Asset, Handle};
+use bevy::reflect::TypePath;
+use std::ops::RangeInclusive;
+
+/// A sound with variations
+///
+/// Every time a sound event is played, one of its variants is selected and played
+/// with randomised volume, playback rate and start position.
+/// Play sound events with [`AudioControl::play_sound_event`](crate::AudioControl::play_sound_event).
+///
+/// With the `settings_loader` feature, sound events can be loaded from `*.sound_event.ron` files:
+/// ```ron
+/// (
+///     variants: [
+///         (file: "sounds/step_1.ogg", weight: 2.0),
+///         (file: "sounds/step_2.ogg"),
+///     ],
+///     selection: RandomNoRepeat,
+///     volume: Some((start: -3.0, end: 0.0)),
+///     playback_rate: Some((start: 0.95, end: 1.05)),
+/// )
+/// ```
+#[derive(Asset, TypePath, Clone, Debug, Default)]
+pub struct SoundEvent {
+    /// The audio sources to choose from
+    pub variants: Vec<SoundEventVariant>,
+    /// How the variant to play is selected
+    pub selection: VariantSelection,
+    /// Range of the volume offset in decibels
+    ///
+    /// The offset is added to the volume set on the play command.
+    pub volume: Option<RangeInclusive<f32>>,
+    /// Range of the playback rate factor
+    ///
+    /// The playback rate set on the play command is multiplied by the factor.
+    pub playback_rate: Option<RangeInclusive<f64>>,
+    /// Range of the start position offset in seconds
+    ///
+    /// The offset is added to the start position set on the play command.
+    pub start_position: Option<RangeInclusive<f64>>,
+}
+
+/// One of the audio sources of a [`SoundEvent`]
+#[derive(Clone, Debug)]
+pub struct SoundEventVariant {
+    /// The audio source of this variant
+    pub source: Handle<AudioSource>,
+    /// Relative probability of this variant for random selections
+    pub weight: f32,
+}
+
+impl SoundEventVariant {
+    /// Create a variant with a weight of 1
+    pub fn new(source: Handle<AudioSource>) -> Self {
+        SoundEventVariant { source, weight: 1. }
+    }
+
+    /// Set the weight of this variant
+    pub fn with_weight(mut self, weight: f32) -> Self {
+        self.weight = weight;
+
+        self
+    }
+}
+
+/// How the variant of a [`SoundEvent`] is selected
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "settings_loader", derive(serde::Deserialize))]
+pub enum VariantSelection {
+    /// Pick a random variant based on the weights
+    #[default]
+    Random,
+    /// Pick a random variant based on the weights, but never the same variant twice in a row
+    RandomNoRepeat,
+    /// Play the variants in order
+    Sequential,
+    /// Play all variants in random order before repeating any of them
+    Shuffle,
+}
+
+impl SoundEvent {
+    /// Create a sound event with the given variants
+    pub fn new(variants: impl IntoIterator<Item = SoundEventVariant>) -> Self {
+        SoundEvent {
+            variants: variants.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Set how the variant to play is selected
+    pub fn with_selection(mut self, selection: VariantSelection) -> Self {
+        self.selection = selection;
+
+        self
+    }
+
+    /// Randomise the volume by adding an offset from the given range in decibels
+    pub fn with_volume(mut self, volume: RangeInclusive<f32>) -> Self {
+        self.volume = Some(volume);
+
+        self
+    }
+
+    /// Randomise the playback rate by multiplying it with a factor from the given range
+    pub fn with_playback_rate(mut self, playback_rate: RangeInclusive<f64>) -> Self {
+        self.playback_rate = Some(playback_rate);
+
+        self
+    }
+
+    /// Randomise the start position by adding an offset from the given range in seconds
+    pub fn with_start_position(mut self, start_position: RangeInclusive<f64>) -> Self {
+        self.start_position = Some(start_position);
+
+        self
+    }
+
+    /// Apply the random ranges of this event to the settings of a play command
+    pub(crate) fn randomise(&self, settings: &mut PartialSoundSettings, rng: &mut AudioRng) {
+        if let Some(volume) = &self.volume {
+            let base = settings.volume.map_or(0., |volume| volume.0);
+            settings.volume = Some((base + rng.range_f32(volume)).into());
+        }
+        if let Some(playback_rate) = &self.playback_rate {
+            let base = settings.playback_rate.unwrap_or(1.);
+            settings.playback_rate = Some(base * rng.range_f64(playback_rate));
+        }
+        if let Some(start_position) = &self.start_position {
+            let base = settings.start_position.unwrap_or(0.);
+            settings.start_position = Some(base + rng.range_f64(start_position));
+        }
+    }
+}
+
+/// Selection state of a [`SoundEvent`]
+#[derive(Default)]
+pub(crate) struct VariantSelector {
+    last: Option<usize>,
+    next: usize,
+    shuffled: Vec<usize>,
+}
+
+impl VariantSelector {
+    /// Select the next variant to play
+    ///
+    /// The event needs at least one variant.
+    pub(crate) fn select(&mut self, event: &SoundEvent, rng: &mut AudioRng) -> usize {
+        let len = event.variants.len();
+        let selected = match event.selection {
+            VariantSelection::Random => weighted_index(event, None, rng),
+            VariantSelection::RandomNoRepeat => {
+                weighted_index(event, self.last.filter(|_| len > 1), rng)
+            }
+            VariantSelection::Sequential => {
+                let selected = self.next % len;
+                self.next = selected + 1;
+                selected
+            }
+            VariantSelection::Shuffle => {
+                self.shuffled.retain(|&index| index < len);
+                if self.shuffled.is_empty() {
+                    self.shuffled = (0..len).collect();
+                    for index in (1..len).rev() {
+                        self.shuffled.swap(index, rng.index(index + 1));
+                    }
+                    // the next selection comes from the end; avoid repeating the last variant
+                    if len > 1 && self.shuffled.last() == self.last.as_ref() {
+                        self.shuffled.swap(0, len - 1);
+                    }
+                }
+                self.shuffled.pop().unwrap_or_default()
+            }
+        };
+        self.last = Some(selected);
+
+        selected
+    }
+}
+
+fn weighted_index(event: &SoundEvent, exclude: Option<usize>, rng: &mut AudioRng) -> usize {
+    let weight = |index: usize| {
+        if Some(index) == exclude {
+            0.
+        } else {
+            event.variants[index].weight.max(0.) as f64
+        }
+    };
+    let len = event.variants.len();
+    let total: f64 = (0..len).map(weight).sum();
+    if total <= 0. {
+        let candidates: Vec<usize> = (0..len).filter(|&index| Some(index) != exclude).collect();
+        return candidates
+            .get(rng.index(candidates.len()))
+            .copied()
+            .unwrap_or_default();
+    }
+    let mut target = rng.next_f64() * total;
+    for index in 0..len {
+        let weight = weight(index);
+        if weight > 0. && target < weight {
+            return index;
+        }
+        target -= weight;
+    }
+
+    (0..len)
+        .rev()
+        .find(|&index| weight(index) > 0.)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn event(len: usize, selection: VariantSelection) -> SoundEvent {
+        SoundEvent::new((0..len).map(|_| SoundEventVariant::new(Handle::default())))
+            .with_selection(selection)
+    }
+
+    #[test]
+    fn sequential_selection_cycles_through_variants() {
+        let event = event(3, VariantSelection::Sequential);
+        let mut selector = VariantSelector::default();
+        let mut rng = AudioRng::from_seed(1);
+
+        let selected: Vec<usize> = (0..5).map(|_| selector.select(&event, &mut rng)).collect();
+
+        assert_eq!(selected, vec![0, 1, 2, 0, 1]);
+    }
+
+    #[test]
+    fn random_no_repeat_never_repeats_variants() {
+        let event = event(2, VariantSelection::RandomNoRepeat);
+        let mut selector = VariantSelector::default();
+        let mut rng = AudioRng::from_seed(7);
+
+        let mut last = selector.select(&event, &mut rng);
+        for _ in 0..20 {
+            let selected = selector.select(&event, &mut rng);
+            assert_ne!(selected, last);
+            last = selected;
+        }
+    }
+
+    #[test]
+    fn shuffle_plays_every_variant_once_per_round() {
+        let event = event(4, VariantSelection::Shuffle);
+        let mut selector = VariantSelector::default();
+        let mut rng = AudioRng::from_seed(3);
+
+        for _ in 0..3 {
+            let mut round: Vec<usize> = (0..4).map(|_| selector.select(&event, &mut rng)).collect();
+            round.sort();
+            assert_eq!(round, vec![0, 1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn random_selection_respects_weights() {
+        let mut event = event(2, VariantSelection::Random);
+        event.variants[0].weight = 0.;
+        let mut selector = VariantSelector::default();
+        let mut rng = AudioRng::from_seed(11);
+
+        assert!((0..20).all(|_| selector.select(&event, &mut rng) == 1));
+    }
+
+    #[test]
+    fn randomises_play_settings_within_ranges() {
+        let event = event(1, VariantSelection::Random)
+            .with_volume(-6.0..=-3.0)
+            .with_playback_rate(0.5..=0.5);
+        let mut settings = PartialSoundSettings {
+            playback_rate: Some(2.),
+            ..Default::default()
+        };
+
+        event.randomise(&mut settings, &mut AudioRng::from_seed(5));
+
+        let volume = settings.volume.unwrap().0;
+        assert!((-6.0..=-3.0).contains(&volume));
+        assert_eq!(settings.playback_rate, Some(1.));
+        assert_eq!(settings.start_position, None);
+    }
+}
@@ -0,0 +1,47 @@
+use anyhow::Result;
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::reflect::TypePath;
+use kira::sound::FromFileError;
+use kira::sound::static_sound::StaticSoundData;
+use std::io::Cursor;
+use thiserror::Error;
+
+use crate::source::AudioSource;
+
+/// Asset loader for AIFF, AIFF-C, and CAF files.
+#[derive(Default, TypePath)]
+pub struct AiffLoader;
+
+/// Possible errors that can be produced by [`AiffLoader`]
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum AiffLoaderError {
+    /// An [IO Error](std::io::Error)
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+    /// An Error loading sound from a file. See [`FromFileError`]
+    #[error("Error while loading a sound: {0}")]
+    FileError(#[from] FromFileError),
+}
+
+impl AssetLoader for AiffLoader {
+    type Asset = AudioSource;
+    type Settings = ();
+    type Error = AiffLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut sound_bytes = vec![];
+        reader.read_to_end(&mut sound_bytes).await?;
+        let sound = StaticSoundData::from_cursor(Cursor::new(sound_bytes))?;
+        Ok(AudioSource::from(sound))
+    }
+    fn extensions(&self) -> &[&str] {
+        &["aif", "aiff", "aifc", "caf"]
+    }
+}
@@ -0,0 +1,292 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::reflect::TypePath;
+use kira::sound::FromFileError;
+use kira::sound::static_sound::StaticSoundData;
+use std::fmt;
+use std::io::Cursor;
+use thiserror::Error;
+
+use crate::source::AudioSource;
+use crate::source::loop_points::{ogg_loop_region, wav_loop_region};
+
+/// Asset loader detecting the audio format from the content of a file.
+///
+/// By default, it loads files with the `audio` extension. Register it again with
+/// [`AudioLoader::with_extensions`] for other extensions, for example those of an asset packer.
+/// Assets with any extension can also be loaded with it by naming it as loader in their
+/// `.meta` file. Loop points in the metadata of Ogg Vorbis and WAV files are read like in the
+/// loaders for these formats.
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_kira_audio::prelude::*;
+/// # let mut app = App::new();
+/// # app.add_plugins(AssetPlugin::default());
+/// app.register_asset_loader(AudioLoader::with_extensions(&["bin"]));
+/// ```
+#[derive(TypePath)]
+pub struct AudioLoader {
+    extensions: Vec<&'static str>,
+}
+
+impl Default for AudioLoader {
+    fn default() -> Self {
+        AudioLoader::with_extensions(&["audio"])
+    }
+}
+
+impl AudioLoader {
+    /// Create a loader for files with the given extensions
+    pub fn with_extensions(extensions: &[&'static str]) -> Self {
+        AudioLoader {
+            extensions: extensions.to_vec(),
+        }
+    }
+}
+
+/// Audio formats that can be detected from their content
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioFormat {
+    /// Ogg container with Vorbis audio (feature `ogg`)
+    Ogg,
+    /// MPEG audio layer III (feature `mp3`)
+    Mp3,
+    /// RIFF wave (feature `wav`)
+    Wav,
+    /// Free lossless audio codec (feature `flac`)
+    Flac,
+    /// Audio interchange file format, including AIFF-C (feature `aiff`)
+    Aiff,
+    /// Core audio format (feature `aiff`)
+    Caf,
+}
+
+impl AudioFormat {
+    /// All formats that can be decoded with the enabled features
+    pub const ENABLED: &'static [AudioFormat] = &[
+        #[cfg(feature = "ogg")]
+        AudioFormat::Ogg,
+        #[cfg(feature = "mp3")]
+        AudioFormat::Mp3,
+        #[cfg(feature = "wav")]
+        AudioFormat::Wav,
+        #[cfg(feature = "flac")]
+        AudioFormat::Flac,
+        #[cfg(feature = "aiff")]
+        AudioFormat::Aiff,
+        #[cfg(feature = "aiff")]
+        AudioFormat::Caf,
+    ];
+
+    /// Detect the format from the first bytes of a file
+    pub fn detect(bytes: &[u8]) -> Option<AudioFormat> {
+        match bytes {
+            [b'O', b'g', b'g', b'S', ..] => Some(AudioFormat::Ogg),
+            [b'f', b'L', b'a', b'C', ..] => Some(AudioFormat::Flac),
+            [b'c', b'a', b'f', b'f', ..] => Some(AudioFormat::Caf),
+            [
+                b'F',
+                b'O',
+                b'R',
+                b'M',
+                _,
+                _,
+                _,
+                _,
+                b'A',
+                b'I',
+                b'F',
+                b'F' | b'C',
+                ..,
+            ] => Some(AudioFormat::Aiff),
+            [
+                b'R',
+                b'I',
+                b'F',
+                b'F',
+                _,
+                _,
+                _,
+                _,
+                b'W',
+                b'A',
+                b'V',
+                b'E',
+                ..,
+            ]
+            | [
+                b'R',
+                b'F',
+                b'6',
+                b'4',
+                _,
+                _,
+                _,
+                _,
+                b'W',
+                b'A',
+                b'V',
+                b'E',
+                ..,
+            ] => Some(AudioFormat::Wav),
+            [b'I', b'D', b'3', _, _, _, size @ ..] if size.len() >= 4 => {
+                // ID3 tags are mostly used for MP3, but can also precede FLAC
+                let size = size[..4]
+                    .iter()
+                    .fold(0usize, |size, byte| (size << 7) | (*byte & 0x7f) as usize);
+                match bytes.get(10 + size..).and_then(AudioFormat::detect) {
+                    Some(AudioFormat::Flac) => Some(AudioFormat::Flac),
+                    _ => Some(AudioFormat::Mp3),
+                }
+            }
+            // MPEG frame sync with a layer other than the reserved one (which AAC uses)
+            [0xff, second, ..] if second & 0xe0 == 0xe0 && second & 0x06 != 0 => {
+                Some(AudioFormat::Mp3)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether this format can be decoded with the enabled features
+    pub fn is_enabled(self) -> bool {
+        AudioFormat::ENABLED.contains(&self)
+    }
+}
+
+/// List of formats for error messages
+#[derive(Debug)]
+pub struct AudioFormats(pub &'static [AudioFormat]);
+
+impl fmt::Display for AudioFormats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "none");
+        }
+        let names: Vec<_> = self.0.iter().map(|format| format!("{format:?}")).collect();
+
+        write!(f, "{}", names.join(", "))
+    }
+}
+
+/// Possible errors that can be produced by [`AudioLoader`]
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum AudioLoaderError {
+    /// An [IO Error](std::io::Error)
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+    /// The content does not match any known audio format
+    #[error("Could not detect the audio format; enabled formats: {enabled}")]
+    UnknownFormat {
+        /// Formats that can be decoded with the enabled features
+        enabled: AudioFormats,
+    },
+    /// The detected format is not enabled
+    #[error("The detected format {format:?} is not enabled; enabled formats: {enabled}")]
+    FormatNotEnabled {
+        /// The detected format
+        format: AudioFormat,
+        /// Formats that can be decoded with the enabled features
+        enabled: AudioFormats,
+    },
+    /// An Error loading sound from a file. See [`FromFileError`]
+    #[error("Error while loading a sound: {0}")]
+    FileError(#[from] FromFileError),
+}
+
+impl AssetLoader for AudioLoader {
+    type Asset = AudioSource;
+    type Settings = ();
+    type Error = AudioLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut sound_bytes = vec![];
+        reader.read_to_end(&mut sound_bytes).await?;
+        let enabled = AudioFormats(AudioFormat::ENABLED);
+        let format = AudioFormat::detect(&sound_bytes);
+        match format {
+            None => return Err(AudioLoaderError::UnknownFormat { enabled }),
+            Some(format) if !format.is_enabled() => {
+                return Err(AudioLoaderError::FormatNotEnabled { format, enabled });
+            }
+            Some(_) => (),
+        }
+        let loop_region = match format {
+            Some(AudioFormat::Ogg) => ogg_loop_region(&sound_bytes),
+            Some(AudioFormat::Wav) => wav_loop_region(&sound_bytes),
+            _ => None,
+        };
+        let sound = StaticSoundData::from_cursor(Cursor::new(sound_bytes))?;
+
+        Ok(AudioSource { sound, loop_region })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &self.extensions
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_formats_by_magic_bytes() {
+        assert_eq!(
+            AudioFormat::detect(b"OggS\0\x02rest"),
+            Some(AudioFormat::Ogg)
+        );
+        assert_eq!(
+            AudioFormat::detect(b"fLaC\0\0\0\x22"),
+            Some(AudioFormat::Flac)
+        );
+        assert_eq!(
+            AudioFormat::detect(b"RIFF\x24\0\0\0WAVEfmt "),
+            Some(AudioFormat::Wav)
+        );
+        assert_eq!(
+            AudioFormat::detect(&[0xff, 0xfb, 0x90, 0x00]),
+            Some(AudioFormat::Mp3)
+        );
+        assert_eq!(AudioFormat::detect(&[0xff, 0xf1, 0x50, 0x80]), None);
+        assert_eq!(
+            AudioFormat::detect(b"FORM\0\0\0\x2eAIFCFVER"),
+            Some(AudioFormat::Aiff)
+        );
+        assert_eq!(
+            AudioFormat::detect(b"caff\0\x01\0\0"),
+            Some(AudioFormat::Caf)
+        );
+        assert_eq!(AudioFormat::detect(b"not audio"), None);
+    }
+
+    #[test]
+    fn looks_behind_id3_tags() {
+        let mut flac = b"ID3\x04\0\0\0\0\0\x02\0\0".to_vec();
+        flac.extend_from_slice(b"fLaC");
+        assert_eq!(AudioFormat::detect(&flac), Some(AudioFormat::Flac));
+
+        let mut mp3 = b"ID3\x04\0\0\0\0\0\x02\0\0".to_vec();
+        mp3.extend_from_slice(&[0xff, 0xfb]);
+        assert_eq!(AudioFormat::detect(&mp3), Some(AudioFormat::Mp3));
+    }
+
+    #[test]
+    fn lists_formats_in_errors() {
+        let error = AudioLoaderError::UnknownFormat {
+            enabled: AudioFormats(&[AudioFormat::Ogg, AudioFormat::Wav]),
+        };
+
+        assert_eq!(
+            error.to_string(),
+            "Could not detect the audio format; enabled formats: Ogg, Wav"
+        );
+    }
+}
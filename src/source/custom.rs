@@ -0,0 +1,506 @@
+//! Play any Kira [`SoundData`] through audio channels
+
+use bevy::asset::Asset;
+use bevy::reflect::TypePath;
+use kira::info::Info;
+use kira::sound::{PlaybackState as KiraPlaybackState, Sound, SoundData};
+use kira::{Decibels, Easing, Frame, Panning, PlaybackRate, Tween, Value};
+use parking_lot::Mutex;
+use std::any::Any;
+use std::convert::Infallible;
+use std::f32::consts::{FRAC_PI_4, SQRT_2};
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+
+/// Handle of the sound data played by a custom source
+pub(crate) type CustomDataHandle = Box<dyn Any + Send + Sync>;
+
+type SoundFactory =
+    dyn Fn(bool) -> Result<(Box<dyn Sound>, CustomDataHandle), String> + Send + Sync;
+
+/// A source of audio that is not backed by static samples
+///
+/// Wraps a factory for any Kira [`SoundData`], for example a synthesiser or a buffer fed over
+/// the network. A new sound is created from the factory every time the source is played with
+/// [`AudioControl::play_custom`](crate::AudioControl::play_custom).
+///
+/// The played [`AudioInstance`](crate::AudioInstance) supports pausing, resuming, stopping, and
+/// changing the volume and panning like instances of an [`AudioSource`](crate::AudioSource).
+/// The playback rate scales the time step passed to the sound, so it only changes sounds that
+/// advance based on that time step. Seeking, reversing, start positions, and modulators are not
+/// supported, and looping is only supported by sources created with
+/// [`CustomAudioSource::loopable`]. Plays using unsupported settings log a warning. The handle of
+/// the played [`SoundData`] is available through
+/// [`AudioInstance::custom_handle`](crate::AudioInstance::custom_handle).
+#[derive(Clone, Asset, TypePath)]
+pub struct CustomAudioSource {
+    factory: Arc<SoundFactory>,
+    loopable: bool,
+}
+
+impl CustomAudioSource {
+    /// Create a source from a function building the sound data
+    pub fn new<D>(factory: impl Fn() -> D + Send + Sync + 'static) -> Self
+    where
+        D: SoundData + 'static,
+        D::Error: Debug,
+        D::Handle: Send + Sync + 'static,
+    {
+        CustomAudioSource {
+            loopable: false,
+            ..CustomAudioSource::loopable(move |_| factory())
+        }
+    }
+
+    /// Create a source from a function building the sound data, which can loop on its own
+    ///
+    /// The function receives whether the sound was played with
+    /// [`PlayAudioCommand::looped`](crate::PlayAudioCommand::looped).
+    pub fn loopable<D>(factory: impl Fn(bool) -> D + Send + Sync + 'static) -> Self
+    where
+        D: SoundData + 'static,
+        D::Error: Debug,
+        D::Handle: Send + Sync + 'static,
+    {
+        CustomAudioSource {
+            factory: Arc::new(move |looped| {
+                factory(looped)
+                    .into_sound()
+                    .map(|(sound, handle)| (sound, Box::new(handle) as CustomDataHandle))
+                    .map_err(|error| format!("{error:?}"))
+            }),
+            loopable: true,
+        }
+    }
+
+    /// Whether the sound data is told if it should loop
+    pub fn is_loopable(&self) -> bool {
+        self.loopable
+    }
+
+    /// Build a new sound with the common controls of audio instances
+    pub(crate) fn sound(
+        &self,
+        settings: CustomSoundSettings,
+    ) -> Result<(ControlledSoundData, CustomSoundHandle, CustomDataHandle), String> {
+        let (sound, custom_handle) = (self.factory)(settings.looped)?;
+        let control = Arc::new(SoundControl::default());
+        let data = ControlledSoundData {
+            sound: ControlledSound::new(sound, settings, control.clone()),
+        };
+
+        Ok((data, CustomSoundHandle { control }, custom_handle))
+    }
+}
+
+/// Initial settings of a custom sound
+pub(crate) struct CustomSoundSettings {
+    pub(crate) volume: Decibels,
+    pub(crate) panning: Panning,
+    pub(crate) playback_rate: f64,
+    pub(crate) paused: bool,
+    pub(crate) fade_in: Option<Tween>,
+    pub(crate) looped: bool,
+}
+
+pub(crate) struct ControlledSoundData {
+    sound: ControlledSound,
+}
+
+impl SoundData for ControlledSoundData {
+    type Error = Infallible;
+    type Handle = ();
+
+    fn into_sound(self) -> Result<(Box<dyn Sound>, Self::Handle), Self::Error> {
+        Ok((Box::new(self.sound), ()))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum ControlCommand {
+    SetVolume(Decibels, Tween),
+    SetPanning(Panning, Tween),
+    SetPlaybackRate(f64, Tween),
+    Pause(Tween),
+    Resume(Tween),
+    Stop(Tween),
+}
+
+/// State shared between a custom sound and its handle
+#[derive(Default)]
+struct SoundControl {
+    commands: Mutex<Vec<ControlCommand>>,
+    state: AtomicU8,
+    position: AtomicU64,
+}
+
+/// Controls a playing custom sound
+pub(crate) struct CustomSoundHandle {
+    control: Arc<SoundControl>,
+}
+
+impl CustomSoundHandle {
+    fn command(&mut self, command: ControlCommand) {
+        self.control.commands.lock().push(command);
+    }
+
+    pub(crate) fn state(&self) -> KiraPlaybackState {
+        state_from_u8(self.control.state.load(Ordering::Acquire))
+    }
+
+    pub(crate) fn position(&self) -> f64 {
+        f64::from_bits(self.control.position.load(Ordering::Acquire))
+    }
+
+    pub(crate) fn pause(&mut self, tween: Tween) {
+        self.command(ControlCommand::Pause(tween));
+    }
+
+    pub(crate) fn resume(&mut self, tween: Tween) {
+        self.command(ControlCommand::Resume(tween));
+    }
+
+    pub(crate) fn stop(&mut self, tween: Tween) {
+        self.command(ControlCommand::Stop(tween));
+    }
+
+    /// Values mapped from modulators are ignored
+    pub(crate) fn set_volume(&mut self, volume: impl Into<Value<Decibels>>, tween: Tween) {
+        if let Value::Fixed(volume) = volume.into() {
+            self.command(ControlCommand::SetVolume(volume, tween));
+        }
+    }
+
+    /// Values mapped from modulators are ignored
+    pub(crate) fn set_panning(&mut self, panning: impl Into<Value<Panning>>, tween: Tween) {
+        if let Value::Fixed(panning) = panning.into() {
+            self.command(ControlCommand::SetPanning(panning, tween));
+        }
+    }
+
+    /// Values mapped from modulators are ignored
+    pub(crate) fn set_playback_rate(
+        &mut self,
+        playback_rate: impl Into<Value<PlaybackRate>>,
+        tween: Tween,
+    ) {
+        if let Value::Fixed(PlaybackRate(playback_rate)) = playback_rate.into() {
+            self.command(ControlCommand::SetPlaybackRate(playback_rate, tween));
+        }
+    }
+}
+
+fn state_to_u8(state: KiraPlaybackState) -> u8 {
+    match state {
+        KiraPlaybackState::Playing => 0,
+        KiraPlaybackState::Pausing => 1,
+        KiraPlaybackState::Paused => 2,
+        KiraPlaybackState::WaitingToResume => 3,
+        KiraPlaybackState::Resuming => 4,
+        KiraPlaybackState::Stopping => 5,
+        KiraPlaybackState::Stopped => 6,
+    }
+}
+
+fn state_from_u8(state: u8) -> KiraPlaybackState {
+    match state {
+        0 => KiraPlaybackState::Playing,
+        1 => KiraPlaybackState::Pausing,
+        2 => KiraPlaybackState::Paused,
+        3 => KiraPlaybackState::WaitingToResume,
+        4 => KiraPlaybackState::Resuming,
+        5 => KiraPlaybackState::Stopping,
+        _ => KiraPlaybackState::Stopped,
+    }
+}
+
+/// A value that is tweened on the audio thread
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Tweened {
+    start: f64,
+    target: f64,
+    duration: f64,
+    elapsed: f64,
+    easing: Easing,
+}
+
+impl Tweened {
+    pub(crate) fn new(value: f64) -> Self {
+        Tweened {
+            start: value,
+            target: value,
+            duration: 0.,
+            elapsed: 0.,
+            easing: Easing::Linear,
+        }
+    }
+
+    pub(crate) fn value(&self) -> f64 {
+        if self.elapsed >= self.duration {
+            return self.target;
+        }
+        let progress = ease(self.easing, self.elapsed / self.duration);
+
+        self.start + (self.target - self.start) * progress
+    }
+
+    pub(crate) fn set(&mut self, target: f64, tween: Tween) {
+        self.start = self.value();
+        self.target = target;
+        self.duration = tween.duration.as_secs_f64();
+        self.elapsed = 0.;
+        self.easing = tween.easing;
+    }
+
+    pub(crate) fn update(&mut self, dt: f64) -> f64 {
+        self.elapsed += dt;
+
+        self.value()
+    }
+
+    pub(crate) fn finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+/// Wraps a sound to add the controls shared by all audio instances
+pub(crate) struct ControlledSound {
+    sound: Box<dyn Sound>,
+    control: Arc<SoundControl>,
+    state: KiraPlaybackState,
+    volume: Tweened,
+    panning: Tweened,
+    playback_rate: Tweened,
+    /// Factor used for fading in and out when playing, pausing, and stopping
+    fade: Tweened,
+    position: f64,
+}
+
+impl ControlledSound {
+    fn new(
+        sound: Box<dyn Sound>,
+        settings: CustomSoundSettings,
+        control: Arc<SoundControl>,
+    ) -> Self {
+        let mut fade = Tweened::new(1.);
+        if let Some(fade_in) = settings.fade_in {
+            fade = Tweened::new(0.);
+            fade.set(1., fade_in);
+        }
+        let state = if settings.paused {
+            KiraPlaybackState::Paused
+        } else {
+            KiraPlaybackState::Playing
+        };
+        control.state.store(state_to_u8(state), Ordering::Release);
+        ControlledSound {
+            sound,
+            control,
+            state,
+            volume: Tweened::new(settings.volume.0 as f64),
+            panning: Tweened::new(settings.panning.0 as f64),
+            playback_rate: Tweened::new(settings.playback_rate),
+            fade,
+            position: 0.,
+        }
+    }
+
+    fn run_commands(&mut self) {
+        // never block the audio thread; remaining commands are picked up in the next batch
+        let Some(mut commands) = self.control.commands.try_lock() else {
+            return;
+        };
+        for command in commands.drain(..) {
+            match command {
+                ControlCommand::SetVolume(volume, tween) => self.volume.set(volume.0 as f64, tween),
+                ControlCommand::SetPanning(panning, tween) => {
+                    self.panning.set(panning.0 as f64, tween)
+                }
+                ControlCommand::SetPlaybackRate(playback_rate, tween) => {
+                    self.playback_rate.set(playback_rate, tween)
+                }
+                ControlCommand::Pause(tween) => {
+                    if matches!(
+                        self.state,
+                        KiraPlaybackState::Playing | KiraPlaybackState::Resuming
+                    ) {
+                        self.state = KiraPlaybackState::Pausing;
+                        self.fade.set(0., tween);
+                    }
+                }
+                ControlCommand::Resume(tween) => {
+                    if matches!(
+                        self.state,
+                        KiraPlaybackState::Paused | KiraPlaybackState::Pausing
+                    ) {
+                        self.state = KiraPlaybackState::Resuming;
+                        self.fade.set(1., tween);
+                    }
+                }
+                ControlCommand::Stop(tween) => {
+                    if self.state != KiraPlaybackState::Stopped {
+                        self.state = KiraPlaybackState::Stopping;
+                        self.fade.set(0., tween);
+                    }
+                }
+            }
+        }
+    }
+
+    fn update_state(&mut self) {
+        if !self.fade.finished() {
+            return;
+        }
+        self.state = match self.state {
+            KiraPlaybackState::Pausing => KiraPlaybackState::Paused,
+            KiraPlaybackState::Resuming => KiraPlaybackState::Playing,
+            KiraPlaybackState::Stopping => KiraPlaybackState::Stopped,
+            state => state,
+        };
+    }
+}
+
+impl Sound for ControlledSound {
+    fn on_start_processing(&mut self) {
+        self.sound.on_start_processing();
+        self.run_commands();
+    }
+
+    fn process(&mut self, out: &mut [Frame], dt: f64, info: &Info) {
+        if matches!(
+            self.state,
+            KiraPlaybackState::Paused | KiraPlaybackState::Stopped
+        ) {
+            out.fill(Frame::ZERO);
+        } else {
+            let playback_rate = self.playback_rate.update(dt * out.len() as f64).max(0.);
+            self.sound.process(out, dt * playback_rate, info);
+            self.position += dt * playback_rate * out.len() as f64;
+            for frame in out.iter_mut() {
+                let volume = self.volume.update(dt) as f32;
+                let amplitude = self.fade.update(dt) as f32 * decibels_to_amplitude(volume);
+                *frame = pan(*frame * amplitude, self.panning.update(dt) as f32);
+            }
+            self.update_state();
+            if self.sound.finished() {
+                self.state = KiraPlaybackState::Stopped;
+            }
+        }
+        self.control
+            .state
+            .store(state_to_u8(self.state), Ordering::Release);
+        self.control
+            .position
+            .store(self.position.to_bits(), Ordering::Release);
+    }
+
+    fn finished(&self) -> bool {
+        self.state == KiraPlaybackState::Stopped
+    }
+}
+
+/// Progress along an easing curve, matching Kira's own tweens
+fn ease(easing: Easing, x: f64) -> f64 {
+    match easing {
+        Easing::Linear => x,
+        Easing::InPowi(power) => x.powi(power),
+        Easing::OutPowi(power) => 1. - (1. - x).powi(power),
+        Easing::InOutPowi(power) if x < 0.5 => 0.5 * (2. * x).powi(power),
+        Easing::InOutPowi(power) => 1. - 0.5 * (2. - 2. * x).powi(power),
+        Easing::InPowf(power) => x.powf(power),
+        Easing::OutPowf(power) => 1. - (1. - x).powf(power),
+        Easing::InOutPowf(power) if x < 0.5 => 0.5 * (2. * x).powf(power),
+        Easing::InOutPowf(power) => 1. - 0.5 * (2. - 2. * x).powf(power),
+    }
+}
+
+fn decibels_to_amplitude(volume: f32) -> f32 {
+    if volume <= Decibels::SILENCE.0 {
+        return 0.;
+    }
+
+    10f32.powf(volume / 20.)
+}
+
+/// Constant power panning that keeps centered frames unchanged
+fn pan(frame: Frame, panning: f32) -> Frame {
+    let angle = (panning.clamp(-1., 1.) + 1.) * FRAC_PI_4;
+
+    Frame {
+        left: frame.left * angle.cos() * SQRT_2,
+        right: frame.right * angle.sin() * SQRT_2,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn loopable_sources_receive_the_looped_flag() {
+        let received = Arc::new(AtomicU8::new(0));
+        let source = CustomAudioSource::loopable({
+            let received = received.clone();
+            move |looped| {
+                received.store(looped as u8 + 1, Ordering::Release);
+                crate::Synth::new(440.)
+            }
+        });
+        let settings = CustomSoundSettings {
+            volume: Decibels::IDENTITY,
+            panning: Panning::CENTER,
+            playback_rate: 1.,
+            paused: false,
+            fade_in: None,
+            looped: true,
+        };
+
+        assert!(source.is_loopable());
+        assert!(source.sound(settings).is_ok());
+        assert_eq!(received.load(Ordering::Acquire), 2);
+        assert!(!CustomAudioSource::new(|| crate::Synth::new(440.)).is_loopable());
+    }
+
+    #[test]
+    fn tweens_reach_their_target() {
+        let mut value = Tweened::new(0.);
+        value.set(
+            1.,
+            Tween {
+                duration: Duration::from_secs(1),
+                ..Default::default()
+            },
+        );
+
+        assert!((value.update(0.5) - 0.5).abs() < 0.0001);
+        assert!(!value.finished());
+        assert_eq!(value.update(1.), 1.);
+        assert!(value.finished());
+    }
+
+    #[test]
+    fn centered_panning_keeps_frames_unchanged() {
+        let frame = pan(Frame::from_mono(0.5), 0.);
+
+        assert!((frame.left - 0.5).abs() < 0.0001);
+        assert!((frame.right - 0.5).abs() < 0.0001);
+        assert!(pan(Frame::from_mono(0.5), 1.).left.abs() < 0.0001);
+    }
+
+    #[test]
+    fn converts_playback_states() {
+        for state in [
+            KiraPlaybackState::Playing,
+            KiraPlaybackState::Pausing,
+            KiraPlaybackState::Paused,
+            KiraPlaybackState::WaitingToResume,
+            KiraPlaybackState::Resuming,
+            KiraPlaybackState::Stopping,
+            KiraPlaybackState::Stopped,
+        ] {
+            assert_eq!(state_from_u8(state_to_u8(state)), state);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! Loop points from the metadata of audio files
+
+use kira::sound::{EndPosition, PlaybackPosition, Region};
+
+/// Loop start and optional loop end in sample frames
+type LoopPoints = (usize, Option<usize>);
+
+/// Loop region from the `LOOPSTART` and `LOOPLENGTH` or `LOOPEND` comments of an Ogg Vorbis file
+pub(crate) fn ogg_loop_region(bytes: &[u8]) -> Option<Region> {
+    ogg_loop_points(bytes).map(region)
+}
+
+/// Loop region from the first loop of the `smpl` chunk of a WAV file
+pub(crate) fn wav_loop_region(bytes: &[u8]) -> Option<Region> {
+    wav_loop_points(bytes).map(region)
+}
+
+fn region((start, end): LoopPoints) -> Region {
+    Region {
+        start: PlaybackPosition::Samples(start),
+        end: end.map_or(EndPosition::EndOfAudio, |end| {
+            EndPosition::Custom(PlaybackPosition::Samples(end))
+        }),
+    }
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset.checked_add(4)?)?.try_into().ok()?,
+    ))
+}
+
+fn ogg_loop_points(bytes: &[u8]) -> Option<LoopPoints> {
+    let comments = vorbis_comment_packet(bytes)?;
+    let comments = comments.strip_prefix(b"\x03vorbis")?;
+    let vendor_length = u32_at(comments, 0)? as usize;
+    let count = u32_at(comments, vendor_length.checked_add(4)?)?;
+    let mut offset = vendor_length.checked_add(8)?;
+    let mut start = None;
+    let mut length = None;
+    let mut end = None;
+    for _ in 0..count {
+        let comment_length = u32_at(comments, offset)? as usize;
+        let comment_start = offset.checked_add(4)?;
+        let comment_end = comment_start.checked_add(comment_length)?;
+        let comment = comments.get(comment_start..comment_end)?;
+        offset = comment_end;
+        let Some((key, value)) = std::str::from_utf8(comment)
+            .ok()
+            .and_then(|comment| comment.split_once('='))
+        else {
+            continue;
+        };
+        let value = value.trim().parse::<usize>().ok();
+        // keys of Vorbis comments are case-insensitive
+        match key.to_ascii_uppercase().as_str() {
+            "LOOPSTART" => start = value,
+            "LOOPLENGTH" => length = value,
+            "LOOPEND" => end = value,
+            _ => (),
+        }
+    }
+    let start = start?;
+    let end = match (length, end) {
+        (Some(length), _) if length > 0 => Some(start.checked_add(length)?),
+        (_, Some(end)) if end > start => Some(end),
+        _ => None,
+    };
+
+    Some((start, end))
+}
+
+/// The second packet of the first logical stream, which holds the comments in Vorbis streams
+fn vorbis_comment_packet(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut serial = None;
+    let mut packet_index = 0;
+    let mut packet = vec![];
+    let mut offset = 0;
+    loop {
+        let page = bytes.get(offset..)?;
+        if !page.starts_with(b"OggS") {
+            return None;
+        }
+        let segment_count = *page.get(26)? as usize;
+        let segments = page.get(27..27 + segment_count)?;
+        let page_serial = u32_at(page, 14)?;
+        let mut data_offset = 27 + segment_count;
+        let page_length = data_offset + segments.iter().map(|size| *size as usize).sum::<usize>();
+        offset += page_length;
+        if *serial.get_or_insert(page_serial) != page_serial {
+            continue;
+        }
+        for size in segments {
+            let size = *size as usize;
+            if packet_index == 1 {
+                packet.extend_from_slice(page.get(data_offset..data_offset + size)?);
+            }
+            data_offset += size;
+            // a segment shorter than 255 bytes ends the packet
+            if size < 255 {
+                if packet_index == 1 {
+                    return Some(packet);
+                }
+                packet_index += 1;
+            }
+        }
+    }
+}
+
+fn wav_loop_points(bytes: &[u8]) -> Option<LoopPoints> {
+    if bytes.get(0..4)? != b"RIFF" || bytes.get(8..12)? != b"WAVE" {
+        return None;
+    }
+    let mut offset: usize = 12;
+    while let Some(id) = bytes.get(offset..offset.checked_add(4)?) {
+        let size = u32_at(bytes, offset + 4)? as usize;
+        let data_start = offset.checked_add(8)?;
+        let data_end = data_start.checked_add(size)?;
+        if id == b"smpl" {
+            let chunk = bytes.get(data_start..data_end)?;
+            if u32_at(chunk, 28)? == 0 {
+                return None;
+            }
+            // the end of the first loop is inclusive
+            let start = u32_at(chunk, 44)? as usize;
+            let end = (u32_at(chunk, 48)? as usize).checked_add(1)?;
+            return (end > start).then_some((start, Some(end)));
+        }
+        // chunks are padded to an even size
+        offset = data_end.checked_add(size % 2)?;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ogg_page(sequence: u32, segments: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut page = b"OggS\0\0".to_vec();
+        page.extend_from_slice(&[0; 8]);
+        page.extend_from_slice(&7u32.to_le_bytes());
+        page.extend_from_slice(&sequence.to_le_bytes());
+        page.extend_from_slice(&[0; 4]);
+        page.push(segments.len() as u8);
+        page.extend_from_slice(segments);
+        page.extend_from_slice(data);
+
+        page
+    }
+
+    fn vorbis(comments: &[&str]) -> Vec<u8> {
+        let mut packet = b"\x03vorbis".to_vec();
+        // a long vendor string splits the packet across pages
+        let vendor = "v".repeat(300);
+        packet.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        packet.extend_from_slice(vendor.as_bytes());
+        packet.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            packet.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            packet.extend_from_slice(comment.as_bytes());
+        }
+        let (first, second) = packet.split_at(255);
+
+        let mut bytes = ogg_page(0, &[30], &[1; 30]);
+        bytes.extend(ogg_page(1, &[255], first));
+        bytes.extend(ogg_page(2, &[second.len() as u8], second));
+
+        bytes
+    }
+
+    #[test]
+    fn reads_vorbis_loop_comments() {
+        let bytes = vorbis(&["TITLE=Theme", "LOOPSTART=44100", "LOOPLENGTH=88200"]);
+        assert_eq!(ogg_loop_points(&bytes), Some((44100, Some(132300))));
+
+        let bytes = vorbis(&["loopstart=100", "LoopEnd=400"]);
+        assert_eq!(ogg_loop_points(&bytes), Some((100, Some(400))));
+
+        let bytes = vorbis(&["LOOPSTART=100"]);
+        assert_eq!(ogg_loop_points(&bytes), Some((100, None)));
+
+        let bytes = vorbis(&["LOOPLENGTH=100"]);
+        assert_eq!(ogg_loop_points(&bytes), None);
+    }
+
+    #[test]
+    fn reads_the_first_wav_sample_loop() {
+        let mut smpl = vec![0; 36];
+        smpl[28] = 1;
+        // cue id, type, start, inclusive end, fraction, and play count
+        for value in [0u32, 0, 1000, 1999, 0, 0] {
+            smpl.extend_from_slice(&value.to_le_bytes());
+        }
+        let mut bytes = b"RIFF\0\0\0\0WAVEfmt \x03\0\0\0abc\0smpl".to_vec();
+        bytes.extend_from_slice(&(smpl.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&smpl);
+
+        assert_eq!(wav_loop_points(&bytes), Some((1000, Some(2000))));
+        assert_eq!(wav_loop_points(&bytes[..40]), None);
+    }
+
+    #[test]
+    fn ignores_loop_points_out_of_range() {
+        let bytes = vorbis(&[&format!("LOOPSTART={}", usize::MAX), "LOOPLENGTH=1"]);
+        assert_eq!(ogg_loop_points(&bytes), None);
+
+        let mut bytes = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(b"smpl\0\0\0\0");
+        assert_eq!(wav_loop_points(&bytes), None);
+    }
+}
@@ -0,0 +1,1036 @@
+//! Playback of MIDI sequences with SoundFont instruments
+
+use crate::source::custom::CustomAudioSource;
+use crate::source::midi_loader::MidiLoaderError;
+use crate::source::soundfont::{LoopMode, SoundFont, SoundFontData, Zone};
+use bevy::asset::Asset;
+use bevy::reflect::TypePath;
+use kira::Frame;
+use kira::info::Info;
+use kira::sound::{Sound, SoundData};
+use parking_lot::Mutex;
+use std::convert::Infallible;
+use std::f32::consts::FRAC_PI_4;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Voices playing at the same time; the oldest voice is stopped to play more
+const MAX_VOICES: usize = 64;
+/// MIDI channel 10 plays percussion from bank 128 and is not transposed
+const PERCUSSION_CHANNEL: u8 = 9;
+const PERCUSSION_BANK: u16 = 128;
+/// Default tempo of 120 beats per minute
+const DEFAULT_TEMPO: u32 = 500_000;
+
+/// A MIDI channel message
+///
+/// Channels range from 0 to 15, keys, velocities, and values from 0 to 127.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MidiEvent {
+    /// Start playing a note
+    NoteOn {
+        /// MIDI channel
+        channel: u8,
+        /// Key of the note (60 is the middle C)
+        key: u8,
+        /// Velocity of the note
+        velocity: u8,
+    },
+    /// Release a note
+    NoteOff {
+        /// MIDI channel
+        channel: u8,
+        /// Key of the note (60 is the middle C)
+        key: u8,
+    },
+    /// Change a controller
+    ///
+    /// Bank select (0), volume (7), pan (10), expression (11), sustain pedal (64),
+    /// all sound off (120), reset all controllers (121), and all notes off (123) are supported.
+    ControlChange {
+        /// MIDI channel
+        channel: u8,
+        /// Number of the controller
+        controller: u8,
+        /// New value of the controller
+        value: u8,
+    },
+    /// Select the instrument of a channel
+    ProgramChange {
+        /// MIDI channel
+        channel: u8,
+        /// Program number in the selected bank
+        program: u8,
+    },
+    /// Bend the pitch of a channel by up to two semitones
+    PitchBend {
+        /// MIDI channel
+        channel: u8,
+        /// From 0 to 16383 with 8192 as center
+        value: u16,
+    },
+}
+
+impl MidiEvent {
+    fn channel(&self) -> u8 {
+        match *self {
+            MidiEvent::NoteOn { channel, .. }
+            | MidiEvent::NoteOff { channel, .. }
+            | MidiEvent::ControlChange { channel, .. }
+            | MidiEvent::ProgramChange { channel, .. }
+            | MidiEvent::PitchBend { channel, .. } => channel,
+        }
+    }
+}
+
+/// An event at a time in seconds from the start of the sequence
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct TimedEvent {
+    time: f64,
+    event: MidiEvent,
+}
+
+/// A sequence of MIDI events loaded from a standard MIDI file (`.mid`)
+///
+/// Play it with the instruments of a [`SoundFont`] through a [`MidiPlayer`].
+#[derive(Clone, Asset, TypePath)]
+pub struct MidiSequence {
+    events: Arc<[TimedEvent]>,
+    duration: f64,
+}
+
+impl MidiSequence {
+    /// Duration of the sequence at its original tempo
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.duration)
+    }
+
+    /// Parse a standard MIDI file of format 0 or 1
+    pub(crate) fn parse(bytes: &[u8]) -> Result<MidiSequence, MidiLoaderError> {
+        let truncated = || MidiLoaderError::InvalidFile("the file is truncated");
+        let overflow = || MidiLoaderError::InvalidFile("chunk size out of range");
+        if !bytes.starts_with(b"MThd") {
+            return Err(MidiLoaderError::InvalidFile("missing the MThd header"));
+        }
+        let header = bytes.get(8..14).ok_or_else(truncated)?;
+        let format = u16::from_be_bytes([header[0], header[1]]);
+        let track_count = u16::from_be_bytes([header[2], header[3]]) as usize;
+        let division = u16::from_be_bytes([header[4], header[5]]);
+        if format > 1 {
+            return Err(MidiLoaderError::UnsupportedFormat(format!(
+                "MIDI file format {format}"
+            )));
+        }
+        if division == 0 {
+            return Err(MidiLoaderError::InvalidFile("zero ticks per quarter note"));
+        }
+
+        // (tick, track, index in track) keeps the order of simultaneous events stable
+        let mut events: Vec<(u64, usize, usize, TrackEvent)> = vec![];
+        let mut offset = (u32::from_be_bytes(bytes[4..8].try_into().unwrap()) as usize)
+            .checked_add(8)
+            .ok_or_else(overflow)?;
+        let mut track = 0;
+        while track < track_count {
+            let start = offset.checked_add(8).ok_or_else(overflow)?;
+            let header = bytes.get(offset..start).ok_or_else(truncated)?;
+            let length = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+            let end = start.checked_add(length).ok_or_else(overflow)?;
+            let data = bytes.get(start..end).ok_or_else(truncated)?;
+            offset = end;
+            // unknown chunks are skipped
+            if &header[0..4] != b"MTrk" {
+                continue;
+            }
+            for (index, (tick, event)) in parse_track(data)?.into_iter().enumerate() {
+                events.push((tick, track, index, event));
+            }
+            track += 1;
+        }
+        events.sort_by_key(|(tick, track, index, _)| (*tick, *track, *index));
+
+        let mut sequence = vec![];
+        let mut tempo = DEFAULT_TEMPO;
+        let mut last_tick = 0;
+        let mut time = 0.;
+        for (tick, _, _, event) in events {
+            time += (tick - last_tick) as f64 * seconds_per_tick(division, tempo);
+            last_tick = tick;
+            match event {
+                TrackEvent::Tempo(new_tempo) => tempo = new_tempo,
+                TrackEvent::Channel(event) => sequence.push(TimedEvent { time, event }),
+                TrackEvent::EndOfTrack => (),
+            }
+        }
+
+        Ok(MidiSequence {
+            events: sequence.into(),
+            duration: time,
+        })
+    }
+}
+
+fn seconds_per_tick(division: u16, tempo: u32) -> f64 {
+    if division & 0x8000 != 0 {
+        // SMPTE frames per second and ticks per frame
+        let frames = -((division >> 8) as u8 as i8) as f64;
+        1. / (frames * (division & 0xff) as f64).max(1.)
+    } else {
+        tempo as f64 / 1_000_000. / division as f64
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum TrackEvent {
+    Channel(MidiEvent),
+    /// Microseconds per quarter note
+    Tempo(u32),
+    EndOfTrack,
+}
+
+/// Reads the bytes of a track
+struct TrackReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> TrackReader<'a> {
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], MidiLoaderError> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + length)
+            .ok_or(MidiLoaderError::InvalidFile("a track is truncated"))?;
+        self.position += length;
+
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, MidiLoaderError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn variable_length(&mut self) -> Result<u64, MidiLoaderError> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.byte()?;
+            value = (value << 7) | (byte & 0x7f) as u64;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(MidiLoaderError::InvalidFile(
+            "invalid variable length quantity",
+        ))
+    }
+}
+
+/// Events of a track with their absolute tick
+fn parse_track(data: &[u8]) -> Result<Vec<(u64, TrackEvent)>, MidiLoaderError> {
+    let mut reader = TrackReader { data, position: 0 };
+    let mut events = vec![];
+    let mut tick = 0;
+    let mut running_status = None;
+    while reader.position < data.len() {
+        tick += reader.variable_length()?;
+        let mut status = reader.byte()?;
+        match status {
+            0xff => {
+                let kind = reader.byte()?;
+                let length = reader.variable_length()? as usize;
+                let content = reader.bytes(length)?;
+                match (kind, content) {
+                    (0x51, [high, middle, low]) => events.push((
+                        tick,
+                        TrackEvent::Tempo(u32::from_be_bytes([0, *high, *middle, *low])),
+                    )),
+                    (0x2f, _) => {
+                        events.push((tick, TrackEvent::EndOfTrack));
+                        break;
+                    }
+                    _ => (),
+                }
+                continue;
+            }
+            0xf0 | 0xf7 => {
+                let length = reader.variable_length()? as usize;
+                reader.bytes(length)?;
+                continue;
+            }
+            _ => (),
+        }
+        let first = if status < 0x80 {
+            // running status: the byte is the first data byte
+            let first = status;
+            status =
+                running_status.ok_or(MidiLoaderError::InvalidFile("data byte without a status"))?;
+            first
+        } else {
+            running_status = Some(status);
+            reader.byte()?
+        };
+        let channel = status & 0x0f;
+        let event = match status & 0xf0 {
+            0x80 => {
+                reader.byte()?;
+                MidiEvent::NoteOff {
+                    channel,
+                    key: first,
+                }
+            }
+            0x90 => match reader.byte()? {
+                0 => MidiEvent::NoteOff {
+                    channel,
+                    key: first,
+                },
+                velocity => MidiEvent::NoteOn {
+                    channel,
+                    key: first,
+                    velocity,
+                },
+            },
+            0xa0 => {
+                // polyphonic aftertouch is ignored
+                reader.byte()?;
+                continue;
+            }
+            0xb0 => MidiEvent::ControlChange {
+                channel,
+                controller: first,
+                value: reader.byte()?,
+            },
+            0xc0 => MidiEvent::ProgramChange {
+                channel,
+                program: first,
+            },
+            // channel aftertouch is ignored
+            0xd0 => continue,
+            0xe0 => MidiEvent::PitchBend {
+                channel,
+                value: ((reader.byte()? as u16) << 7) | first as u16,
+            },
+            _ => return Err(MidiLoaderError::InvalidFile("invalid status byte")),
+        };
+        events.push((tick, TrackEvent::Channel(event)));
+    }
+
+    Ok(events)
+}
+
+/// Plays MIDI with the instruments of a [`SoundFont`]
+///
+/// Without a [`MidiSequence`], the player only plays events sent through its [`MidiHandle`]
+/// and keeps playing until it is stopped. With a sequence, it stops once the sequence ended,
+/// unless it is looped with [`MidiPlayer::with_looped`] or played with
+/// [`PlayAudioCommand::looped`](crate::PlayAudioCommand::looped). Play it by converting it into a [`CustomAudioSource`]. Live events,
+/// tempo, transposition, and muted channels can be changed with the methods of the playing
+/// [`AudioInstance`](crate::AudioInstance) or its [`MidiHandle`].
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_kira_audio::prelude::*;
+/// fn play_music(
+///     sound_fonts: Res<Assets<SoundFont>>,
+///     sequences: Res<Assets<MidiSequence>>,
+///     mut custom_sources: ResMut<Assets<CustomAudioSource>>,
+///     audio: Res<Audio>,
+/// #   sound_font: Handle<SoundFont>,
+/// #   sequence: Handle<MidiSequence>,
+/// ) {
+///     let (Some(sound_font), Some(sequence)) =
+///         (sound_fonts.get(&sound_font), sequences.get(&sequence))
+///     else {
+///         return;
+///     };
+///     let player = MidiPlayer::new(sound_font)
+///         .with_sequence(sequence)
+///         .with_looped(true);
+///     audio.play_custom(custom_sources.add(player));
+/// }
+/// ```
+#[derive(Clone)]
+pub struct MidiPlayer {
+    sound_font: Arc<SoundFontData>,
+    sequence: Option<MidiSequence>,
+    tempo: f64,
+    transpose: i8,
+    looped: bool,
+    gain: f32,
+}
+
+impl MidiPlayer {
+    /// Create a player with the instruments of the given sound font
+    pub fn new(sound_font: &SoundFont) -> Self {
+        MidiPlayer {
+            sound_font: sound_font.data.clone(),
+            sequence: None,
+            tempo: 1.,
+            transpose: 0,
+            looped: false,
+            gain: 1.,
+        }
+    }
+
+    /// Play the given sequence
+    pub fn with_sequence(mut self, sequence: &MidiSequence) -> Self {
+        self.sequence = Some(sequence.clone());
+
+        self
+    }
+
+    /// Set the tempo as factor of the tempo of the sequence
+    pub fn with_tempo(mut self, tempo: f64) -> Self {
+        self.tempo = tempo;
+
+        self
+    }
+
+    /// Transpose all notes except percussion by the given number of semitones
+    pub fn with_transpose(mut self, semitones: i8) -> Self {
+        self.transpose = semitones;
+
+        self
+    }
+
+    /// Start the sequence over once it ended
+    pub fn with_looped(mut self, looped: bool) -> Self {
+        self.looped = looped;
+
+        self
+    }
+
+    /// Set the gain factor of the whole player
+    pub fn with_gain(mut self, gain: f32) -> Self {
+        self.gain = gain;
+
+        self
+    }
+}
+
+impl From<MidiPlayer> for CustomAudioSource {
+    fn from(player: MidiPlayer) -> Self {
+        CustomAudioSource::loopable(move |looped| {
+            let looped = looped || player.looped;
+            player.clone().with_looped(looped)
+        })
+    }
+}
+
+impl SoundData for MidiPlayer {
+    type Error = Infallible;
+    type Handle = MidiHandle;
+
+    fn into_sound(self) -> Result<(Box<dyn Sound>, Self::Handle), Self::Error> {
+        let control = Arc::new(MidiControl {
+            commands: Mutex::new(vec![]),
+            position: AtomicU64::new(0f64.to_bits()),
+        });
+        let sound = MidiSound::new(self, control.clone());
+
+        Ok((Box::new(sound), MidiHandle { control }))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum MidiCommand {
+    Event(MidiEvent),
+    SetTempo(f64),
+    SetTranspose(i8),
+    SetMuted(u8, bool),
+    SetLooped(bool),
+}
+
+/// State shared between a playing [`MidiPlayer`] and its handle
+struct MidiControl {
+    commands: Mutex<Vec<MidiCommand>>,
+    position: AtomicU64,
+}
+
+/// Controls a playing [`MidiPlayer`]
+///
+/// Get it from the playing instance with
+/// [`AudioInstance::custom_handle`](crate::AudioInstance::custom_handle) or use the MIDI
+/// methods of [`AudioInstance`](crate::AudioInstance). Events sent through the handle are played
+/// at the start of the next processed block of audio.
+pub struct MidiHandle {
+    control: Arc<MidiControl>,
+}
+
+impl MidiHandle {
+    fn command(&mut self, command: MidiCommand) {
+        self.control.commands.lock().push(command);
+    }
+
+    /// Play a MIDI event
+    pub fn send(&mut self, event: MidiEvent) {
+        self.command(MidiCommand::Event(event));
+    }
+
+    /// Start playing a note
+    pub fn note_on(&mut self, channel: u8, key: u8, velocity: u8) {
+        self.send(MidiEvent::NoteOn {
+            channel,
+            key,
+            velocity,
+        });
+    }
+
+    /// Release a note
+    pub fn note_off(&mut self, channel: u8, key: u8) {
+        self.send(MidiEvent::NoteOff { channel, key });
+    }
+
+    /// Set the tempo as factor of the tempo of the sequence
+    pub fn set_tempo(&mut self, tempo: f64) {
+        self.command(MidiCommand::SetTempo(tempo));
+    }
+
+    /// Transpose new notes except percussion by the given number of semitones
+    pub fn set_transpose(&mut self, semitones: i8) {
+        self.command(MidiCommand::SetTranspose(semitones));
+    }
+
+    /// Mute or unmute a MIDI channel
+    ///
+    /// Muting releases all notes of the channel and ignores new ones.
+    pub fn set_channel_muted(&mut self, channel: u8, muted: bool) {
+        self.command(MidiCommand::SetMuted(channel, muted));
+    }
+
+    /// Whether the sequence starts over once it ended
+    pub fn set_looped(&mut self, looped: bool) {
+        self.command(MidiCommand::SetLooped(looped));
+    }
+
+    /// Position in the sequence in seconds at its original tempo
+    pub fn position(&self) -> f64 {
+        f64::from_bits(self.control.position.load(Ordering::Acquire))
+    }
+}
+
+/// Controller state of a MIDI channel
+#[derive(Clone, Copy, Debug)]
+struct ChannelState {
+    bank: u16,
+    program: u8,
+    volume: f32,
+    expression: f32,
+    /// From -1.0 (left) to 1.0 (right)
+    pan: f32,
+    /// Frequency factor of the pitch bend
+    pitch_bend: f64,
+    sustain: bool,
+    muted: bool,
+}
+
+impl Default for ChannelState {
+    fn default() -> Self {
+        ChannelState {
+            bank: 0,
+            program: 0,
+            volume: controller_gain(100),
+            expression: 1.,
+            pan: 0.,
+            pitch_bend: 1.,
+            sustain: false,
+            muted: false,
+        }
+    }
+}
+
+impl ChannelState {
+    fn reset_controllers(&mut self) {
+        *self = ChannelState {
+            bank: self.bank,
+            program: self.program,
+            volume: self.volume,
+            muted: self.muted,
+            ..Default::default()
+        };
+    }
+}
+
+/// Gain of volume and expression controllers
+fn controller_gain(value: u8) -> f32 {
+    let value = value.min(127) as f32 / 127.;
+
+    value * value
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EnvelopeStage {
+    Delay,
+    Attack,
+    Hold,
+    Decay,
+    Sustain,
+    Release,
+    Done,
+}
+
+/// A zone playing a note
+#[derive(Clone, Debug)]
+struct Voice {
+    channel: u8,
+    /// Key of the note before transposition
+    key: u8,
+    zone: Zone,
+    position: f64,
+    /// Frequency factor of the note relative to the root key of the zone
+    pitch: f64,
+    gain: f32,
+    stage: EnvelopeStage,
+    stage_time: f64,
+    level: f32,
+    release_level: f32,
+    /// Released while the sustain pedal is held
+    sustained: bool,
+}
+
+impl Voice {
+    fn released(&self) -> bool {
+        matches!(self.stage, EnvelopeStage::Release | EnvelopeStage::Done)
+    }
+
+    fn release(&mut self) {
+        if !self.released() {
+            self.stage = EnvelopeStage::Release;
+            self.stage_time = 0.;
+            self.release_level = self.level;
+        }
+        self.sustained = false;
+    }
+
+    fn update_envelope(&mut self, dt: f64) {
+        let envelope = self.zone.envelope;
+        self.stage_time += dt;
+        loop {
+            let (duration, next) = match self.stage {
+                EnvelopeStage::Delay => (envelope.delay, EnvelopeStage::Attack),
+                EnvelopeStage::Attack => (envelope.attack, EnvelopeStage::Hold),
+                EnvelopeStage::Hold => (envelope.hold, EnvelopeStage::Decay),
+                EnvelopeStage::Decay => (envelope.decay, EnvelopeStage::Sustain),
+                EnvelopeStage::Release => (envelope.release, EnvelopeStage::Done),
+                EnvelopeStage::Sustain | EnvelopeStage::Done => break,
+            };
+            if self.stage_time < duration {
+                break;
+            }
+            self.stage_time -= duration;
+            self.stage = next;
+        }
+        let progress = |duration: f64| (self.stage_time / duration.max(f64::EPSILON)) as f32;
+        self.level = match self.stage {
+            EnvelopeStage::Delay => 0.,
+            EnvelopeStage::Attack => progress(envelope.attack),
+            EnvelopeStage::Hold => 1.,
+            EnvelopeStage::Decay => 1. - (1. - envelope.sustain) * progress(envelope.decay),
+            EnvelopeStage::Sustain => envelope.sustain,
+            EnvelopeStage::Release => self.release_level * (1. - progress(envelope.release)),
+            EnvelopeStage::Done => 0.,
+        };
+    }
+
+    /// Advance the voice and return its sample
+    fn next_sample(&mut self, samples: &[f32], pitch_bend: f64, dt: f64) -> f32 {
+        self.update_envelope(dt);
+        let looping = match self.zone.loop_mode {
+            LoopMode::None => false,
+            LoopMode::Continuous => true,
+            LoopMode::UntilRelease => !self.released(),
+        } && self.zone.loop_end > self.zone.loop_start;
+        if looping && self.position >= self.zone.loop_end as f64 {
+            self.position -= (self.zone.loop_end - self.zone.loop_start) as f64;
+        }
+        let index = self.position as usize;
+        if index + 1 >= self.zone.end {
+            self.stage = EnvelopeStage::Done;
+            return 0.;
+        }
+        let current = samples.get(index).copied().unwrap_or_default();
+        let next = if looping && index + 1 >= self.zone.loop_end {
+            samples
+                .get(self.zone.loop_start)
+                .copied()
+                .unwrap_or_default()
+        } else {
+            samples.get(index + 1).copied().unwrap_or_default()
+        };
+        let sample = current + (next - current) * self.position.fract() as f32;
+        self.position += self.pitch * pitch_bend * self.zone.sample_rate * dt;
+
+        sample * self.gain * self.level
+    }
+}
+
+struct MidiSound {
+    sound_font: Arc<SoundFontData>,
+    sequence: Option<MidiSequence>,
+    control: Arc<MidiControl>,
+    channels: [ChannelState; 16],
+    voices: Vec<Voice>,
+    /// Position in the sequence in seconds at its original tempo
+    time: f64,
+    next_event: usize,
+    tempo: f64,
+    transpose: i8,
+    looped: bool,
+    gain: f32,
+    finished: bool,
+}
+
+impl MidiSound {
+    fn new(player: MidiPlayer, control: Arc<MidiControl>) -> Self {
+        let mut channels = [ChannelState::default(); 16];
+        channels[PERCUSSION_CHANNEL as usize].bank = PERCUSSION_BANK;
+
+        MidiSound {
+            sound_font: player.sound_font,
+            sequence: player.sequence,
+            control,
+            channels,
+            voices: Vec::with_capacity(MAX_VOICES),
+            time: 0.,
+            next_event: 0,
+            tempo: player.tempo,
+            transpose: player.transpose,
+            looped: player.looped,
+            gain: player.gain,
+            finished: false,
+        }
+    }
+
+    fn run_commands(&mut self) {
+        let control = self.control.clone();
+        let Some(mut commands) = control.commands.try_lock() else {
+            return;
+        };
+        for command in commands.drain(..) {
+            match command {
+                MidiCommand::Event(event) => self.handle_event(event),
+                MidiCommand::SetTempo(tempo) => self.tempo = tempo.max(0.),
+                MidiCommand::SetTranspose(semitones) => self.transpose = semitones,
+                MidiCommand::SetMuted(channel, muted) => {
+                    let channel = channel & 0x0f;
+                    self.channels[channel as usize].muted = muted;
+                    if muted {
+                        self.release_channel(channel);
+                    }
+                }
+                MidiCommand::SetLooped(looped) => self.looped = looped,
+            }
+        }
+    }
+
+    fn release_channel(&mut self, channel: u8) {
+        for voice in self
+            .voices
+            .iter_mut()
+            .filter(|voice| voice.channel == channel)
+        {
+            voice.release();
+        }
+    }
+
+    fn handle_event(&mut self, event: MidiEvent) {
+        let channel = event.channel() & 0x0f;
+        let state = &mut self.channels[channel as usize];
+        match event {
+            MidiEvent::NoteOn { key, velocity, .. } if velocity > 0 => {
+                if !state.muted {
+                    self.note_on(channel, key.min(127), velocity.min(127));
+                }
+            }
+            MidiEvent::NoteOn { key, .. } | MidiEvent::NoteOff { key, .. } => {
+                let sustain = state.sustain;
+                for voice in self
+                    .voices
+                    .iter_mut()
+                    .filter(|voice| voice.channel == channel && voice.key == key)
+                {
+                    if sustain {
+                        voice.sustained = true;
+                    } else {
+                        voice.release();
+                    }
+                }
+            }
+            MidiEvent::ControlChange {
+                controller, value, ..
+            } => match controller {
+                // the percussion channel keeps the percussion bank, like General MIDI players
+                0 if channel != PERCUSSION_CHANNEL => state.bank = value as u16,
+                7 => state.volume = controller_gain(value),
+                10 => state.pan = ((value as f32 - 64.) / 63.).clamp(-1., 1.),
+                11 => state.expression = controller_gain(value),
+                64 => {
+                    state.sustain = value >= 64;
+                    if !state.sustain {
+                        for voice in self
+                            .voices
+                            .iter_mut()
+                            .filter(|voice| voice.channel == channel && voice.sustained)
+                        {
+                            voice.release();
+                        }
+                    }
+                }
+                120 => self.voices.retain(|voice| voice.channel != channel),
+                121 => state.reset_controllers(),
+                123 => self.release_channel(channel),
+                _ => (),
+            },
+            MidiEvent::ProgramChange { program, .. } => state.program = program,
+            MidiEvent::PitchBend { value, .. } => {
+                let semitones = (value.min(16383) as f64 - 8192.) / 8192. * 2.;
+                state.pitch_bend = 2f64.powf(semitones / 12.);
+            }
+        }
+    }
+
+    fn note_on(&mut self, channel: u8, key: u8, velocity: u8) {
+        let state = self.channels[channel as usize];
+        let played_key = if channel == PERCUSSION_CHANNEL {
+            key
+        } else {
+            (key as i16 + self.transpose as i16).clamp(0, 127) as u8
+        };
+        let velocity_gain = controller_gain(velocity);
+        for zone in self
+            .sound_font
+            .zones(state.bank, state.program, played_key, velocity)
+        {
+            if self.voices.len() >= MAX_VOICES {
+                self.voices.remove(0);
+            }
+            let cents = (played_key as f64 - zone.root_key as f64) * zone.scale_tuning + zone.tune;
+            self.voices.push(Voice {
+                channel,
+                key,
+                zone: *zone,
+                position: zone.start as f64,
+                pitch: 2f64.powf(cents / 1200.),
+                gain: velocity_gain * zone.attenuation,
+                stage: EnvelopeStage::Delay,
+                stage_time: 0.,
+                level: 0.,
+                release_level: 0.,
+                sustained: false,
+            });
+        }
+    }
+
+    /// Handle sequence events up to the current time
+    fn advance_sequence(&mut self, dt: f64) {
+        let Some((event_count, duration)) = self
+            .sequence
+            .as_ref()
+            .map(|sequence| (sequence.events.len(), sequence.duration))
+        else {
+            return;
+        };
+        while let Some(event) = self
+            .sequence
+            .as_ref()
+            .and_then(|sequence| sequence.events.get(self.next_event))
+            .copied()
+        {
+            if event.time > self.time {
+                break;
+            }
+            self.handle_event(event.event);
+            self.next_event += 1;
+        }
+        if self.next_event >= event_count && self.time >= duration {
+            if self.looped && duration > 0. {
+                self.time -= duration;
+                self.next_event = 0;
+            } else if self.voices.is_empty() {
+                self.finished = true;
+            }
+        }
+        self.time += dt * self.tempo;
+    }
+
+    fn next_frame(&mut self, dt: f64) -> Frame {
+        self.advance_sequence(dt);
+        let mut frame = Frame::ZERO;
+        for voice in &mut self.voices {
+            let channel = &self.channels[voice.channel as usize];
+            let sample = voice.next_sample(&self.sound_font.samples, channel.pitch_bend, dt)
+                * channel.volume
+                * channel.expression;
+            let angle = ((voice.zone.pan + channel.pan).clamp(-1., 1.) + 1.) * FRAC_PI_4;
+            frame.left += sample * angle.cos();
+            frame.right += sample * angle.sin();
+        }
+        self.voices
+            .retain(|voice| voice.stage != EnvelopeStage::Done);
+
+        frame * self.gain
+    }
+}
+
+impl Sound for MidiSound {
+    fn on_start_processing(&mut self) {
+        self.run_commands();
+    }
+
+    fn process(&mut self, out: &mut [Frame], dt: f64, _info: &Info) {
+        for frame in out {
+            *frame = self.next_frame(dt);
+        }
+        self.control
+            .position
+            .store(self.time.to_bits(), Ordering::Release);
+    }
+
+    fn finished(&self) -> bool {
+        self.finished
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Format 0 file with one quarter note per second playing the middle C for one quarter
+    fn midi_file() -> Vec<u8> {
+        let track = [
+            0x00, 0xff, 0x51, 0x03, 0x0f, 0x42, 0x40, // tempo of 1,000,000µs per quarter
+            0x00, 0x90, 0x3c, 0x64, // note on
+            0x60, 0x3c, 0x00, // note on with velocity 0 and running status after 96 ticks
+            0x00, 0xff, 0x2f, 0x00, // end of track
+        ];
+        let mut bytes = b"MThd\0\0\0\x06\0\0\0\x01\0\x60MTrk".to_vec();
+        bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&track);
+
+        bytes
+    }
+
+    fn player(sequence: Option<&MidiSequence>) -> (MidiSound, MidiHandle) {
+        let player = MidiPlayer {
+            sound_font: Arc::new(SoundFontData::square_wave()),
+            sequence: sequence.cloned(),
+            tempo: 1.,
+            transpose: 0,
+            looped: false,
+            gain: 1.,
+        };
+        let control = Arc::new(MidiControl {
+            commands: Mutex::new(vec![]),
+            position: AtomicU64::new(0),
+        });
+
+        (
+            MidiSound::new(player, control.clone()),
+            MidiHandle { control },
+        )
+    }
+
+    #[test]
+    fn rejects_chunks_larger_than_the_file() {
+        let mut header_too_large = midi_file();
+        header_too_large[4..8].copy_from_slice(&u32::MAX.to_be_bytes());
+        let mut track_too_large = midi_file();
+        track_too_large[18..22].copy_from_slice(&u32::MAX.to_be_bytes());
+
+        for bytes in [header_too_large, track_too_large] {
+            assert!(matches!(
+                MidiSequence::parse(&bytes),
+                Err(MidiLoaderError::InvalidFile(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn parses_standard_midi_files() {
+        let sequence = MidiSequence::parse(&midi_file()).unwrap();
+
+        assert_eq!(sequence.duration(), Duration::from_secs(1));
+        assert_eq!(
+            sequence.events[..],
+            [
+                TimedEvent {
+                    time: 0.,
+                    event: MidiEvent::NoteOn {
+                        channel: 0,
+                        key: 60,
+                        velocity: 100
+                    }
+                },
+                TimedEvent {
+                    time: 1.,
+                    event: MidiEvent::NoteOff {
+                        channel: 0,
+                        key: 60
+                    }
+                }
+            ]
+        );
+
+        let mut format_2 = midi_file();
+        format_2[9] = 2;
+        assert!(matches!(
+            MidiSequence::parse(&format_2),
+            Err(MidiLoaderError::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn plays_the_sequence_until_it_ended() {
+        let sequence = MidiSequence::parse(&midi_file()).unwrap();
+        let (mut sound, _) = player(Some(&sequence));
+        let dt = 1. / 1000.;
+
+        let played: Vec<_> = (0..990).map(|_| sound.next_frame(dt)).collect();
+        assert!(played.iter().any(|frame| frame.left > 0.1));
+        assert!(!sound.finished);
+        for _ in 0..30 {
+            sound.next_frame(dt);
+        }
+        assert!(sound.finished);
+    }
+
+    #[test]
+    fn live_notes_follow_transposition_and_mutes() {
+        let (mut sound, mut handle) = player(None);
+        handle.set_transpose(12);
+        handle.note_on(0, 60, 127);
+        sound.run_commands();
+
+        assert_eq!(sound.voices.len(), 1);
+        assert_eq!(sound.voices[0].pitch, 2.);
+        assert!((0..10).any(|_| sound.next_frame(1. / 1000.).left > 0.1));
+
+        handle.set_channel_muted(0, true);
+        handle.note_on(0, 64, 127);
+        sound.run_commands();
+        for _ in 0..20 {
+            sound.next_frame(1. / 1000.);
+        }
+        assert!(sound.voices.is_empty());
+        assert!(!sound.finished);
+    }
+
+    #[test]
+    fn bank_selects_keep_the_percussion_bank() {
+        let (mut sound, mut handle) = player(None);
+        for channel in [0, PERCUSSION_CHANNEL] {
+            handle.send(MidiEvent::ControlChange {
+                channel,
+                controller: 0,
+                value: 1,
+            });
+        }
+        sound.run_commands();
+
+        assert_eq!(sound.channels[0].bank, 1);
+        assert_eq!(
+            sound.channels[PERCUSSION_CHANNEL as usize].bank,
+            PERCUSSION_BANK
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::reflect::TypePath;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::source::midi::MidiSequence;
+use crate::source::soundfont::{SoundFont, SoundFontData};
+
+/// Asset loader for standard MIDI files.
+///
+/// Files of format 0 and 1 are supported. Play the loaded [`MidiSequence`] with a
+/// [`MidiPlayer`](crate::MidiPlayer).
+#[derive(Default, TypePath)]
+pub struct MidiLoader;
+
+/// Possible errors that can be produced by [`MidiLoader`]
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum MidiLoaderError {
+    /// An [IO Error](std::io::Error)
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+    /// The file is not a valid MIDI file
+    #[error("Invalid MIDI file: {0}")]
+    InvalidFile(&'static str),
+    /// The file uses a format that cannot be played
+    #[error("Unsupported MIDI format: {0}")]
+    UnsupportedFormat(String),
+}
+
+impl AssetLoader for MidiLoader {
+    type Asset = MidiSequence;
+    type Settings = ();
+    type Error = MidiLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes).await?;
+
+        MidiSequence::parse(&bytes)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["mid", "midi"]
+    }
+}
+
+/// Asset loader for SoundFont 2 files.
+#[derive(Default, TypePath)]
+pub struct SoundFontLoader;
+
+/// Possible errors that can be produced by [`SoundFontLoader`]
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum SoundFontLoaderError {
+    /// An [IO Error](std::io::Error)
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+    /// The file is not a valid SoundFont 2 file
+    #[error("Invalid SoundFont: {0}")]
+    InvalidFile(&'static str),
+}
+
+impl AssetLoader for SoundFontLoader {
+    type Asset = SoundFont;
+    type Settings = ();
+    type Error = SoundFontLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes).await?;
+
+        Ok(SoundFont {
+            data: Arc::new(SoundFontData::parse(&bytes)?),
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["sf2"]
+    }
+}
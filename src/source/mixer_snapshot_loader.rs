@@ -0,0 +1,44 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::reflect::TypePath;
+use thiserror::Error;
+
+use crate::mixer::MixerSnapshot;
+
+/// Asset loader for mixer snapshot files.
+#[derive(Default, TypePath)]
+pub struct MixerSnapshotLoader;
+
+/// Possible errors that can be produced by [`MixerSnapshotLoader`]
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum MixerSnapshotLoaderError {
+    /// An [IO Error](std::io::Error)
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [RON Error](serde_ron::error::SpannedError)
+    #[error("Could not parse RON: {0}")]
+    RonError(#[from] ron::error::SpannedError),
+}
+
+impl AssetLoader for MixerSnapshotLoader {
+    type Asset = MixerSnapshot;
+    type Settings = ();
+    type Error = MixerSnapshotLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["mixer.ron"]
+    }
+}
@@ -1,23 +1,221 @@
-//! Asset loaders for commonly used audio file formats
+//! Audio sources and asset loaders for commonly used audio file formats
 
+#[cfg(feature = "aiff")]
+pub mod aiff_loader;
+pub mod audio_loader;
+pub mod custom;
 #[cfg(feature = "flac")]
 pub mod flac_loader;
+mod loop_points;
+#[cfg(feature = "midi")]
+pub mod midi;
+#[cfg(feature = "midi")]
+pub mod midi_loader;
+#[cfg(feature = "settings_loader")]
+pub mod mixer_snapshot_loader;
+#[cfg(feature = "mod")]
+pub mod mod_loader;
 #[cfg(feature = "mp3")]
 pub mod mp3_loader;
 #[cfg(feature = "ogg")]
 pub mod ogg_loader;
 #[cfg(feature = "settings_loader")]
 pub mod settings_loader;
+#[cfg(feature = "settings_loader")]
+pub mod sfx_loader;
+#[cfg(feature = "settings_loader")]
+pub mod sound_event_loader;
+#[cfg(feature = "midi")]
+pub mod soundfont;
+pub mod synth;
+#[cfg(feature = "mod")]
+pub mod tracker;
+#[cfg(feature = "mod")]
+mod tracker_formats;
 #[cfg(feature = "wav")]
 pub mod wav_loader;
 
 use bevy::asset::Asset;
 use bevy::reflect::TypePath;
-use kira::sound::static_sound::StaticSoundData;
+use kira::Frame;
+use kira::sound::static_sound::{StaticSoundData, StaticSoundSettings};
+use kira::sound::{FromFileError, Region};
+use std::io::Cursor;
+use std::sync::Arc;
+use thiserror::Error;
 
 /// A source of audio data
+///
+/// Audio sources are usually loaded from files by the asset loaders of the enabled features.
+/// Sounds generated or received at runtime can be added to `Assets<AudioSource>` with one of
+/// the constructors.
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_kira_audio::prelude::*;
+/// fn add_silence(mut audio_sources: ResMut<Assets<AudioSource>>) {
+///     let silence = AudioSource::from_interleaved_f32(2, 44_100, &[0.; 88_200]).unwrap();
+///     audio_sources.add(silence);
+/// }
+/// ```
 #[derive(Clone, Asset, TypePath)]
 pub struct AudioSource {
     /// The Kira sound making up this `AudioSource`
     pub sound: StaticSoundData,
+    loop_region: Option<Region>,
+}
+
+impl From<StaticSoundData> for AudioSource {
+    fn from(sound: StaticSoundData) -> Self {
+        AudioSource {
+            sound,
+            loop_region: None,
+        }
+    }
+}
+
+impl AudioSource {
+    /// Loop region used when the sound is played looped, for example from loop points in the
+    /// metadata of the file
+    pub fn loop_region(&self) -> Option<Region> {
+        self.loop_region
+    }
+
+    /// Set the loop region used when the sound is played looped
+    ///
+    /// Sounds played without [`looped`](crate::PlayAudioCommand::looped) ignore it.
+    pub fn with_loop_region(mut self, loop_region: impl Into<Region>) -> Self {
+        self.loop_region = Some(loop_region.into());
+        self
+    }
+
+    /// Create an audio source from stereo frames
+    pub fn from_frames(sample_rate: u32, frames: impl Into<Arc<[Frame]>>) -> Self {
+        AudioSource::from(StaticSoundData {
+            sample_rate,
+            frames: frames.into(),
+            settings: StaticSoundSettings::default(),
+            slice: None,
+        })
+    }
+
+    /// Create an audio source from interleaved 16-bit samples
+    ///
+    /// Mono data is played on both sides. For more than two channels, only the first two are used.
+    pub fn from_interleaved_i16(
+        channels: u16,
+        sample_rate: u32,
+        data: &[i16],
+    ) -> Result<Self, AudioSourceError> {
+        let frames = interleaved_frames(channels, data, |sample| sample as f32 / 32768.)?;
+
+        Ok(AudioSource::from_frames(sample_rate, frames))
+    }
+
+    /// Create an audio source from interleaved samples between -1.0 and 1.0
+    ///
+    /// Mono data is played on both sides. For more than two channels, only the first two are used.
+    pub fn from_interleaved_f32(
+        channels: u16,
+        sample_rate: u32,
+        data: &[f32],
+    ) -> Result<Self, AudioSourceError> {
+        let frames = interleaved_frames(channels, data, |sample| sample)?;
+
+        Ok(AudioSource::from_frames(sample_rate, frames))
+    }
+
+    /// Decode an audio file from memory
+    ///
+    /// The format is detected from the data. Only formats of enabled features can be decoded.
+    pub fn from_encoded_bytes(bytes: impl Into<Vec<u8>>) -> Result<Self, AudioSourceError> {
+        let sound = StaticSoundData::from_cursor(Cursor::new(bytes.into()))?;
+
+        Ok(AudioSource::from(sound))
+    }
+}
+
+fn interleaved_frames<T: Copy>(
+    channels: u16,
+    data: &[T],
+    to_f32: impl Fn(T) -> f32,
+) -> Result<Vec<Frame>, AudioSourceError> {
+    if channels == 0 {
+        return Err(AudioSourceError::NoChannels);
+    }
+    if !data.len().is_multiple_of(channels as usize) {
+        return Err(AudioSourceError::IncompleteFrame {
+            samples: data.len(),
+            channels,
+        });
+    }
+
+    Ok(data
+        .chunks_exact(channels as usize)
+        .map(|frame| match frame {
+            [mono] => Frame::from_mono(to_f32(*mono)),
+            [left, right, ..] => Frame::new(to_f32(*left), to_f32(*right)),
+            [] => Frame::ZERO,
+        })
+        .collect())
+}
+
+/// Possible errors when creating an [`AudioSource`] from samples or bytes
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum AudioSourceError {
+    /// The data was declared to have zero channels
+    #[error("Audio data needs at least one channel")]
+    NoChannels,
+    /// The number of samples does not fill the last frame
+    #[error("{samples} samples do not split into frames of {channels} channels")]
+    IncompleteFrame {
+        /// Number of interleaved samples
+        samples: usize,
+        /// Number of channels per frame
+        channels: u16,
+    },
+    /// An Error loading sound from a file. See [`FromFileError`]
+    #[error("Error while decoding a sound: {0}")]
+    FileError(#[from] FromFileError),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn converts_interleaved_samples_to_frames() {
+        let source =
+            AudioSource::from_interleaved_i16(2, 48_000, &[16384, -16384, 0, 32767]).unwrap();
+
+        assert_eq!(source.sound.sample_rate, 48_000);
+        assert_eq!(source.sound.frames.len(), 2);
+        assert_eq!(source.sound.frames[0], Frame::new(0.5, -0.5));
+
+        let mono = AudioSource::from_interleaved_f32(1, 44_100, &[0.25, 0.5]).unwrap();
+        assert_eq!(mono.sound.frames[1], Frame::from_mono(0.5));
+    }
+
+    #[test]
+    fn rejects_invalid_channel_counts() {
+        assert!(matches!(
+            AudioSource::from_interleaved_f32(0, 44_100, &[0.]),
+            Err(AudioSourceError::NoChannels)
+        ));
+        assert!(matches!(
+            AudioSource::from_interleaved_f32(2, 44_100, &[0., 0., 0.]),
+            Err(AudioSourceError::IncompleteFrame {
+                samples: 3,
+                channels: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn uses_the_first_two_of_many_channels() {
+        let source = AudioSource::from_interleaved_f32(3, 44_100, &[0.1, 0.2, 0.9]).unwrap();
+
+        assert_eq!(source.sound.frames[0], Frame::new(0.1, 0.2));
+    }
 }
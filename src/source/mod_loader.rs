@@ -0,0 +1,53 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::reflect::TypePath;
+use thiserror::Error;
+
+use crate::source::custom::CustomAudioSource;
+use crate::source::tracker::Module;
+
+/// Asset loader for ProTracker, Scream Tracker 3, FastTracker 2, and Impulse Tracker modules.
+///
+/// Modules are rendered in real time while playing them with
+/// [`AudioControl::play_custom`](crate::AudioControl::play_custom). The common effects,
+/// instruments, and volume envelopes are supported. Tremolo, retriggers, pattern loops, panning
+/// envelopes, auto-vibrato, and the new note actions of Impulse Tracker are not.
+#[derive(Default, TypePath)]
+pub struct ModLoader;
+
+/// Possible errors that can be produced by [`ModLoader`]
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum ModLoaderError {
+    /// An [IO Error](std::io::Error)
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+    /// The file is not a valid module
+    #[error("Invalid module: {0}")]
+    InvalidFile(&'static str),
+    /// The module uses a format that cannot be played
+    #[error("Unsupported module format: {0}")]
+    UnsupportedFormat(String),
+}
+
+impl AssetLoader for ModLoader {
+    type Asset = CustomAudioSource;
+    type Settings = ();
+    type Error = ModLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes).await?;
+
+        Ok(Module::parse(&bytes)?.into())
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["mod", "s3m", "xm", "it"]
+    }
+}
@@ -39,7 +39,7 @@ impl AssetLoader for Mp3Loader {
         let mut sound_bytes = vec![];
         reader.read_to_end(&mut sound_bytes).await?;
         let sound = StaticSoundData::from_cursor(Cursor::new(sound_bytes))?;
-        Ok(AudioSource { sound })
+        Ok(AudioSource::from(sound))
     }
 
     fn extensions(&self) -> &[&str] {
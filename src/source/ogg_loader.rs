@@ -8,6 +8,7 @@ use std::io::Cursor;
 use thiserror::Error;
 
 use crate::source::AudioSource;
+use crate::source::loop_points::ogg_loop_region;
 
 /// Possible errors that can be produced by [`OggLoader`]
 #[non_exhaustive]
@@ -22,6 +23,12 @@ pub enum OggLoaderError {
 }
 
 /// Asset loader for OGG files.
+///
+/// Only Vorbis streams can be decoded; Opus and Speex streams fail to load with a
+/// [`FromFileError`].
+///
+/// The `LOOPSTART` and `LOOPLENGTH` (or `LOOPEND`) comments of Vorbis streams set the loop region
+/// of the loaded sound in sample frames.
 #[derive(Default, TypePath)]
 pub struct OggLoader;
 
@@ -38,8 +45,9 @@ impl AssetLoader for OggLoader {
     ) -> Result<Self::Asset, Self::Error> {
         let mut sound_bytes = vec![];
         reader.read_to_end(&mut sound_bytes).await?;
+        let loop_region = ogg_loop_region(&sound_bytes);
         let sound = StaticSoundData::from_cursor(Cursor::new(sound_bytes))?;
-        Ok(AudioSource { sound })
+        Ok(AudioSource { sound, loop_region })
     }
 
     fn extensions(&self) -> &[&str] {
@@ -125,7 +125,7 @@ impl AssetLoader for SettingsLoader {
         let mut sound = StaticSoundData::from_cursor(Cursor::new(sound_bytes))?;
         sound.settings = sound_settings.into();
 
-        Ok(AudioSource { sound })
+        Ok(AudioSource::from(sound))
     }
 
     fn extensions(&self) -> &[&str] {
@@ -0,0 +1,308 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::reflect::TypePath;
+use kira::Frame;
+use kira::sound::static_sound::{StaticSoundData, StaticSoundSettings};
+use serde::Deserialize;
+use std::f64::consts::TAU;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::AudioSource;
+use crate::rng::AudioRng;
+
+/// Asset loader for procedural sound effect files.
+///
+/// The sfxr-like parameters in *.sfx.ron files are rendered into an [`AudioSource`] at load time.
+/// With Bevy's `file_watcher` feature, changed files are reloaded while the game is running.
+#[derive(Default, TypePath)]
+pub struct SfxLoader;
+
+/// Waveform of a procedural sound effect
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum SfxWave {
+    #[default]
+    Square,
+    Sawtooth,
+    Sine,
+    Noise,
+}
+
+/// Procedural sound effect definition
+///
+/// This is used when loading an [`AudioSource`] from a *.sfx.ron file.
+/// Durations are in seconds and frequencies in Hz.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields, default)]
+struct SfxDefinition {
+    /// The waveform of the oscillator
+    wave: SfxWave,
+
+    /// Time to rise from silence to full volume
+    attack: f64,
+
+    /// Time to hold the volume
+    sustain: f64,
+
+    /// Extra volume at the start of the sustain, falling off until its end
+    sustain_punch: f64,
+
+    /// Time to fall from full volume to silence
+    decay: f64,
+
+    /// Start frequency
+    frequency: f64,
+
+    /// The sound ends once a sliding frequency falls below this frequency
+    min_frequency: f64,
+
+    /// Change of the frequency in octaves per second
+    frequency_slide: f64,
+
+    /// Change of the frequency slide in octaves per second squared
+    frequency_delta_slide: f64,
+
+    /// Depth of the vibrato as a fraction of the frequency
+    vibrato_depth: f64,
+
+    /// Vibrato oscillations per second
+    vibrato_speed: f64,
+
+    /// Factor applied to the frequency after the arpeggio delay
+    arpeggio_multiplier: f64,
+
+    /// Time until the frequency jumps by the arpeggio multiplier
+    arpeggio_delay: f64,
+
+    /// Fraction of each cycle the square wave is high (from 0.0 to 1.0)
+    duty: f64,
+
+    /// Change of the duty per second
+    duty_sweep: f64,
+
+    /// Cutoff frequency of a low-pass filter
+    low_pass_cutoff: Option<f64>,
+
+    /// Cutoff frequency of a high-pass filter
+    high_pass_cutoff: Option<f64>,
+
+    /// Amplitude factor of the rendered sound
+    volume: f32,
+
+    /// Sample rate of the rendered sound
+    sample_rate: u32,
+
+    /// Seed for the noise waveform
+    seed: u64,
+}
+
+impl Default for SfxDefinition {
+    fn default() -> Self {
+        SfxDefinition {
+            wave: SfxWave::Square,
+            attack: 0.,
+            sustain: 0.3,
+            sustain_punch: 0.,
+            decay: 0.4,
+            frequency: 440.,
+            min_frequency: 0.,
+            frequency_slide: 0.,
+            frequency_delta_slide: 0.,
+            vibrato_depth: 0.,
+            vibrato_speed: 0.,
+            arpeggio_multiplier: 1.,
+            arpeggio_delay: 0.,
+            duty: 0.5,
+            duty_sweep: 0.,
+            low_pass_cutoff: None,
+            high_pass_cutoff: None,
+            volume: 0.5,
+            sample_rate: 44_100,
+            seed: 0,
+        }
+    }
+}
+
+impl SfxDefinition {
+    fn duration(&self) -> f64 {
+        self.attack.max(0.) + self.sustain.max(0.) + self.decay.max(0.)
+    }
+
+    fn envelope(&self, time: f64) -> f64 {
+        let attack = self.attack.max(0.);
+        let sustain = self.sustain.max(0.);
+        let decay = self.decay.max(0.);
+        if time < attack {
+            return time / attack;
+        }
+        if time < attack + sustain {
+            let progress = (time - attack) / sustain;
+            return 1. + self.sustain_punch * (1. - progress);
+        }
+
+        (1. - (time - attack - sustain) / decay).max(0.)
+    }
+
+    fn frequency(&self, time: f64) -> f64 {
+        let octaves = self.frequency_slide * time + 0.5 * self.frequency_delta_slide * time * time;
+        let mut frequency = self.frequency * 2f64.powf(octaves);
+        if self.arpeggio_multiplier != 1. && time >= self.arpeggio_delay {
+            frequency *= self.arpeggio_multiplier;
+        }
+
+        frequency * (1. + self.vibrato_depth * (TAU * self.vibrato_speed * time).sin())
+    }
+
+    fn render(&self) -> StaticSoundData {
+        let sample_rate = self.sample_rate.max(1) as f64;
+        let dt = 1. / sample_rate;
+        let length = (self.duration() * sample_rate).ceil() as usize;
+        let mut rng = AudioRng::from_seed(self.seed);
+        let low_pass = self.low_pass_cutoff.map(|cutoff| {
+            let rc = 1. / (TAU * cutoff);
+            dt / (rc + dt)
+        });
+        let high_pass = self.high_pass_cutoff.map(|cutoff| {
+            let rc = 1. / (TAU * cutoff);
+            rc / (rc + dt)
+        });
+        let mut phase = 0.;
+        let mut noise = rng.next_f64() * 2. - 1.;
+        let mut low_passed = 0.;
+        let mut high_passed = 0.;
+        let mut previous = 0.;
+        let mut frames = Vec::with_capacity(length);
+        for index in 0..length {
+            let time = index as f64 * dt;
+            let frequency = self.frequency(time);
+            if self.min_frequency > 0. && frequency < self.min_frequency {
+                break;
+            }
+            let duty = (self.duty + self.duty_sweep * time).clamp(0.01, 0.99);
+            let mut sample = match self.wave {
+                SfxWave::Square => {
+                    if phase < duty {
+                        1.
+                    } else {
+                        -1.
+                    }
+                }
+                SfxWave::Sawtooth => 2. * phase - 1.,
+                SfxWave::Sine => (TAU * phase).sin(),
+                SfxWave::Noise => noise,
+            };
+            phase += frequency * dt;
+            if phase >= 1. {
+                phase = phase.fract();
+                noise = rng.next_f64() * 2. - 1.;
+            }
+            if let Some(factor) = low_pass {
+                low_passed += factor * (sample - low_passed);
+                sample = low_passed;
+            }
+            if let Some(factor) = high_pass {
+                high_passed = factor * (high_passed + sample - previous);
+                previous = sample;
+                sample = high_passed;
+            }
+            let amplitude = sample * self.envelope(time) * self.volume as f64;
+            frames.push(Frame::from_mono(amplitude.clamp(-1., 1.) as f32));
+        }
+
+        StaticSoundData {
+            sample_rate: self.sample_rate.max(1),
+            frames: Arc::from(frames),
+            settings: StaticSoundSettings::default(),
+            slice: None,
+        }
+    }
+}
+
+/// Possible errors that can be produced by [`SfxLoader`]
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum SfxLoaderError {
+    /// An [IO Error](std::io::Error)
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [RON Error](serde_ron::error::SpannedError)
+    #[error("Could not parse RON: {0}")]
+    RonError(#[from] ron::error::SpannedError),
+    /// Attack, sustain, and decay add up to zero
+    #[error("A sound effect needs a duration above zero")]
+    NoDuration,
+}
+
+impl AssetLoader for SfxLoader {
+    type Asset = AudioSource;
+    type Settings = ();
+    type Error = SfxLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let definition: SfxDefinition = ron::de::from_bytes(&bytes)?;
+        if definition.duration() <= 0. {
+            return Err(SfxLoaderError::NoDuration);
+        }
+
+        Ok(AudioSource::from(definition.render()))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["sfx.ron"]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn renders_the_full_envelope() {
+        let definition = SfxDefinition {
+            attack: 0.1,
+            sustain: 0.2,
+            decay: 0.1,
+            sample_rate: 1000,
+            ..Default::default()
+        };
+        let sound = definition.render();
+
+        assert_eq!(sound.frames.len(), 400);
+        assert_eq!(sound.frames[0].left, 0.);
+        assert!(sound.frames[200].left.abs() > 0.49);
+        assert!(sound.frames.iter().all(|frame| frame.left.abs() <= 0.5));
+    }
+
+    #[test]
+    fn sliding_below_the_minimum_frequency_ends_the_sound() {
+        let definition = SfxDefinition {
+            frequency: 800.,
+            min_frequency: 400.,
+            frequency_slide: -2.,
+            sample_rate: 1000,
+            ..Default::default()
+        };
+
+        // one octave down after half a second
+        let length = definition.render().frames.len();
+        assert!((500..=501).contains(&length));
+    }
+
+    #[test]
+    fn parses_partial_definitions() {
+        let definition: SfxDefinition =
+            ron::de::from_str("(wave: Noise, frequency: 220., low_pass_cutoff: Some(2000.))")
+                .unwrap();
+
+        assert_eq!(definition.wave, SfxWave::Noise);
+        assert_eq!(definition.duty, 0.5);
+        assert_eq!(definition.low_pass_cutoff, Some(2000.));
+    }
+}
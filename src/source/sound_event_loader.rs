@@ -0,0 +1,108 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::reflect::TypePath;
+use serde::Deserialize;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use thiserror::Error;
+
+use crate::sound_event::{SoundEvent, SoundEventVariant, VariantSelection};
+
+/// Asset loader for sound event files.
+#[derive(Default, TypePath)]
+pub struct SoundEventLoader;
+
+/// Sound event definition
+///
+/// This is used when loading a [`SoundEvent`] from a *.sound_event.ron file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SoundEventDefinition {
+    /// The audio files to choose from
+    variants: Vec<VariantDefinition>,
+
+    /// How the variant to play is selected
+    #[serde(default)]
+    selection: VariantSelection,
+
+    /// Range of the volume offset in decibels
+    #[serde(default)]
+    volume: Option<RangeInclusive<f32>>,
+
+    /// Range of the playback rate factor
+    #[serde(default)]
+    playback_rate: Option<RangeInclusive<f64>>,
+
+    /// Range of the start position offset in seconds
+    #[serde(default)]
+    start_position: Option<RangeInclusive<f64>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VariantDefinition {
+    /// Location of the audio file
+    file: PathBuf,
+
+    /// Relative probability of this variant for random selections
+    #[serde(default = "default_weight")]
+    weight: f32,
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+/// Possible errors that can be produced by [`SoundEventLoader`]
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum SoundEventLoaderError {
+    /// An [IO Error](std::io::Error)
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [RON Error](serde_ron::error::SpannedError)
+    #[error("Could not parse RON: {0}")]
+    RonError(#[from] ron::error::SpannedError),
+    /// The sound event does not have any variants
+    #[error("A sound event needs at least one variant")]
+    NoVariants,
+}
+
+impl AssetLoader for SoundEventLoader {
+    type Asset = SoundEvent;
+    type Settings = ();
+    type Error = SoundEventLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let definition: SoundEventDefinition = ron::de::from_bytes(&bytes)?;
+        if definition.variants.is_empty() {
+            return Err(SoundEventLoaderError::NoVariants);
+        }
+        let variants = definition
+            .variants
+            .into_iter()
+            .map(|variant| {
+                SoundEventVariant::new(load_context.load(variant.file)).with_weight(variant.weight)
+            })
+            .collect();
+
+        Ok(SoundEvent {
+            variants,
+            selection: definition.selection,
+            volume: definition.volume,
+            playback_rate: definition.playback_rate,
+            start_position: definition.start_position,
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["sound_event.ron"]
+    }
+}
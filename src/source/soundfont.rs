@@ -0,0 +1,613 @@
+//! Instruments from SoundFont 2 files
+
+use crate::source::midi_loader::SoundFontLoaderError;
+use bevy::asset::Asset;
+use bevy::reflect::TypePath;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Generators are the parameters of SoundFont zones, identified by their index
+type Generators = [Option<i16>; GENERATOR_COUNT];
+
+const GENERATOR_COUNT: usize = 61;
+const START_OFFSET: usize = 0;
+const END_OFFSET: usize = 1;
+const LOOP_START_OFFSET: usize = 2;
+const LOOP_END_OFFSET: usize = 3;
+const START_COARSE_OFFSET: usize = 4;
+const END_COARSE_OFFSET: usize = 12;
+const PAN: usize = 17;
+const DELAY_VOLUME_ENVELOPE: usize = 33;
+const ATTACK_VOLUME_ENVELOPE: usize = 34;
+const HOLD_VOLUME_ENVELOPE: usize = 35;
+const DECAY_VOLUME_ENVELOPE: usize = 36;
+const SUSTAIN_VOLUME_ENVELOPE: usize = 37;
+const RELEASE_VOLUME_ENVELOPE: usize = 38;
+const INSTRUMENT: usize = 41;
+const KEY_RANGE: usize = 43;
+const VELOCITY_RANGE: usize = 44;
+const LOOP_START_COARSE_OFFSET: usize = 45;
+const INITIAL_ATTENUATION: usize = 48;
+const LOOP_END_COARSE_OFFSET: usize = 50;
+const COARSE_TUNE: usize = 51;
+const FINE_TUNE: usize = 52;
+const SAMPLE_ID: usize = 53;
+const SAMPLE_MODES: usize = 54;
+const SCALE_TUNING: usize = 56;
+const OVERRIDING_ROOT_KEY: usize = 58;
+
+/// Instruments loaded from a SoundFont 2 (`.sf2`) file
+///
+/// Play MIDI with these instruments through a [`MidiPlayer`](crate::MidiPlayer). Samples,
+/// key and velocity ranges, tuning, attenuation, panning, loops, and the volume envelope of
+/// the instruments are used. Modulators, filters, and effects are ignored.
+#[derive(Clone, Asset, TypePath)]
+pub struct SoundFont {
+    pub(crate) data: Arc<SoundFontData>,
+}
+
+impl SoundFont {
+    /// Name of the preset with the given bank and program number
+    pub fn preset_name(&self, bank: u16, program: u8) -> Option<&str> {
+        self.data
+            .presets
+            .iter()
+            .find(|preset| preset.bank == bank && preset.program == program as u16)
+            .map(|preset| preset.name.as_str())
+    }
+}
+
+/// How a zone loops its sample
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LoopMode {
+    None,
+    Continuous,
+    UntilRelease,
+}
+
+/// Volume envelope of a zone with durations in seconds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct VolumeEnvelope {
+    pub(crate) delay: f64,
+    pub(crate) attack: f64,
+    pub(crate) hold: f64,
+    pub(crate) decay: f64,
+    /// Level from 0.0 to 1.0
+    pub(crate) sustain: f32,
+    pub(crate) release: f64,
+}
+
+/// A sample played for a range of keys and velocities
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Zone {
+    pub(crate) keys: (u8, u8),
+    pub(crate) velocities: (u8, u8),
+    /// Index of the first sample in the sample data
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) loop_start: usize,
+    pub(crate) loop_end: usize,
+    pub(crate) loop_mode: LoopMode,
+    pub(crate) sample_rate: f64,
+    pub(crate) root_key: u8,
+    /// Tuning in cents
+    pub(crate) tune: f64,
+    /// Cents per key
+    pub(crate) scale_tuning: f64,
+    /// Gain factor
+    pub(crate) attenuation: f32,
+    /// From -1.0 (left) to 1.0 (right)
+    pub(crate) pan: f32,
+    pub(crate) envelope: VolumeEnvelope,
+}
+
+impl Zone {
+    fn contains(&self, key: u8, velocity: u8) -> bool {
+        (self.keys.0..=self.keys.1).contains(&key)
+            && (self.velocities.0..=self.velocities.1).contains(&velocity)
+    }
+}
+
+#[derive(Debug)]
+struct Preset {
+    name: String,
+    bank: u16,
+    program: u16,
+    zones: Vec<Zone>,
+}
+
+/// Parsed SoundFont shared by all players
+#[derive(Debug)]
+pub(crate) struct SoundFontData {
+    pub(crate) samples: Vec<f32>,
+    presets: Vec<Preset>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct SampleHeader {
+    start: usize,
+    end: usize,
+    loop_start: usize,
+    loop_end: usize,
+    sample_rate: u32,
+    original_pitch: u8,
+    pitch_correction: i8,
+    sample_type: u16,
+}
+
+impl SoundFontData {
+    /// Zones of the matching preset that play the given key and velocity
+    ///
+    /// Falls back to the first bank for unknown presets and to the first preset after that.
+    pub(crate) fn zones(
+        &self,
+        bank: u16,
+        program: u8,
+        key: u8,
+        velocity: u8,
+    ) -> impl Iterator<Item = &Zone> {
+        let find = |bank: u16, program: u16| {
+            self.presets
+                .iter()
+                .find(|preset| preset.bank == bank && preset.program == program)
+        };
+        let fallback_bank = if bank == 128 { 128 } else { 0 };
+        let preset = find(bank, program as u16)
+            .or_else(|| find(fallback_bank, program as u16))
+            .or_else(|| find(fallback_bank, 0))
+            .or(self.presets.first());
+
+        preset
+            .into_iter()
+            .flat_map(|preset| &preset.zones)
+            .filter(move |zone| zone.contains(key, velocity))
+    }
+
+    /// Parse a SoundFont 2 file
+    pub(crate) fn parse(bytes: &[u8]) -> Result<SoundFontData, SoundFontLoaderError> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"sfbk" {
+            return Err(SoundFontLoaderError::InvalidFile(
+                "missing the sfbk RIFF header",
+            ));
+        }
+        let mut sample_data = None;
+        let mut preset_data = None;
+        for (id, data) in chunks(&bytes[12..]) {
+            if id != b"LIST" || data.len() < 4 {
+                continue;
+            }
+            match &data[0..4] {
+                b"sdta" => {
+                    sample_data = chunks(&data[4..])
+                        .find(|(id, _)| *id == b"smpl")
+                        .map(|(_, data)| data)
+                }
+                b"pdta" => preset_data = Some(&data[4..]),
+                _ => (),
+            }
+        }
+        let sample_data =
+            sample_data.ok_or(SoundFontLoaderError::InvalidFile("missing the sample data"))?;
+        let preset_data =
+            preset_data.ok_or(SoundFontLoaderError::InvalidFile("missing the preset data"))?;
+        let samples: Vec<f32> = sample_data
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]) as f32 / 32768.)
+            .collect();
+
+        let sub_chunks: HashMap<&[u8; 4], &[u8]> = chunks(preset_data).collect();
+        let records = |id: &[u8; 4], size: usize| {
+            sub_chunks
+                .get(id)
+                .map(|data| data.chunks_exact(size).collect::<Vec<_>>())
+                .filter(|records| !records.is_empty())
+                .ok_or(SoundFontLoaderError::InvalidFile(
+                    "missing or empty preset data chunk",
+                ))
+        };
+        let preset_headers = records(b"phdr", 38)?;
+        let preset_bags = bags(&records(b"pbag", 4)?);
+        let preset_generators = generators(&records(b"pgen", 4)?);
+        let instruments = records(b"inst", 22)?;
+        let instrument_bags = bags(&records(b"ibag", 4)?);
+        let instrument_generators = generators(&records(b"igen", 4)?);
+        let sample_headers: Vec<SampleHeader> = records(b"shdr", 46)?
+            .iter()
+            .map(|header| SampleHeader {
+                start: u32_at(header, 20) as usize,
+                end: u32_at(header, 24) as usize,
+                loop_start: u32_at(header, 28) as usize,
+                loop_end: u32_at(header, 32) as usize,
+                sample_rate: u32_at(header, 36),
+                original_pitch: header[40],
+                pitch_correction: header[41] as i8,
+                sample_type: u16_at(header, 44),
+            })
+            .collect();
+
+        // zones of instruments with their global zone applied
+        let instrument_zones =
+            |instrument: usize| -> Result<Vec<Generators>, SoundFontLoaderError> {
+                let start = u16_at(instruments[instrument], 20) as usize;
+                let end =
+                    u16_at(
+                        instruments.get(instrument + 1).ok_or(
+                            SoundFontLoaderError::InvalidFile("invalid instrument index"),
+                        )?,
+                        20,
+                    ) as usize;
+                zone_generators(
+                    start,
+                    end,
+                    &instrument_bags,
+                    &instrument_generators,
+                    SAMPLE_ID,
+                )
+            };
+
+        let mut presets = Vec::with_capacity(preset_headers.len() - 1);
+        for (index, header) in preset_headers[..preset_headers.len() - 1]
+            .iter()
+            .enumerate()
+        {
+            let start = u16_at(header, 24) as usize;
+            let end = u16_at(preset_headers[index + 1], 24) as usize;
+            let mut zones = vec![];
+            for preset_zone in
+                zone_generators(start, end, &preset_bags, &preset_generators, INSTRUMENT)?
+            {
+                let Some(instrument) = preset_zone[INSTRUMENT] else {
+                    continue;
+                };
+                let instrument = instrument as u16 as usize;
+                if instrument + 1 >= instruments.len() {
+                    return Err(SoundFontLoaderError::InvalidFile(
+                        "invalid instrument index",
+                    ));
+                }
+                for instrument_zone in instrument_zones(instrument)? {
+                    let Some(sample) = instrument_zone[SAMPLE_ID] else {
+                        continue;
+                    };
+                    let sample = sample_headers
+                        .get(sample as u16 as usize)
+                        .filter(|sample| sample.sample_type & 0x8000 == 0)
+                        .ok_or(SoundFontLoaderError::InvalidFile("invalid sample index"))?;
+                    zones.push(zone(&preset_zone, &instrument_zone, sample, samples.len()));
+                }
+            }
+            presets.push(Preset {
+                name: String::from_utf8_lossy(&header[0..20])
+                    .trim_end_matches('\0')
+                    .to_owned(),
+                program: u16_at(header, 20),
+                bank: u16_at(header, 22),
+                zones,
+            });
+        }
+
+        Ok(SoundFontData { samples, presets })
+    }
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// RIFF chunks with their id and data
+fn chunks(mut bytes: &[u8]) -> impl Iterator<Item = (&[u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        let id: &[u8; 4] = bytes.get(0..4)?.try_into().ok()?;
+        let size = u32_at(bytes.get(0..8)?, 4) as usize;
+        let end = size.checked_add(8)?;
+        let data = bytes.get(8..end)?;
+        // chunks are padded to an even size
+        bytes = bytes
+            .get(end.saturating_add(size % 2)..)
+            .unwrap_or_default();
+
+        Some((id, data))
+    })
+}
+
+/// Index of the first generator of each zone
+fn bags(records: &[&[u8]]) -> Vec<usize> {
+    records
+        .iter()
+        .map(|record| u16_at(record, 0) as usize)
+        .collect()
+}
+
+fn generators(records: &[&[u8]]) -> Vec<(u16, i16)> {
+    records
+        .iter()
+        .map(|record| (u16_at(record, 0), u16_at(record, 2) as i16))
+        .collect()
+}
+
+/// Generators of the zones from `start` to `end`
+///
+/// A first zone without the `terminal` generator is a global zone. Its generators are the
+/// defaults of the other zones.
+fn zone_generators(
+    start: usize,
+    end: usize,
+    bags: &[usize],
+    generators: &[(u16, i16)],
+    terminal: usize,
+) -> Result<Vec<Generators>, SoundFontLoaderError> {
+    let invalid = || SoundFontLoaderError::InvalidFile("invalid zone index");
+    let mut zones = Vec::with_capacity(end.saturating_sub(start));
+    let mut global = [None; GENERATOR_COUNT];
+    for bag in start..end {
+        let first = *bags.get(bag).ok_or_else(invalid)?;
+        let last = *bags.get(bag + 1).ok_or_else(invalid)?;
+        let mut zone = global;
+        for (generator, amount) in generators.get(first..last).ok_or_else(invalid)? {
+            if let Some(value) = zone.get_mut(*generator as usize) {
+                *value = Some(*amount);
+            }
+        }
+        if zone[terminal].is_none() {
+            if bag == start {
+                global = zone;
+            }
+            continue;
+        }
+        zones.push(zone);
+    }
+
+    Ok(zones)
+}
+
+/// Intersection of the key or velocity ranges of a preset and an instrument zone
+fn range(preset: Option<i16>, instrument: Option<i16>) -> (u8, u8) {
+    let bounds = |amount: Option<i16>| {
+        amount.map_or((0, 127), |amount| {
+            let [low, high] = amount.to_le_bytes();
+            (low, high)
+        })
+    };
+    let (preset_low, preset_high) = bounds(preset);
+    let (instrument_low, instrument_high) = bounds(instrument);
+
+    (
+        preset_low.max(instrument_low),
+        preset_high.min(instrument_high),
+    )
+}
+
+fn zone(
+    preset: &Generators,
+    instrument: &Generators,
+    sample: &SampleHeader,
+    sample_count: usize,
+) -> Zone {
+    // preset generators are added to those of the instrument
+    let value = |generator: usize, default: i16| {
+        instrument[generator].unwrap_or(default) as f64 + preset[generator].unwrap_or(0) as f64
+    };
+    let offset = |fine: usize, coarse: usize| {
+        instrument[fine].unwrap_or(0) as i64 + instrument[coarse].unwrap_or(0) as i64 * 32768
+    };
+    let address =
+        |base: usize, offset: i64| (base as i64 + offset).clamp(0, sample_count as i64) as usize;
+    let seconds = |generator: usize| 2f64.powf(value(generator, -12000) / 1200.);
+    let side_pan = match sample.sample_type & 0x7 {
+        2 => 1.,
+        4 => -1.,
+        _ => 0.,
+    };
+    let root_key = instrument[OVERRIDING_ROOT_KEY]
+        .filter(|key| (0..128).contains(key))
+        .map_or(sample.original_pitch, |key| key as u8);
+
+    Zone {
+        keys: range(preset[KEY_RANGE], instrument[KEY_RANGE]),
+        velocities: range(preset[VELOCITY_RANGE], instrument[VELOCITY_RANGE]),
+        start: address(sample.start, offset(START_OFFSET, START_COARSE_OFFSET)),
+        end: address(sample.end, offset(END_OFFSET, END_COARSE_OFFSET)),
+        loop_start: address(
+            sample.loop_start,
+            offset(LOOP_START_OFFSET, LOOP_START_COARSE_OFFSET),
+        ),
+        loop_end: address(
+            sample.loop_end,
+            offset(LOOP_END_OFFSET, LOOP_END_COARSE_OFFSET),
+        ),
+        loop_mode: match instrument[SAMPLE_MODES].unwrap_or(0) & 3 {
+            1 => LoopMode::Continuous,
+            3 => LoopMode::UntilRelease,
+            _ => LoopMode::None,
+        },
+        sample_rate: sample.sample_rate.max(1) as f64,
+        // a root key of 255 means the sample is not pitched
+        root_key: if root_key > 127 { 60 } else { root_key },
+        tune: value(COARSE_TUNE, 0) * 100. + value(FINE_TUNE, 0) + sample.pitch_correction as f64,
+        scale_tuning: instrument[SCALE_TUNING].unwrap_or(100) as f64,
+        attenuation: 10f32.powf(-(value(INITIAL_ATTENUATION, 0).clamp(0., 1440.) as f32) / 200.),
+        pan: (value(PAN, 0) as f32 / 500. + side_pan).clamp(-1., 1.),
+        envelope: VolumeEnvelope {
+            delay: seconds(DELAY_VOLUME_ENVELOPE),
+            attack: seconds(ATTACK_VOLUME_ENVELOPE),
+            hold: seconds(HOLD_VOLUME_ENVELOPE),
+            decay: seconds(DECAY_VOLUME_ENVELOPE),
+            sustain: 10f32
+                .powf(-(value(SUSTAIN_VOLUME_ENVELOPE, 0).clamp(0., 1440.) as f32) / 200.),
+            release: seconds(RELEASE_VOLUME_ENVELOPE),
+        },
+    }
+}
+
+#[cfg(test)]
+impl SoundFontData {
+    /// Sound font with a single preset playing a looped square wave on all keys
+    pub(crate) fn square_wave() -> SoundFontData {
+        let samples: Vec<f32> = (0..100)
+            .map(|index| if index % 10 < 5 { 0.5 } else { -0.5 })
+            .collect();
+        let zone = Zone {
+            keys: (0, 127),
+            velocities: (0, 127),
+            start: 0,
+            end: samples.len(),
+            loop_start: 0,
+            loop_end: samples.len(),
+            loop_mode: LoopMode::Continuous,
+            sample_rate: 1000.,
+            root_key: 60,
+            tune: 0.,
+            scale_tuning: 100.,
+            attenuation: 1.,
+            pan: 0.,
+            envelope: VolumeEnvelope {
+                delay: 0.,
+                attack: 0.,
+                hold: 0.,
+                decay: 0.,
+                sustain: 1.,
+                release: 0.01,
+            },
+        };
+
+        SoundFontData {
+            samples,
+            presets: vec![Preset {
+                name: "Square".to_owned(),
+                bank: 0,
+                program: 0,
+                zones: vec![zone],
+            }],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+
+        chunk
+    }
+
+    fn list(kind: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = kind.to_vec();
+        for sub_chunk in chunks {
+            data.extend_from_slice(sub_chunk);
+        }
+
+        chunk(b"LIST", &data)
+    }
+
+    fn name(name: &str) -> Vec<u8> {
+        let mut bytes = name.as_bytes().to_vec();
+        bytes.resize(20, 0);
+
+        bytes
+    }
+
+    fn words(words: &[u16]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    /// Preset with an instrument zone for keys up to 60 and a global attenuation of 6 dB
+    fn sound_font() -> Vec<u8> {
+        let samples: Vec<u8> = (0..146i16)
+            .flat_map(|index| (if index < 100 { index * 100 } else { 0 }).to_le_bytes())
+            .collect();
+        let mut phdr = name("Square");
+        phdr.extend(words(&[0, 0, 0, 0, 0, 0, 0, 0, 0]));
+        phdr.extend(name("EOP"));
+        phdr.extend(words(&[0, 0, 1, 0, 0, 0, 0, 0, 0]));
+        let mut inst = name("Square");
+        inst.extend(words(&[0]));
+        inst.extend(name("EOI"));
+        inst.extend(words(&[2]));
+        let mut shdr = name("Square");
+        shdr.extend(words(&[0, 0, 100, 0, 20, 0, 80, 0, 22050, 0]));
+        shdr.extend([69, 0]);
+        shdr.extend(words(&[0, 1]));
+        shdr.extend(name("EOS"));
+        shdr.extend([0; 26]);
+
+        let mut bytes = b"sfbk".to_vec();
+        bytes.extend(list(b"INFO", &[chunk(b"ifil", &words(&[2, 1]))]));
+        bytes.extend(list(b"sdta", &[chunk(b"smpl", &samples)]));
+        bytes.extend(list(
+            b"pdta",
+            &[
+                chunk(b"phdr", &phdr),
+                chunk(b"pbag", &words(&[0, 0, 1, 0])),
+                chunk(b"pmod", &[0; 10]),
+                chunk(b"pgen", &words(&[41, 0, 0, 0])),
+                chunk(b"inst", &inst),
+                chunk(b"ibag", &words(&[0, 0, 1, 0, 4, 0])),
+                chunk(b"imod", &[0; 10]),
+                chunk(b"igen", &words(&[48, 60, 43, 60 << 8, 54, 1, 53, 0, 0, 0])),
+                chunk(b"shdr", &shdr),
+            ],
+        ));
+
+        chunk(b"RIFF", &bytes)
+    }
+
+    #[test]
+    fn parses_presets_and_zones() {
+        let data = SoundFontData::parse(&sound_font()).unwrap();
+
+        assert_eq!(data.samples.len(), 146);
+        assert_eq!(data.presets.len(), 1);
+        assert_eq!(data.presets[0].name, "Square");
+        let zones: Vec<_> = data.zones(0, 0, 60, 100).collect();
+        assert_eq!(zones.len(), 1);
+        assert_eq!(zones[0].keys, (0, 60));
+        assert_eq!((zones[0].loop_start, zones[0].loop_end), (20, 80));
+        assert_eq!(zones[0].loop_mode, LoopMode::Continuous);
+        assert_eq!(zones[0].root_key, 69);
+        assert_eq!(zones[0].sample_rate, 22050.);
+        assert!((zones[0].attenuation - 0.501).abs() < 0.001);
+        assert_eq!(data.zones(0, 0, 61, 100).count(), 0);
+    }
+
+    #[test]
+    fn falls_back_to_the_first_preset() {
+        let data = SoundFontData::parse(&sound_font()).unwrap();
+
+        assert_eq!(data.zones(3, 42, 40, 100).count(), 1);
+    }
+
+    #[test]
+    fn rejects_other_riff_files() {
+        let bytes = chunk(b"RIFF", b"WAVEfmt ");
+
+        assert!(matches!(
+            SoundFontData::parse(&bytes),
+            Err(SoundFontLoaderError::InvalidFile(_))
+        ));
+    }
+
+    #[test]
+    fn stops_at_chunks_larger_than_the_file() {
+        let mut bytes = b"data".to_vec();
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+
+        assert_eq!(chunks(&bytes).count(), 0);
+    }
+}
@@ -0,0 +1,569 @@
+//! Sounds generated from oscillators and noise
+
+use crate::AudioTween;
+use crate::rng::AudioRng;
+use crate::source::custom::{CustomAudioSource, Tweened};
+use kira::info::Info;
+use kira::sound::{Sound, SoundData};
+use kira::{Frame, Tween};
+use parking_lot::Mutex;
+use std::convert::Infallible;
+use std::f64::consts::TAU;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Shape of an oscillator
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SynthWaveform {
+    /// Pure tone
+    Sine,
+    /// Hollow tone switching between the highest and lowest value
+    Square,
+    /// Bright tone rising linearly and dropping at the end of each cycle
+    Sawtooth,
+    /// Soft tone rising and falling linearly
+    Triangle,
+}
+
+impl SynthWaveform {
+    /// Value of the waveform at the given phase in cycles (from 0.0 to 1.0)
+    fn sample(self, phase: f64) -> f64 {
+        match self {
+            SynthWaveform::Sine => (phase * TAU).sin(),
+            SynthWaveform::Square => {
+                if phase < 0.5 {
+                    1.
+                } else {
+                    -1.
+                }
+            }
+            SynthWaveform::Sawtooth => 2. * phase - 1.,
+            SynthWaveform::Triangle => 1. - 4. * (phase - 0.5).abs(),
+        }
+    }
+}
+
+/// Spectrum of a noise generator
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoiseColor {
+    /// Equal energy at all frequencies
+    White,
+    /// Energy falling by 3 dB per octave
+    Pink,
+    /// Energy falling by 6 dB per octave
+    Brown,
+}
+
+/// Attack, decay, sustain, and release envelope for the gain of a [`Synth`]
+#[derive(Clone, Debug)]
+pub struct AdsrEnvelope {
+    /// Time to rise from silence to full gain
+    pub attack: Duration,
+    /// Time to fall from full gain to the sustain level
+    pub decay: Duration,
+    /// Gain factor held until the synth is released
+    pub sustain: f32,
+    /// Time to fall from the current level to silence after the synth is released
+    pub release: Duration,
+}
+
+impl Default for AdsrEnvelope {
+    fn default() -> Self {
+        AdsrEnvelope {
+            attack: Duration::from_millis(10),
+            decay: Duration::from_millis(100),
+            sustain: 0.7,
+            release: Duration::from_millis(200),
+        }
+    }
+}
+
+impl AdsrEnvelope {
+    /// Create an envelope
+    pub fn new(attack: Duration, decay: Duration, sustain: f32, release: Duration) -> Self {
+        AdsrEnvelope {
+            attack,
+            decay,
+            sustain,
+            release,
+        }
+    }
+
+    /// Level of the envelope the given number of seconds after the start
+    fn level(&self, time: f64) -> f32 {
+        let attack = self.attack.as_secs_f64();
+        let decay = self.decay.as_secs_f64();
+        if time < attack {
+            return (time / attack) as f32;
+        }
+        if time < attack + decay {
+            let progress = ((time - attack) / decay) as f32;
+            return 1. - (1. - self.sustain) * progress;
+        }
+
+        self.sustain
+    }
+
+    /// Level of the envelope the given number of seconds after releasing it at the given level
+    fn release_level(&self, level: f32, since_release: f64) -> f32 {
+        let release = self.release.as_secs_f64();
+        if since_release >= release {
+            return 0.;
+        }
+
+        level * (1. - (since_release / release) as f32)
+    }
+}
+
+/// Frequency modulation of an oscillator by a sine wave
+#[derive(Clone, Debug)]
+pub struct FrequencyModulation {
+    /// Frequency of the modulating sine wave relative to the frequency of the oscillator
+    pub ratio: f64,
+    /// Depth of the modulation; higher values give brighter and more metallic sounds
+    pub index: f64,
+}
+
+impl FrequencyModulation {
+    /// Create a frequency modulation
+    pub fn new(ratio: f64, index: f64) -> Self {
+        FrequencyModulation { ratio, index }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum SynthLayerKind {
+    Oscillator {
+        waveform: SynthWaveform,
+        frequency_ratio: f64,
+        fm: Option<FrequencyModulation>,
+    },
+    Noise(NoiseColor),
+}
+
+/// An oscillator or noise generator of a [`Synth`]
+#[derive(Clone, Debug)]
+pub struct SynthLayer {
+    kind: SynthLayerKind,
+    gain: f32,
+}
+
+impl SynthLayer {
+    /// Oscillator playing at the frequency of the synth
+    pub fn oscillator(waveform: SynthWaveform) -> Self {
+        SynthLayer {
+            kind: SynthLayerKind::Oscillator {
+                waveform,
+                frequency_ratio: 1.,
+                fm: None,
+            },
+            gain: 1.,
+        }
+    }
+
+    /// Noise generator
+    ///
+    /// Noise ignores the frequency of the synth.
+    pub fn noise(color: NoiseColor) -> Self {
+        SynthLayer {
+            kind: SynthLayerKind::Noise(color),
+            gain: 1.,
+        }
+    }
+
+    /// Set the gain factor of this layer
+    pub fn with_gain(mut self, gain: f32) -> Self {
+        self.gain = gain;
+
+        self
+    }
+
+    /// Play an oscillator at a multiple of the frequency of the synth
+    ///
+    /// Does nothing for noise.
+    pub fn with_frequency_ratio(mut self, ratio: f64) -> Self {
+        if let SynthLayerKind::Oscillator {
+            frequency_ratio, ..
+        } = &mut self.kind
+        {
+            *frequency_ratio = ratio;
+        }
+
+        self
+    }
+
+    /// Modulate the frequency of an oscillator
+    ///
+    /// Does nothing for noise.
+    pub fn with_fm(mut self, modulation: FrequencyModulation) -> Self {
+        if let SynthLayerKind::Oscillator { fm, .. } = &mut self.kind {
+            *fm = Some(modulation);
+        }
+
+        self
+    }
+}
+
+/// Sound generated from oscillators and noise
+///
+/// All layers are mixed and shaped by an optional [`AdsrEnvelope`]. Synths with a duration are
+/// released after it passed and stop at the end of the release. Play them by converting them into
+/// a [`CustomAudioSource`]. Frequency and gain of a playing synth can be tweened with
+/// [`AudioInstance::set_synth_frequency`](crate::AudioInstance::set_synth_frequency) and
+/// [`AudioInstance::set_synth_gain`](crate::AudioInstance::set_synth_gain).
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_kira_audio::prelude::*;
+/// # use std::time::Duration;
+/// fn play_beep(mut custom_sources: ResMut<Assets<CustomAudioSource>>, audio: Res<Audio>) {
+///     let beep = Synth::oscillator(SynthWaveform::Square, 880.)
+///         .with_gain(0.3)
+///         .with_envelope(AdsrEnvelope::default())
+///         .with_duration(Duration::from_millis(150));
+///     audio.play_custom(custom_sources.add(beep));
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Synth {
+    frequency: f64,
+    gain: f32,
+    layers: Vec<SynthLayer>,
+    envelope: Option<AdsrEnvelope>,
+    duration: Option<Duration>,
+    seed: Option<u64>,
+}
+
+impl Synth {
+    /// Create a synth without layers playing at the given frequency in Hz
+    pub fn new(frequency: f64) -> Self {
+        Synth {
+            frequency,
+            gain: 1.,
+            layers: vec![],
+            envelope: None,
+            duration: None,
+            seed: None,
+        }
+    }
+
+    /// Create a synth with a single oscillator
+    pub fn oscillator(waveform: SynthWaveform, frequency: f64) -> Self {
+        Synth::new(frequency).with_layer(SynthLayer::oscillator(waveform))
+    }
+
+    /// Create a synth with a single noise generator
+    pub fn noise(color: NoiseColor) -> Self {
+        Synth::new(440.).with_layer(SynthLayer::noise(color))
+    }
+
+    /// Add a layer
+    pub fn with_layer(mut self, layer: SynthLayer) -> Self {
+        self.layers.push(layer);
+
+        self
+    }
+
+    /// Set the gain factor of the whole synth
+    pub fn with_gain(mut self, gain: f32) -> Self {
+        self.gain = gain;
+
+        self
+    }
+
+    /// Shape the gain with an envelope
+    pub fn with_envelope(mut self, envelope: AdsrEnvelope) -> Self {
+        self.envelope = Some(envelope);
+
+        self
+    }
+
+    /// Release the synth after the given duration
+    ///
+    /// Without a duration, the synth plays until it is stopped or released.
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+
+        self
+    }
+
+    /// Seed the noise generators to make them reproducible
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+
+        self
+    }
+}
+
+impl From<Synth> for CustomAudioSource {
+    fn from(synth: Synth) -> Self {
+        CustomAudioSource::new(move || synth.clone())
+    }
+}
+
+impl SoundData for Synth {
+    type Error = Infallible;
+    type Handle = SynthHandle;
+
+    fn into_sound(self) -> Result<(Box<dyn Sound>, Self::Handle), Self::Error> {
+        let commands = Arc::new(Mutex::new(vec![]));
+        let sound = SynthSound::new(self, commands.clone());
+
+        Ok((Box::new(sound), SynthHandle { commands }))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum SynthCommand {
+    SetFrequency(f64, Tween),
+    SetGain(f32, Tween),
+    Release,
+}
+
+/// Controls a playing [`Synth`]
+///
+/// Get it from the playing instance with
+/// [`AudioInstance::custom_handle`](crate::AudioInstance::custom_handle).
+pub struct SynthHandle {
+    commands: Arc<Mutex<Vec<SynthCommand>>>,
+}
+
+impl SynthHandle {
+    /// Tween the frequency in Hz
+    pub fn set_frequency(&mut self, frequency: f64, tween: AudioTween) {
+        self.commands
+            .lock()
+            .push(SynthCommand::SetFrequency(frequency, tween.into()));
+    }
+
+    /// Tween the gain factor
+    pub fn set_gain(&mut self, gain: f32, tween: AudioTween) {
+        self.commands
+            .lock()
+            .push(SynthCommand::SetGain(gain, tween.into()));
+    }
+
+    /// Start the release of the envelope
+    ///
+    /// Synths without an envelope stop right away.
+    pub fn release(&mut self) {
+        self.commands.lock().push(SynthCommand::Release);
+    }
+}
+
+/// State of a single layer on the audio thread
+#[derive(Default)]
+struct LayerState {
+    phase: f64,
+    modulator_phase: f64,
+    /// Filter state for colored noise
+    noise: [f64; 3],
+}
+
+struct SynthSound {
+    synth: Synth,
+    commands: Arc<Mutex<Vec<SynthCommand>>>,
+    layers: Vec<LayerState>,
+    rng: AudioRng,
+    frequency: Tweened,
+    gain: Tweened,
+    time: f64,
+    /// Time and envelope level of the release
+    released: Option<(f64, f32)>,
+}
+
+impl SynthSound {
+    fn new(synth: Synth, commands: Arc<Mutex<Vec<SynthCommand>>>) -> Self {
+        let rng = synth.seed.map(AudioRng::from_seed).unwrap_or_default();
+
+        SynthSound {
+            layers: synth.layers.iter().map(|_| LayerState::default()).collect(),
+            rng,
+            frequency: Tweened::new(synth.frequency),
+            gain: Tweened::new(synth.gain as f64),
+            time: 0.,
+            released: None,
+            synth,
+            commands,
+        }
+    }
+
+    fn envelope_level(&self) -> f32 {
+        let Some(envelope) = &self.synth.envelope else {
+            return if self.released.is_some() { 0. } else { 1. };
+        };
+        match self.released {
+            Some((time, level)) => envelope.release_level(level, self.time - time),
+            None => envelope.level(self.time),
+        }
+    }
+
+    fn release(&mut self) {
+        if self.released.is_none() {
+            self.released = Some((self.time, self.envelope_level()));
+        }
+    }
+
+    fn next_frame(&mut self, dt: f64) -> Frame {
+        if self
+            .synth
+            .duration
+            .is_some_and(|duration| self.time >= duration.as_secs_f64())
+        {
+            self.release();
+        }
+        let frequency = self.frequency.update(dt);
+        let gain = self.gain.update(dt) as f32 * self.envelope_level();
+        let mut value = 0.;
+        for (layer, state) in self.synth.layers.iter().zip(self.layers.iter_mut()) {
+            let sample = match &layer.kind {
+                SynthLayerKind::Oscillator {
+                    waveform,
+                    frequency_ratio,
+                    fm,
+                } => {
+                    let frequency = frequency * frequency_ratio;
+                    let mut phase_offset = 0.;
+                    if let Some(fm) = fm {
+                        state.modulator_phase =
+                            (state.modulator_phase + frequency * fm.ratio * dt).fract();
+                        phase_offset = fm.index * (state.modulator_phase * TAU).sin() / TAU;
+                    }
+                    let sample = waveform.sample((state.phase + phase_offset).rem_euclid(1.));
+                    state.phase = (state.phase + frequency * dt).fract();
+                    sample
+                }
+                SynthLayerKind::Noise(color) => {
+                    let white = self.rng.next_f64() * 2. - 1.;
+                    noise(*color, white, &mut state.noise)
+                }
+            };
+            value += sample * layer.gain as f64;
+        }
+        self.time += dt;
+
+        Frame::from_mono(value as f32 * gain)
+    }
+}
+
+/// Filter white noise to the given color
+fn noise(color: NoiseColor, white: f64, state: &mut [f64; 3]) -> f64 {
+    match color {
+        NoiseColor::White => white,
+        NoiseColor::Pink => {
+            // Paul Kellet's economy filter
+            state[0] = 0.99765 * state[0] + white * 0.0990460;
+            state[1] = 0.96300 * state[1] + white * 0.2965164;
+            state[2] = 0.57000 * state[2] + white * 1.0526913;
+            (state[0] + state[1] + state[2] + white * 0.1848) * 0.11
+        }
+        NoiseColor::Brown => {
+            state[0] = (state[0] + 0.02 * white) / 1.02;
+            state[0] * 3.5
+        }
+    }
+}
+
+impl Sound for SynthSound {
+    fn on_start_processing(&mut self) {
+        // never block the audio thread; remaining commands are picked up in the next batch
+        let shared = self.commands.clone();
+        let Some(mut commands) = shared.try_lock() else {
+            return;
+        };
+        for command in commands.drain(..) {
+            match command {
+                SynthCommand::SetFrequency(frequency, tween) => {
+                    self.frequency.set(frequency, tween)
+                }
+                SynthCommand::SetGain(gain, tween) => self.gain.set(gain as f64, tween),
+                SynthCommand::Release => self.release(),
+            }
+        }
+    }
+
+    fn process(&mut self, out: &mut [Frame], dt: f64, _info: &Info) {
+        for frame in out {
+            *frame = self.next_frame(dt);
+        }
+    }
+
+    fn finished(&self) -> bool {
+        let Some((time, _)) = self.released else {
+            return false;
+        };
+        let release = self
+            .synth
+            .envelope
+            .as_ref()
+            .map_or(0., |envelope| envelope.release.as_secs_f64());
+
+        self.time - time >= release
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn waveforms_span_full_range() {
+        for waveform in [
+            SynthWaveform::Sine,
+            SynthWaveform::Square,
+            SynthWaveform::Sawtooth,
+            SynthWaveform::Triangle,
+        ] {
+            let samples: Vec<f64> = (0..100)
+                .map(|index| waveform.sample(index as f64 / 100.))
+                .collect();
+            let max = samples.iter().cloned().fold(f64::MIN, f64::max);
+            let min = samples.iter().cloned().fold(f64::MAX, f64::min);
+
+            assert!(max > 0.95 && max <= 1., "{waveform:?}");
+            assert!((-1.0..-0.95).contains(&min), "{waveform:?}");
+        }
+    }
+
+    #[test]
+    fn envelope_goes_through_all_stages() {
+        let envelope = AdsrEnvelope::new(
+            Duration::from_secs(1),
+            Duration::from_secs(1),
+            0.5,
+            Duration::from_secs(2),
+        );
+
+        assert!((envelope.level(0.5) - 0.5).abs() < 0.0001);
+        assert!((envelope.level(1.5) - 0.75).abs() < 0.0001);
+        assert_eq!(envelope.level(10.), 0.5);
+        assert!((envelope.release_level(0.5, 1.) - 0.25).abs() < 0.0001);
+        assert_eq!(envelope.release_level(0.5, 3.), 0.);
+    }
+
+    #[test]
+    fn synth_finishes_after_duration_and_release() {
+        let synth = Synth::noise(NoiseColor::Pink)
+            .with_seed(3)
+            .with_envelope(AdsrEnvelope::new(
+                Duration::ZERO,
+                Duration::ZERO,
+                1.,
+                Duration::from_millis(100),
+            ))
+            .with_duration(Duration::from_millis(100));
+        let mut sound = SynthSound::new(synth, Arc::default());
+        let dt = 1. / 1000.;
+
+        let mut audible = false;
+        for _ in 0..100 {
+            audible |= sound.next_frame(dt).left != 0.;
+        }
+        assert!(audible);
+        assert!(!sound.finished());
+        for _ in 0..110 {
+            sound.next_frame(dt);
+        }
+        assert!(sound.finished());
+    }
+}
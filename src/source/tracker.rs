@@ -0,0 +1,1266 @@
+//! Real-time playback of tracker modules
+
+use crate::source::custom::CustomAudioSource;
+use crate::source::mod_loader::ModLoaderError;
+use crate::source::tracker_formats;
+use kira::Frame;
+use kira::info::Info;
+use kira::sound::{Sound, SoundData};
+use parking_lot::Mutex;
+use std::convert::Infallible;
+use std::f64::consts::TAU;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+const ROWS_PER_PATTERN: usize = 64;
+/// Half the clock rate of a PAL Amiga; divided by a period, it gives the sample rate of a note
+const PAL_CLOCK: f64 = 3_546_894.6;
+const MIN_PERIOD: f64 = 113.;
+const MAX_PERIOD: f64 = 856.;
+/// Period of C-5, which plays a sample at its C-5 speed
+const C5_PERIOD: f64 = 428.;
+/// Number of notes from C-0 to B-9
+pub(crate) const KEYS: usize = 120;
+
+/// A sample of a module
+#[derive(Debug, Default)]
+pub(crate) struct ModuleSample {
+    pub(crate) data: Vec<f32>,
+    /// Sample rate when playing C-5
+    pub(crate) c5_speed: f64,
+    /// Finetune in eighths of a semitone
+    pub(crate) finetune: i8,
+    pub(crate) volume: u8,
+    /// Factor of the volume of all notes from 0 to 64
+    pub(crate) global_volume: u8,
+    /// Panning from 0 (left) to 255 (right) set when the sample starts
+    pub(crate) panning: Option<u8>,
+    pub(crate) loop_start: usize,
+    pub(crate) loop_length: usize,
+}
+
+impl ModuleSample {
+    fn looped(&self) -> bool {
+        self.loop_length > 2
+    }
+
+    /// Append the loop played backwards, so a ping-pong loop can be played as a forward loop
+    pub(crate) fn unroll_ping_pong_loop(&mut self) {
+        if !self.looped() {
+            return;
+        }
+        let loop_end = self.loop_start + self.loop_length;
+        self.data.truncate(loop_end);
+        let backwards: Vec<f32> = self.data[self.loop_start + 1..loop_end - 1]
+            .iter()
+            .rev()
+            .copied()
+            .collect();
+        self.loop_length += backwards.len();
+        self.data.extend(backwards);
+    }
+}
+
+/// Volume envelope of an instrument
+#[derive(Debug, Default)]
+pub(crate) struct Envelope {
+    /// Ticks and volumes from 0 to 64
+    pub(crate) points: Vec<(u16, u8)>,
+    /// First and last point of the loop while the note is held
+    pub(crate) sustain: Option<(usize, usize)>,
+    /// First and last point of the loop
+    pub(crate) looped: Option<(usize, usize)>,
+}
+
+impl Envelope {
+    /// Volume from 0 to 1 at the given tick
+    fn value(&self, tick: u16) -> f32 {
+        let Some(next) = self.points.iter().position(|(point, _)| *point > tick) else {
+            return self.points.last().map_or(64, |(_, value)| *value) as f32 / 64.;
+        };
+        if next == 0 {
+            return self.points[0].1 as f32 / 64.;
+        }
+        let (start, from) = self.points[next - 1];
+        let (end, to) = self.points[next];
+        let fraction = (tick - start) as f32 / (end - start) as f32;
+
+        (from as f32 + (to as f32 - from as f32) * fraction) / 64.
+    }
+
+    /// The tick after the given one
+    fn advance(&self, tick: u16, released: bool) -> u16 {
+        let next = tick.saturating_add(1);
+        let sustain = self.sustain.filter(|_| !released);
+        for (start, end) in sustain.into_iter().chain(self.looped) {
+            if let (Some((start, _)), Some((end, _))) =
+                (self.points.get(start), self.points.get(end))
+                && next > *end
+            {
+                return *start;
+            }
+        }
+
+        next
+    }
+}
+
+/// Samples and envelope of an instrument
+#[derive(Debug)]
+pub(crate) struct Instrument {
+    /// Sample (starting at 1) and the key it plays for each key
+    pub(crate) keymap: Vec<(u8, u8)>,
+    pub(crate) volume_envelope: Option<Envelope>,
+    /// Decrease of the volume per tick after the note was released
+    pub(crate) fadeout: f32,
+    /// Factor of the volume of all notes
+    pub(crate) global_volume: f32,
+}
+
+impl Instrument {
+    /// An instrument playing a single sample on all keys
+    pub(crate) fn sample(sample: u8) -> Self {
+        Instrument {
+            keymap: (0..KEYS as u8).map(|key| (key, sample)).collect(),
+            volume_envelope: None,
+            fadeout: 0.,
+            global_volume: 1.,
+        }
+    }
+}
+
+/// Pitch column of a note
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Pitch {
+    #[default]
+    None,
+    /// Amiga period of a ProTracker note
+    Period(u16),
+    /// Key from 0 (C-0) to 119 (B-9)
+    Key(u8),
+    /// Release the note
+    Off,
+    /// Stop the note
+    Cut,
+    /// Fade the note out
+    Fade,
+}
+
+/// Volume column of a note
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum VolumeCommand {
+    #[default]
+    None,
+    Set(u8),
+    SlideUp(u8),
+    SlideDown(u8),
+    FineSlideUp(u8),
+    FineSlideDown(u8),
+    /// Panning from 0 (left) to 255 (right)
+    Panning(u8),
+}
+
+/// Effect of a note
+///
+/// Volume slides use the parameters of Scream Tracker: `x0` slides up and `0y` slides down on
+/// all but the first tick, while `xF` and `Fy` slide once on the first tick.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Effect {
+    #[default]
+    None,
+    Arpeggio(u8),
+    PortaUp(u8),
+    PortaDown(u8),
+    FinePortaUp(u8),
+    FinePortaDown(u8),
+    ExtraFinePortaUp(u8),
+    ExtraFinePortaDown(u8),
+    /// Slide up with fine (`Fx`) and extra fine (`Ex`) slides in the parameter
+    PackedPortaUp(u8),
+    /// Slide down with fine (`Fx`) and extra fine (`Ex`) slides in the parameter
+    PackedPortaDown(u8),
+    TonePortamento(u8),
+    Vibrato(u8),
+    TonePortamentoVolumeSlide(u8),
+    VibratoVolumeSlide(u8),
+    /// Panning from 0 (left) to 255 (right)
+    SetPanning(u8),
+    SampleOffset(u8),
+    VolumeSlide(u8),
+    PositionJump(u8),
+    SetVolume(u8),
+    PatternBreak(u8),
+    SetSpeed(u8),
+    SetTempo(u8),
+    NoteCut(u8),
+    NoteDelay(u8),
+    KeyOff(u8),
+    /// Global volume from 0 to 128
+    GlobalVolume(u8),
+}
+
+impl Effect {
+    /// Convert an effect of ProTracker or FastTracker 2
+    pub(crate) fn protracker(effect: u8, param: u8) -> Effect {
+        let (x, y) = (param >> 4, param & 0x0f);
+        // volume slides prefer sliding up, like in ProTracker
+        let volume_slide = if x > 0 { x << 4 } else { y };
+        match effect {
+            0x0 if param > 0 => Effect::Arpeggio(param),
+            0x1 => Effect::PortaUp(param),
+            0x2 => Effect::PortaDown(param),
+            0x3 => Effect::TonePortamento(param),
+            0x4 => Effect::Vibrato(param),
+            0x5 => Effect::TonePortamentoVolumeSlide(volume_slide),
+            0x6 => Effect::VibratoVolumeSlide(volume_slide),
+            0x8 => Effect::SetPanning(param),
+            0x9 => Effect::SampleOffset(param),
+            0xa => Effect::VolumeSlide(volume_slide),
+            0xb => Effect::PositionJump(param),
+            0xc => Effect::SetVolume(param.min(64)),
+            0xd => Effect::PatternBreak(x * 10 + y),
+            0xe => match x {
+                0x1 => Effect::FinePortaUp(y),
+                0x2 => Effect::FinePortaDown(y),
+                0xa if y > 0 => Effect::VolumeSlide((y << 4) | 0xf),
+                0xb if y > 0 => Effect::VolumeSlide(0xf0 | y),
+                0xc => Effect::NoteCut(y),
+                0xd => Effect::NoteDelay(y),
+                _ => Effect::None,
+            },
+            0xf if param > 0 && param < 32 => Effect::SetSpeed(param),
+            0xf if param >= 32 => Effect::SetTempo(param),
+            // Gxx
+            0x10 => Effect::GlobalVolume(param.min(64) * 2),
+            // Kxx
+            0x14 => Effect::KeyOff(param),
+            // X1y and X2y
+            0x21 if x == 1 => Effect::ExtraFinePortaUp(y),
+            0x21 if x == 2 => Effect::ExtraFinePortaDown(y),
+            _ => Effect::None,
+        }
+    }
+
+    /// Convert an effect of Scream Tracker 3 or Impulse Tracker, where command 1 is `A`
+    pub(crate) fn scream_tracker(command: u8, param: u8, impulse_tracker: bool) -> Effect {
+        let (x, y) = (param >> 4, param & 0x0f);
+        match command {
+            1 if param > 0 => Effect::SetSpeed(param),
+            2 => Effect::PositionJump(param),
+            // Scream Tracker writes the row in decimal digits
+            3 if impulse_tracker => Effect::PatternBreak(param),
+            3 => Effect::PatternBreak(x * 10 + y),
+            4 => Effect::VolumeSlide(param),
+            5 => Effect::PackedPortaDown(param),
+            6 => Effect::PackedPortaUp(param),
+            7 => Effect::TonePortamento(param),
+            8 => Effect::Vibrato(param),
+            10 => Effect::Arpeggio(param),
+            11 => Effect::VibratoVolumeSlide(param),
+            12 => Effect::TonePortamentoVolumeSlide(param),
+            15 => Effect::SampleOffset(param),
+            19 => match x {
+                0x8 => Effect::SetPanning(y * 17),
+                0xc => Effect::NoteCut(y),
+                0xd => Effect::NoteDelay(y),
+                _ => Effect::None,
+            },
+            20 if param >= 0x20 => Effect::SetTempo(param),
+            22 if impulse_tracker => Effect::GlobalVolume(param.min(128)),
+            22 => Effect::GlobalVolume(param.min(64) * 2),
+            24 if impulse_tracker => Effect::SetPanning(param),
+            24 if param <= 0x80 => Effect::SetPanning((param as u16 * 2).min(255) as u8),
+            _ => Effect::None,
+        }
+    }
+
+    fn is_tone_portamento(self) -> bool {
+        matches!(
+            self,
+            Effect::TonePortamento(_) | Effect::TonePortamentoVolumeSlide(_)
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Note {
+    pub(crate) pitch: Pitch,
+    /// Instrument starting at 1
+    pub(crate) instrument: u8,
+    pub(crate) volume: VolumeCommand,
+    pub(crate) effect: Effect,
+}
+
+#[derive(Debug)]
+pub(crate) struct Pattern {
+    pub(crate) rows: usize,
+    /// Notes of all channels, row by row
+    pub(crate) notes: Vec<Note>,
+}
+
+/// A parsed tracker module
+#[derive(Debug)]
+pub(crate) struct Module {
+    pub(crate) channels: usize,
+    pub(crate) samples: Vec<ModuleSample>,
+    pub(crate) instruments: Vec<Instrument>,
+    pub(crate) orders: Vec<u8>,
+    pub(crate) restart: usize,
+    pub(crate) patterns: Vec<Pattern>,
+    pub(crate) speed: u32,
+    pub(crate) tempo: u32,
+    /// Global volume from 0 to 128
+    pub(crate) global_volume: u8,
+    /// Initial panning of each channel from 0 (left) to 1 (right)
+    pub(crate) panning: Vec<f32>,
+    /// Whether pitch slides change the pitch linearly instead of the Amiga period
+    pub(crate) linear_slides: bool,
+    /// Whether effects without parameter use the parameter of the last effect of their kind
+    pub(crate) effect_memory: bool,
+    /// Lowest and highest period of notes
+    pub(crate) period_range: (f64, f64),
+}
+
+impl Module {
+    /// Parse a ProTracker, Scream Tracker 3, FastTracker 2, or Impulse Tracker module
+    pub(crate) fn parse(bytes: &[u8]) -> Result<Module, ModLoaderError> {
+        let module = if bytes.starts_with(b"Extended Module: ") {
+            tracker_formats::parse_xm(bytes)?
+        } else if bytes.starts_with(b"IMPM") {
+            tracker_formats::parse_it(bytes)?
+        } else if bytes.get(44..48) == Some(b"SCRM") {
+            tracker_formats::parse_s3m(bytes)?
+        } else {
+            Module::parse_protracker(bytes)?
+        };
+        if module.orders.is_empty() {
+            return Err(ModLoaderError::InvalidFile("the module has no orders"));
+        }
+
+        Ok(module)
+    }
+
+    /// Parse a ProTracker module with 31 samples
+    fn parse_protracker(bytes: &[u8]) -> Result<Module, ModLoaderError> {
+        let truncated = || ModLoaderError::InvalidFile("the file is truncated");
+        let tag = bytes.get(1080..1084).ok_or_else(truncated)?;
+        let channels = match tag {
+            b"M.K." | b"M!K!" | b"FLT4" | b"4CHN" => 4,
+            b"FLT8" => 8,
+            [digit @ b'1'..=b'9', b'C', b'H', b'N'] => (digit - b'0') as usize,
+            [tens @ b'1'..=b'3', ones @ b'0'..=b'9', b'C', b'H' | b'N'] => {
+                ((tens - b'0') * 10 + (ones - b'0')) as usize
+            }
+            other => {
+                return Err(ModLoaderError::UnsupportedFormat(format!(
+                    "unknown module tag {:?}",
+                    String::from_utf8_lossy(other)
+                )));
+            }
+        };
+        let song_length = (bytes[950] as usize).clamp(1, 128);
+        let restart = bytes[951] as usize;
+        let orders = bytes[952..952 + song_length].to_vec();
+        let pattern_count = bytes[952..1080]
+            .iter()
+            .max()
+            .map_or(0, |max| *max as usize + 1);
+        let pattern_size = ROWS_PER_PATTERN * channels * 4;
+        let mut offset = 1084;
+        let mut patterns = Vec::with_capacity(pattern_count);
+        for _ in 0..pattern_count {
+            let pattern = bytes
+                .get(offset..offset + pattern_size)
+                .ok_or_else(truncated)?;
+            patterns.push(Pattern {
+                rows: ROWS_PER_PATTERN,
+                notes: pattern
+                    .chunks_exact(4)
+                    .map(|note| {
+                        let period = (((note[0] & 0x0f) as u16) << 8) | note[1] as u16;
+                        Note {
+                            pitch: if period > 0 {
+                                Pitch::Period(period)
+                            } else {
+                                Pitch::None
+                            },
+                            instrument: (note[0] & 0xf0) | (note[2] >> 4),
+                            volume: VolumeCommand::None,
+                            effect: Effect::protracker(note[2] & 0x0f, note[3]),
+                        }
+                    })
+                    .collect(),
+            });
+            offset += pattern_size;
+        }
+        let mut samples = Vec::with_capacity(31);
+        for header in bytes[20..950].chunks_exact(30) {
+            let word = |index: usize| u16::from_be_bytes([header[index], header[index + 1]]);
+            let length = word(22) as usize * 2;
+            // samples at the end of damaged files are often cut off
+            let data = bytes
+                .get(offset..(offset + length).min(bytes.len()))
+                .unwrap_or_default();
+            offset += length;
+            let loop_start = word(26) as usize * 2;
+            let loop_length = (word(28) as usize * 2).min(data.len().saturating_sub(loop_start));
+            samples.push(ModuleSample {
+                data: data.iter().map(|byte| *byte as i8 as f32 / 128.).collect(),
+                c5_speed: PAL_CLOCK / C5_PERIOD,
+                // the low nibble is a signed four bit number
+                finetune: (((header[24] & 0x0f) << 4) as i8) >> 4,
+                volume: header[25].min(64),
+                global_volume: 64,
+                panning: None,
+                loop_start,
+                loop_length,
+            });
+        }
+
+        Ok(Module {
+            channels,
+            instruments: (1..=samples.len() as u8).map(Instrument::sample).collect(),
+            samples,
+            orders,
+            restart,
+            patterns,
+            speed: 6,
+            tempo: 125,
+            global_volume: 128,
+            // Amiga channels are panned left, right, right, left; soften that for headphones
+            panning: (0..channels)
+                .map(|index| {
+                    if matches!(index % 4, 0 | 3) {
+                        0.25
+                    } else {
+                        0.75
+                    }
+                })
+                .collect(),
+            linear_slides: false,
+            effect_memory: false,
+            period_range: (MIN_PERIOD, MAX_PERIOD),
+        })
+    }
+
+    fn pattern(&self, order: usize) -> Option<&Pattern> {
+        self.orders
+            .get(order)
+            .and_then(|pattern| self.patterns.get(*pattern as usize))
+    }
+
+    /// Number of rows of the pattern at the given order
+    fn rows(&self, order: usize) -> usize {
+        self.pattern(order)
+            .map_or(ROWS_PER_PATTERN, |pattern| pattern.rows)
+            .max(1)
+    }
+
+    fn note(&self, order: usize, row: usize, channel: usize) -> Note {
+        self.pattern(order)
+            .and_then(|pattern| pattern.notes.get(row * self.channels + channel))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Period of a key, which plays samples at their C-5 speed for C-5
+    pub(crate) fn key_period(key: u8) -> f64 {
+        C5_PERIOD * 2f64.powf((60. - key as f64) / 12.)
+    }
+
+    /// Shift a period by the given number of slide units; positive units lower the pitch
+    ///
+    /// With linear slides, a unit is a sixteenth of a semitone.
+    fn shift_period(&self, period: f64, units: f64) -> f64 {
+        if self.linear_slides {
+            period * 2f64.powf(units / 192.)
+        } else {
+            period + units
+        }
+    }
+
+    /// Slide a period and keep it in the range of notes
+    fn slide_period(&self, period: f64, units: f64) -> f64 {
+        self.shift_period(period, units)
+            .clamp(self.period_range.0, self.period_range.1)
+    }
+}
+
+impl From<Module> for CustomAudioSource {
+    fn from(module: Module) -> Self {
+        let module = Arc::new(module);
+
+        CustomAudioSource::loopable(move |looped| ModuleData {
+            module: module.clone(),
+            looped,
+        })
+    }
+}
+
+/// Position of a playing module
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TrackerPosition {
+    /// Index in the order list of the module
+    pub order: usize,
+    /// Row in the current pattern
+    pub row: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum TrackerCommand {
+    Seek(TrackerPosition),
+    SetMuted(usize, bool),
+}
+
+/// State shared between a playing module and its handle
+struct TrackerControl {
+    commands: Mutex<Vec<TrackerCommand>>,
+    /// Order in the upper and row in the lower half
+    position: AtomicU64,
+    looped: AtomicBool,
+}
+
+/// Controls a playing tracker module
+///
+/// Get it from the playing instance with
+/// [`AudioInstance::custom_handle`](crate::AudioInstance::custom_handle) or use the tracker
+/// methods of [`AudioInstance`](crate::AudioInstance).
+pub struct TrackerHandle {
+    control: Arc<TrackerControl>,
+    channels: usize,
+}
+
+impl TrackerHandle {
+    /// Number of channels of the module
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// The order and row that is currently playing
+    pub fn position(&self) -> TrackerPosition {
+        let position = self.control.position.load(Ordering::Acquire);
+
+        TrackerPosition {
+            order: (position >> 32) as usize,
+            row: (position & 0xffff_ffff) as usize,
+        }
+    }
+
+    /// Continue playing at the given order and row
+    pub fn seek(&mut self, position: TrackerPosition) {
+        self.control
+            .commands
+            .lock()
+            .push(TrackerCommand::Seek(position));
+    }
+
+    /// Mute or unmute a channel of the module
+    pub fn set_channel_muted(&mut self, channel: usize, muted: bool) {
+        self.control
+            .commands
+            .lock()
+            .push(TrackerCommand::SetMuted(channel, muted));
+    }
+
+    /// Whether the module starts over at its restart position once it reaches its end
+    pub fn set_looped(&mut self, looped: bool) {
+        self.control.looped.store(looped, Ordering::Release);
+    }
+}
+
+struct ModuleData {
+    module: Arc<Module>,
+    looped: bool,
+}
+
+impl SoundData for ModuleData {
+    type Error = Infallible;
+    type Handle = TrackerHandle;
+
+    fn into_sound(self) -> Result<(Box<dyn Sound>, Self::Handle), Self::Error> {
+        let control = Arc::new(TrackerControl {
+            commands: Mutex::new(vec![]),
+            position: AtomicU64::new(0),
+            looped: AtomicBool::new(self.looped),
+        });
+        let handle = TrackerHandle {
+            control: control.clone(),
+            channels: self.module.channels,
+        };
+
+        Ok((Box::new(ModulePlayer::new(self.module, control)), handle))
+    }
+}
+
+/// Playback state of a single channel
+#[derive(Clone, Debug, Default)]
+struct Channel {
+    instrument: usize,
+    sample: usize,
+    /// Last played key
+    key: u8,
+    playing: bool,
+    muted: bool,
+    position: f64,
+    /// Period of the last note, changed by slides
+    period: f64,
+    /// Period to play in the current tick, including vibrato and arpeggio
+    output_period: f64,
+    volume: i32,
+    /// Panning from 0 (left) to 1 (right)
+    panning: f32,
+    finetune: i8,
+    porta_target: f64,
+    porta_speed: f64,
+    vibrato_speed: u8,
+    vibrato_depth: u8,
+    vibrato_phase: u8,
+    note: Note,
+    /// Parameters of the last effects without their own memory
+    arpeggio_memory: u8,
+    porta_memory: u8,
+    volume_slide_memory: u8,
+    sample_offset_memory: u8,
+    envelope_tick: u16,
+    released: bool,
+    fading: bool,
+    fade: f32,
+}
+
+struct ModulePlayer {
+    module: Arc<Module>,
+    control: Arc<TrackerControl>,
+    channels: Vec<Channel>,
+    order: usize,
+    row: usize,
+    tick: u32,
+    speed: u32,
+    tempo: u32,
+    global_volume: u8,
+    /// Seconds until the next tick
+    tick_timer: f64,
+    /// Order and row to jump to after the current row
+    jump: Option<(usize, usize)>,
+    finished: bool,
+}
+
+impl ModulePlayer {
+    fn new(module: Arc<Module>, control: Arc<TrackerControl>) -> Self {
+        ModulePlayer {
+            channels: module
+                .panning
+                .iter()
+                .map(|panning| Channel {
+                    panning: *panning,
+                    key: 60,
+                    fade: 1.,
+                    ..Default::default()
+                })
+                .collect(),
+            order: 0,
+            row: 0,
+            tick: 0,
+            speed: module.speed,
+            tempo: module.tempo,
+            global_volume: module.global_volume,
+            module,
+            control,
+            tick_timer: 0.,
+            jump: None,
+            finished: false,
+        }
+    }
+
+    fn tick_duration(&self) -> f64 {
+        2.5 / self.tempo as f64
+    }
+
+    fn run_commands(&mut self) {
+        let control = self.control.clone();
+        // never block the audio thread; remaining commands are picked up in the next batch
+        let Some(mut commands) = control.commands.try_lock() else {
+            return;
+        };
+        for command in commands.drain(..) {
+            match command {
+                TrackerCommand::Seek(position) => {
+                    self.order = position.order.min(self.module.orders.len() - 1);
+                    self.row = position.row.min(self.module.rows(self.order) - 1);
+                    self.tick = 0;
+                    self.tick_timer = 0.;
+                    self.jump = None;
+                    self.finished = false;
+                }
+                TrackerCommand::SetMuted(channel, muted) => {
+                    if let Some(channel) = self.channels.get_mut(channel) {
+                        channel.muted = muted;
+                    }
+                }
+            }
+        }
+    }
+
+    fn process_tick(&mut self) {
+        if self.tick == 0 {
+            self.start_row();
+        } else {
+            for index in 0..self.channels.len() {
+                self.update_effect(index);
+            }
+        }
+        for index in 0..self.channels.len() {
+            self.update_instrument(index);
+        }
+        self.tick += 1;
+        if self.tick >= self.speed {
+            self.tick = 0;
+            self.advance_row();
+        }
+    }
+
+    fn start_row(&mut self) {
+        self.control.position.store(
+            ((self.order as u64) << 32) | self.row as u64,
+            Ordering::Release,
+        );
+        for index in 0..self.channels.len() {
+            let note = self.module.note(self.order, self.row, index);
+            if let Effect::NoteDelay(delay) = note.effect
+                && delay > 0
+            {
+                // started in update_effect
+                self.channels[index].note = note;
+                continue;
+            }
+            self.start_note(index, note);
+        }
+    }
+
+    fn start_note(&mut self, index: usize, note: Note) {
+        let module = self.module.clone();
+        let channel = &mut self.channels[index];
+        let note = Note {
+            effect: channel.remember(note.effect, module.effect_memory),
+            ..note
+        };
+        channel.note = note;
+        if note.instrument > 0
+            && module
+                .instruments
+                .get(note.instrument as usize - 1)
+                .is_some()
+        {
+            channel.instrument = note.instrument as usize;
+        }
+        let instrument = channel
+            .instrument
+            .checked_sub(1)
+            .and_then(|index| module.instruments.get(index));
+        let key = match note.pitch {
+            Pitch::Key(key) => key.min(KEYS as u8 - 1),
+            _ => channel.key,
+        };
+        let mapped = instrument.and_then(|instrument| instrument.keymap.get(key as usize));
+        let period = match (note.pitch, mapped) {
+            (Pitch::Period(period), _) => Some(period as f64),
+            (Pitch::Key(_), Some((key, _))) => Some(Module::key_period(*key)),
+            _ => None,
+        };
+        if note.instrument > 0
+            && let Some((_, sample_index)) = mapped
+            && let Some(sample) = (*sample_index as usize)
+                .checked_sub(1)
+                .and_then(|index| module.samples.get(index))
+        {
+            channel.sample = *sample_index as usize;
+            channel.finetune = sample.finetune;
+            channel.volume = sample.volume as i32;
+            if let Some(panning) = sample.panning {
+                channel.panning = panning as f32 / 255.;
+            }
+        }
+        if let Some(period) = period {
+            // a finetune step is an eighth of a semitone
+            let period = period * 2f64.powf(-channel.finetune as f64 / 96.);
+            if note.effect.is_tone_portamento() {
+                channel.porta_target = period;
+            } else {
+                // instruments can play different samples for each key
+                if let (Pitch::Key(_), Some((_, sample))) = (note.pitch, mapped)
+                    && *sample > 0
+                {
+                    channel.sample = *sample as usize;
+                }
+                channel.key = key;
+                channel.period = period;
+                channel.playing = channel.sample > 0;
+                channel.vibrato_phase = 0;
+                channel.envelope_tick = 0;
+                channel.released = false;
+                channel.fading = false;
+                channel.fade = 1.;
+                channel.position = match note.effect {
+                    Effect::SampleOffset(offset) => offset as f64 * 256.,
+                    _ => 0.,
+                };
+            }
+        }
+        match note.pitch {
+            Pitch::Off => channel.release(instrument),
+            Pitch::Cut => channel.playing = false,
+            Pitch::Fade => channel.fading = true,
+            _ => (),
+        }
+        channel.output_period = channel.period;
+        match note.volume {
+            VolumeCommand::Set(volume) => channel.volume = volume.min(64) as i32,
+            VolumeCommand::FineSlideUp(amount) => {
+                channel.volume = (channel.volume + amount as i32).min(64)
+            }
+            VolumeCommand::FineSlideDown(amount) => {
+                channel.volume = (channel.volume - amount as i32).max(0)
+            }
+            VolumeCommand::Panning(panning) => channel.panning = panning as f32 / 255.,
+            _ => (),
+        }
+        match note.effect {
+            Effect::TonePortamento(speed) if speed > 0 => channel.porta_speed = speed as f64,
+            Effect::Vibrato(param) => {
+                let (x, y) = (param >> 4, param & 0x0f);
+                if x > 0 {
+                    channel.vibrato_speed = x;
+                }
+                if y > 0 {
+                    channel.vibrato_depth = y;
+                }
+            }
+            Effect::FinePortaUp(amount) => {
+                channel.period = module.slide_period(channel.period, -(amount as f64))
+            }
+            Effect::FinePortaDown(amount) => {
+                channel.period = module.slide_period(channel.period, amount as f64)
+            }
+            Effect::ExtraFinePortaUp(amount) => {
+                channel.period = module.slide_period(channel.period, -(amount as f64) / 4.)
+            }
+            Effect::ExtraFinePortaDown(amount) => {
+                channel.period = module.slide_period(channel.period, amount as f64 / 4.)
+            }
+            Effect::VolumeSlide(param)
+            | Effect::TonePortamentoVolumeSlide(param)
+            | Effect::VibratoVolumeSlide(param) => channel.volume_slide(param, true),
+            Effect::SetPanning(panning) => channel.panning = panning as f32 / 255.,
+            Effect::PositionJump(order) => self.jump = Some((order as usize, 0)),
+            Effect::SetVolume(volume) => channel.volume = volume.min(64) as i32,
+            Effect::PatternBreak(row) => {
+                let order = self.jump.map_or(self.order + 1, |(order, _)| order);
+                self.jump = Some((order, row as usize));
+            }
+            Effect::NoteCut(0) => channel.volume = 0,
+            Effect::KeyOff(0) => channel.release(instrument),
+            Effect::SetSpeed(speed) => self.speed = speed as u32,
+            Effect::SetTempo(tempo) => self.tempo = tempo as u32,
+            Effect::GlobalVolume(volume) => self.global_volume = volume,
+            _ => (),
+        }
+    }
+
+    fn update_effect(&mut self, index: usize) {
+        let tick = self.tick;
+        let module = self.module.clone();
+        let channel = &mut self.channels[index];
+        let note = channel.note;
+        match note.volume {
+            VolumeCommand::SlideUp(amount) => {
+                channel.volume = (channel.volume + amount as i32).min(64)
+            }
+            VolumeCommand::SlideDown(amount) => {
+                channel.volume = (channel.volume - amount as i32).max(0)
+            }
+            _ => (),
+        }
+        match note.effect {
+            Effect::Arpeggio(param) => {
+                let (x, y) = (param >> 4, param & 0x0f);
+                let semitones = [0, x, y][tick as usize % 3];
+                channel.output_period = channel.period / 2f64.powf(semitones as f64 / 12.);
+            }
+            Effect::PortaUp(amount) => {
+                channel.period = module.slide_period(channel.period, -(amount as f64))
+            }
+            Effect::PortaDown(amount) => {
+                channel.period = module.slide_period(channel.period, amount as f64)
+            }
+            Effect::TonePortamento(_) => channel.tone_portamento(&module),
+            Effect::Vibrato(_) => channel.vibrato(&module),
+            Effect::TonePortamentoVolumeSlide(param) => {
+                channel.tone_portamento(&module);
+                channel.volume_slide(param, false);
+            }
+            Effect::VibratoVolumeSlide(param) => {
+                channel.vibrato(&module);
+                channel.volume_slide(param, false);
+            }
+            Effect::VolumeSlide(param) => channel.volume_slide(param, false),
+            Effect::NoteCut(cut) if tick == cut as u32 => channel.volume = 0,
+            Effect::KeyOff(off) if tick == off as u32 => {
+                let instrument = channel
+                    .instrument
+                    .checked_sub(1)
+                    .and_then(|index| module.instruments.get(index));
+                channel.release(instrument);
+            }
+            Effect::NoteDelay(delay) if tick == delay as u32 => {
+                self.start_note(index, note);
+                return;
+            }
+            _ => (),
+        }
+        let modulated = matches!(
+            note.effect,
+            Effect::Vibrato(_) | Effect::VibratoVolumeSlide(_) | Effect::Arpeggio(_)
+        );
+        if !modulated {
+            channel.output_period = channel.period;
+        }
+    }
+
+    /// Advance the envelope and fadeout of the instrument of a channel
+    fn update_instrument(&mut self, index: usize) {
+        let channel = &mut self.channels[index];
+        let Some(instrument) = channel
+            .instrument
+            .checked_sub(1)
+            .and_then(|index| self.module.instruments.get(index))
+        else {
+            return;
+        };
+        if let Some(envelope) = &instrument.volume_envelope {
+            channel.envelope_tick = envelope.advance(channel.envelope_tick, channel.released);
+        }
+        if channel.fading {
+            channel.fade = (channel.fade - instrument.fadeout).max(0.);
+        }
+    }
+
+    fn advance_row(&mut self) {
+        let (order, row) = self.jump.take().unwrap_or((self.order, self.row + 1));
+        let (mut order, row) = if row >= self.module.rows(order) {
+            (order + 1, 0)
+        } else {
+            (order, row)
+        };
+        if order >= self.module.orders.len() {
+            if !self.control.looped.load(Ordering::Acquire) {
+                self.finished = true;
+                return;
+            }
+            order = if self.module.restart < self.module.orders.len() {
+                self.module.restart
+            } else {
+                0
+            };
+        }
+        self.order = order;
+        self.row = row.min(self.module.rows(order) - 1);
+    }
+
+    fn next_frame(&mut self, dt: f64) -> Frame {
+        if self.finished {
+            return Frame::ZERO;
+        }
+        if self.tick_timer <= 0. {
+            self.process_tick();
+            self.tick_timer += self.tick_duration();
+        }
+        self.tick_timer -= dt;
+        let global_volume = self.global_volume as f32 / 128.;
+        let mut frame = Frame::ZERO;
+        for channel in self.channels.iter_mut() {
+            if !channel.playing || channel.output_period <= 0. {
+                continue;
+            }
+            let Some(sample) = self.module.samples.get(channel.sample - 1) else {
+                continue;
+            };
+            let value = sample_at(sample, channel.position);
+            channel.position += sample.c5_speed * C5_PERIOD / channel.output_period * dt;
+            if sample.looped() {
+                let loop_end = (sample.loop_start + sample.loop_length) as f64;
+                while channel.position >= loop_end {
+                    channel.position -= sample.loop_length as f64;
+                }
+            } else if channel.position >= sample.data.len() as f64 {
+                channel.playing = false;
+            }
+            let Some(instrument) = channel
+                .instrument
+                .checked_sub(1)
+                .and_then(|index| self.module.instruments.get(index))
+            else {
+                continue;
+            };
+            if channel.muted {
+                continue;
+            }
+            let envelope = instrument
+                .volume_envelope
+                .as_ref()
+                .map_or(1., |envelope| envelope.value(channel.envelope_tick));
+            let volume = channel.volume as f32 / 64. * sample.global_volume as f32 / 64.;
+            let value =
+                value * volume * envelope * channel.fade * instrument.global_volume * global_volume;
+            frame.left += value * (1. - channel.panning);
+            frame.right += value * channel.panning;
+        }
+
+        frame * (2. / self.channels.len() as f32)
+    }
+}
+
+impl Channel {
+    /// Replace a missing effect parameter with the parameter of the last effect of its kind
+    fn remember(&mut self, effect: Effect, effect_memory: bool) -> Effect {
+        let recall = |memory: &mut u8, param: u8| {
+            if param > 0 || !effect_memory {
+                *memory = param;
+            }
+            *memory
+        };
+        let effect = match effect {
+            Effect::Arpeggio(param) => Effect::Arpeggio(recall(&mut self.arpeggio_memory, param)),
+            Effect::PortaUp(param) => Effect::PortaUp(recall(&mut self.porta_memory, param)),
+            Effect::PortaDown(param) => Effect::PortaDown(recall(&mut self.porta_memory, param)),
+            Effect::PackedPortaUp(param) => {
+                Effect::PackedPortaUp(recall(&mut self.porta_memory, param))
+            }
+            Effect::PackedPortaDown(param) => {
+                Effect::PackedPortaDown(recall(&mut self.porta_memory, param))
+            }
+            Effect::VolumeSlide(param) => {
+                Effect::VolumeSlide(recall(&mut self.volume_slide_memory, param))
+            }
+            Effect::TonePortamentoVolumeSlide(param) => {
+                Effect::TonePortamentoVolumeSlide(recall(&mut self.volume_slide_memory, param))
+            }
+            Effect::VibratoVolumeSlide(param) => {
+                Effect::VibratoVolumeSlide(recall(&mut self.volume_slide_memory, param))
+            }
+            Effect::SampleOffset(param) => {
+                Effect::SampleOffset(recall(&mut self.sample_offset_memory, param))
+            }
+            effect => effect,
+        };
+        // unpack fine and extra fine slides
+        match effect {
+            Effect::PackedPortaUp(param @ 0xf0..) => Effect::FinePortaUp(param & 0x0f),
+            Effect::PackedPortaUp(param @ 0xe0..) => Effect::ExtraFinePortaUp(param & 0x0f),
+            Effect::PackedPortaUp(param) => Effect::PortaUp(param),
+            Effect::PackedPortaDown(param @ 0xf0..) => Effect::FinePortaDown(param & 0x0f),
+            Effect::PackedPortaDown(param @ 0xe0..) => Effect::ExtraFinePortaDown(param & 0x0f),
+            Effect::PackedPortaDown(param) => Effect::PortaDown(param),
+            effect => effect,
+        }
+    }
+
+    /// Release the note; without a volume envelope, this silences it
+    fn release(&mut self, instrument: Option<&Instrument>) {
+        if instrument.is_some_and(|instrument| instrument.volume_envelope.is_some()) {
+            self.released = true;
+            self.fading = true;
+        } else {
+            self.volume = 0;
+        }
+    }
+
+    fn tone_portamento(&mut self, module: &Module) {
+        if self.porta_target <= 0. {
+            return;
+        }
+        if self.period < self.porta_target {
+            self.period = module
+                .shift_period(self.period, self.porta_speed)
+                .min(self.porta_target);
+        } else {
+            self.period = module
+                .shift_period(self.period, -self.porta_speed)
+                .max(self.porta_target);
+        }
+    }
+
+    fn vibrato(&mut self, module: &Module) {
+        let offset = (self.vibrato_phase as f64 / 64. * TAU).sin() * self.vibrato_depth as f64 * 2.;
+        self.output_period = module.shift_period(self.period, offset);
+        self.vibrato_phase = (self.vibrato_phase + self.vibrato_speed) % 64;
+    }
+
+    /// Slide the volume on the first tick for fine slides, or on all other ticks
+    fn volume_slide(&mut self, param: u8, first_tick: bool) {
+        let (x, y) = (param >> 4, param & 0x0f);
+        let change = match (x, y) {
+            (x, 0xf) if x > 0 => first_tick.then_some(x as i32),
+            (0xf, y) if y > 0 => first_tick.then_some(-(y as i32)),
+            (x, _) if x > 0 => (!first_tick).then_some(x as i32),
+            (_, y) => (!first_tick).then_some(-(y as i32)),
+        };
+        if let Some(change) = change {
+            self.volume = (self.volume + change).clamp(0, 64);
+        }
+    }
+}
+
+/// Linearly interpolated value of the sample at the given position
+fn sample_at(sample: &ModuleSample, position: f64) -> f32 {
+    let index = position as usize;
+    let Some(current) = sample.data.get(index) else {
+        return 0.;
+    };
+    let next = sample.data.get(index + 1).unwrap_or(current);
+    let fraction = position.fract() as f32;
+
+    current + (next - current) * fraction
+}
+
+impl Sound for ModulePlayer {
+    fn on_start_processing(&mut self) {
+        self.run_commands();
+    }
+
+    fn process(&mut self, out: &mut [Frame], dt: f64, _info: &Info) {
+        for frame in out {
+            *frame = self.next_frame(dt);
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.finished
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Module with one pattern playing a looped square wave on the first channel
+    fn module(song_length: u8) -> Vec<u8> {
+        let mut bytes = vec![0; 1084];
+        // sample 1: 32 bytes, volume 64, looped over its full length
+        bytes[20 + 22..20 + 30].copy_from_slice(&[0, 16, 0, 64, 0, 0, 0, 16]);
+        bytes[950] = song_length;
+        bytes[1080..1084].copy_from_slice(b"M.K.");
+        let mut pattern = vec![0; ROWS_PER_PATTERN * 4 * 4];
+        // C-2 (period 428) with sample 1 and pattern break to row 32 in the second row
+        pattern[0..4].copy_from_slice(&[0x01, 0xac, 0x10, 0x00]);
+        pattern[16..20].copy_from_slice(&[0x00, 0x00, 0x0d, 0x32]);
+        bytes.extend_from_slice(&pattern);
+        bytes.extend((0..32).map(|index| if index < 16 { 100u8 } else { 156 }));
+
+        bytes
+    }
+
+    #[test]
+    fn parses_protracker_modules() {
+        let module = Module::parse(&module(1)).unwrap();
+
+        assert_eq!(module.channels, 4);
+        assert_eq!(module.patterns.len(), 1);
+        assert_eq!(module.samples[0].data.len(), 32);
+        assert!(module.samples[0].looped());
+        assert_eq!(
+            module.note(0, 0, 0),
+            Note {
+                pitch: Pitch::Period(428),
+                instrument: 1,
+                volume: VolumeCommand::None,
+                effect: Effect::None
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_formats() {
+        let mut bytes = module(1);
+        bytes[1080..1084].copy_from_slice(b"ABCD");
+
+        assert!(matches!(
+            Module::parse(&bytes),
+            Err(ModLoaderError::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn plays_until_the_end_of_the_song() {
+        let module = Arc::new(Module::parse(&module(2)).unwrap());
+        let control = Arc::new(TrackerControl {
+            commands: Mutex::new(vec![]),
+            position: AtomicU64::new(0),
+            looped: AtomicBool::new(false),
+        });
+        let mut player = ModulePlayer::new(module, control.clone());
+        let dt = 1. / 1000.;
+
+        assert!(player.next_frame(dt).left > 0.);
+        // the pattern break skips to row 32 of the second order, leaving 34 rows
+        // of 6 ticks with 20ms each
+        for _ in 0..(34 * 6 * 20 - 100) {
+            player.next_frame(dt);
+        }
+        assert!(!player.finished);
+        for _ in 0..200 {
+            player.next_frame(dt);
+        }
+        assert!(player.finished);
+        let position = control.position.load(Ordering::Acquire);
+        assert_eq!(position, (1 << 32) | 63);
+    }
+
+    #[test]
+    fn envelopes_interpolate_and_hold_the_sustain_point() {
+        let envelope = Envelope {
+            points: vec![(0, 64), (10, 32), (20, 0)],
+            sustain: Some((1, 1)),
+            looped: None,
+        };
+
+        assert_eq!(envelope.value(0), 1.);
+        assert_eq!(envelope.value(5), 0.75);
+        assert_eq!(envelope.value(25), 0.);
+        assert_eq!(envelope.advance(10, false), 10);
+        assert_eq!(envelope.advance(10, true), 11);
+    }
+
+    #[test]
+    fn volume_slides_use_scream_tracker_parameters() {
+        let mut channel = Channel {
+            volume: 32,
+            ..Default::default()
+        };
+
+        channel.volume_slide(0x20, true);
+        assert_eq!(channel.volume, 32);
+        channel.volume_slide(0x20, false);
+        assert_eq!(channel.volume, 34);
+        channel.volume_slide(0x04, false);
+        assert_eq!(channel.volume, 30);
+        channel.volume_slide(0x3f, true);
+        assert_eq!(channel.volume, 33);
+        channel.volume_slide(0xf2, false);
+        assert_eq!(channel.volume, 33);
+        channel.volume_slide(0xf2, true);
+        assert_eq!(channel.volume, 31);
+    }
+
+    #[test]
+    fn muted_channels_are_silent() {
+        let module = Arc::new(Module::parse(&module(1)).unwrap());
+        let control = Arc::new(TrackerControl {
+            commands: Mutex::new(vec![TrackerCommand::SetMuted(0, true)]),
+            position: AtomicU64::new(0),
+            looped: AtomicBool::new(false),
+        });
+        let mut player = ModulePlayer::new(module, control);
+        player.run_commands();
+
+        assert_eq!(player.next_frame(1. / 1000.), Frame::ZERO);
+    }
+}
@@ -0,0 +1,1019 @@
+//! Parsers for Scream Tracker 3, FastTracker 2, and Impulse Tracker modules
+
+use crate::source::mod_loader::ModLoaderError;
+use crate::source::tracker::{
+    Effect, Envelope, Instrument, KEYS, Module, ModuleSample, Note, Pattern, Pitch, VolumeCommand,
+};
+
+/// Sample rate of C-5 in samples without a speed of their own
+const DEFAULT_C5_SPEED: f64 = 8363.;
+
+fn truncated() -> ModLoaderError {
+    ModLoaderError::InvalidFile("the file is truncated")
+}
+
+fn bytes_at(bytes: &[u8], offset: usize, length: usize) -> Result<&[u8], ModLoaderError> {
+    bytes
+        .get(offset..offset.checked_add(length).ok_or_else(truncated)?)
+        .ok_or_else(truncated)
+}
+
+fn u8_at(bytes: &[u8], offset: usize) -> Result<u8, ModLoaderError> {
+    bytes.get(offset).copied().ok_or_else(truncated)
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Result<u16, ModLoaderError> {
+    let word = bytes_at(bytes, offset, 2)?;
+
+    Ok(u16::from_le_bytes([word[0], word[1]]))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Result<u32, ModLoaderError> {
+    let word = bytes_at(bytes, offset, 4)?;
+
+    Ok(u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+}
+
+/// Decode 8 or 16-bit samples
+///
+/// Data ending early is cut off instead of failing, like in damaged ProTracker modules.
+fn decode_samples(data: &[u8], length: usize, wide: bool, signed: bool) -> Vec<f32> {
+    if wide {
+        data.chunks_exact(2)
+            .take(length)
+            .map(|sample| {
+                let sample = u16::from_le_bytes([sample[0], sample[1]]);
+                let sample = if signed { sample } else { sample ^ 0x8000 };
+                sample as i16 as f32 / 32768.
+            })
+            .collect()
+    } else {
+        data.iter()
+            .take(length)
+            .map(|sample| {
+                let sample = if signed { *sample } else { *sample ^ 0x80 };
+                sample as i8 as f32 / 128.
+            })
+            .collect()
+    }
+}
+
+/// Loop start and length in samples, limited to the sample data
+fn loop_bounds(data: &[f32], start: usize, end: usize) -> (usize, usize) {
+    let end = end.min(data.len());
+    if start >= end {
+        return (0, 0);
+    }
+
+    (start, end - start)
+}
+
+/// Order list without skip markers, ending at the first end marker
+fn scream_tracker_orders(orders: &[u8]) -> Vec<u8> {
+    orders
+        .iter()
+        .copied()
+        .take_while(|order| *order != 255)
+        .filter(|order| *order != 254)
+        .collect()
+}
+
+/// Lowest and highest period of notes from C-0 to B-9, with room for slides
+fn key_period_range() -> (f64, f64) {
+    (
+        Module::key_period(KEYS as u8 - 1) / 2.,
+        Module::key_period(0) * 2.,
+    )
+}
+
+/// Parse a Scream Tracker 3 module
+pub(crate) fn parse_s3m(bytes: &[u8]) -> Result<Module, ModLoaderError> {
+    let order_count = u16_at(bytes, 32)? as usize;
+    let instrument_count = u16_at(bytes, 34)? as usize;
+    let pattern_count = u16_at(bytes, 36)? as usize;
+    let signed = u16_at(bytes, 42)? == 1;
+    let global_volume = u8_at(bytes, 48)?.min(64) * 2;
+    let speed = u8_at(bytes, 49)?;
+    let tempo = u8_at(bytes, 50)?;
+    let stereo = u8_at(bytes, 51)? & 0x80 != 0;
+    let default_panning = u8_at(bytes, 53)? == 252;
+    let settings = bytes_at(bytes, 64, 32)?;
+    let orders = scream_tracker_orders(bytes_at(bytes, 96, order_count)?);
+    let pointers_offset = 96 + order_count;
+    let pointer = |index: usize| -> Result<usize, ModLoaderError> {
+        Ok(u16_at(bytes, pointers_offset + index * 2)? as usize * 16)
+    };
+    let panning_offset = pointers_offset + (instrument_count + pattern_count) * 2;
+
+    // only PCM channels are played; their index in the file maps to a channel of the module
+    let mut channel_map = [None; 32];
+    let mut panning = vec![];
+    for (index, setting) in settings.iter().enumerate() {
+        if *setting >= 16 {
+            continue;
+        }
+        channel_map[index] = Some(panning.len());
+        let mut channel_panning = if !stereo {
+            0.5
+        } else if *setting < 8 {
+            0.25
+        } else {
+            0.75
+        };
+        if default_panning
+            && let Ok(value) = u8_at(bytes, panning_offset + index)
+            && value & 0x20 != 0
+        {
+            channel_panning = (value & 0x0f) as f32 / 15.;
+        }
+        panning.push(channel_panning);
+    }
+    let channels = panning.len();
+    if channels == 0 {
+        return Err(ModLoaderError::InvalidFile("the module has no channels"));
+    }
+
+    let mut samples = Vec::with_capacity(instrument_count);
+    for index in 0..instrument_count {
+        let offset = pointer(index)?;
+        let header = bytes_at(bytes, offset, 80)?;
+        if header[0] != 1 {
+            // empty or AdLib instrument
+            samples.push(ModuleSample::default());
+            continue;
+        }
+        let data_offset = (((header[13] as usize) << 16) | u16_at(header, 14)? as usize) * 16;
+        let length = u32_at(header, 16)? as usize;
+        let flags = header[31];
+        let wide = flags & 4 != 0;
+        let data_length = length.saturating_mul(if wide { 2 } else { 1 });
+        let data = bytes
+            .get(data_offset..data_offset.saturating_add(data_length).min(bytes.len()))
+            .unwrap_or_default();
+        let data = decode_samples(data, length, wide, signed);
+        let (loop_start, loop_length) = if flags & 1 != 0 {
+            loop_bounds(
+                &data,
+                u32_at(header, 20)? as usize,
+                u32_at(header, 24)? as usize,
+            )
+        } else {
+            (0, 0)
+        };
+        let c5_speed = u32_at(header, 32)?;
+        samples.push(ModuleSample {
+            data,
+            c5_speed: if c5_speed > 0 {
+                c5_speed as f64
+            } else {
+                DEFAULT_C5_SPEED
+            },
+            finetune: 0,
+            volume: header[28].min(64),
+            global_volume: 64,
+            panning: None,
+            loop_start,
+            loop_length,
+        });
+    }
+
+    let mut patterns = Vec::with_capacity(pattern_count);
+    for index in 0..pattern_count {
+        let mut notes = vec![Note::default(); 64 * channels];
+        let offset = pointer(instrument_count + index)?;
+        if offset > 0 {
+            let length = u16_at(bytes, offset)? as usize;
+            let data = bytes
+                .get(offset + 2..(offset + 2 + length).min(bytes.len()))
+                .unwrap_or_default();
+            let mut data = data.iter().copied();
+            let mut row = 0;
+            while row < 64 {
+                let Some(what) = data.next() else {
+                    break;
+                };
+                if what == 0 {
+                    row += 1;
+                    continue;
+                }
+                let mut note = Note::default();
+                if what & 0x20 != 0 {
+                    note.pitch = match data.next().ok_or_else(truncated)? {
+                        255 => Pitch::None,
+                        254 => Pitch::Cut,
+                        // octave and note; Scream Tracker plays C-4 at the C-5 speed
+                        value => {
+                            Pitch::Key(((value >> 4) * 12 + (value & 0x0f) % 12 + 12).min(119))
+                        }
+                    };
+                    note.instrument = data.next().ok_or_else(truncated)?;
+                }
+                if what & 0x40 != 0 {
+                    let volume = data.next().ok_or_else(truncated)?;
+                    if volume <= 64 {
+                        note.volume = VolumeCommand::Set(volume);
+                    }
+                }
+                if what & 0x80 != 0 {
+                    let command = data.next().ok_or_else(truncated)?;
+                    let param = data.next().ok_or_else(truncated)?;
+                    note.effect = Effect::scream_tracker(command, param, false);
+                }
+                if let Some(channel) = channel_map[(what & 0x1f) as usize] {
+                    notes[row * channels + channel] = note;
+                }
+            }
+        }
+        patterns.push(Pattern { rows: 64, notes });
+    }
+
+    Ok(Module {
+        channels,
+        instruments: (1..=samples.len() as u8).map(Instrument::sample).collect(),
+        samples,
+        orders,
+        restart: 0,
+        patterns,
+        speed: if speed > 0 { speed as u32 } else { 6 },
+        tempo: if tempo >= 32 { tempo as u32 } else { 125 },
+        global_volume,
+        panning,
+        linear_slides: false,
+        effect_memory: true,
+        period_range: key_period_range(),
+    })
+}
+
+/// Convert a value of the volume column of a FastTracker 2 module
+fn xm_volume(value: u8) -> VolumeCommand {
+    let amount = value & 0x0f;
+    match value {
+        0x10..=0x50 => VolumeCommand::Set(value - 0x10),
+        0x60..=0x6f => VolumeCommand::SlideDown(amount),
+        0x70..=0x7f => VolumeCommand::SlideUp(amount),
+        0x80..=0x8f => VolumeCommand::FineSlideDown(amount),
+        0x90..=0x9f => VolumeCommand::FineSlideUp(amount),
+        0xc0..=0xcf => VolumeCommand::Panning(amount * 17),
+        _ => VolumeCommand::None,
+    }
+}
+
+/// Parse the volume envelope of a FastTracker 2 instrument
+fn xm_envelope(header: &[u8]) -> Result<Option<Envelope>, ModLoaderError> {
+    let kind = u8_at(header, 233)?;
+    if kind & 1 == 0 {
+        return Ok(None);
+    }
+    let count = (u8_at(header, 225)? as usize).min(12);
+    let points = (0..count)
+        .map(|index| {
+            Ok((
+                u16_at(header, 129 + index * 4)?,
+                u16_at(header, 131 + index * 4)?.min(64) as u8,
+            ))
+        })
+        .collect::<Result<Vec<_>, ModLoaderError>>()?;
+    let sustain = u8_at(header, 227)? as usize;
+    let loop_start = u8_at(header, 228)? as usize;
+    let loop_end = u8_at(header, 229)? as usize;
+
+    Ok(Some(Envelope {
+        points,
+        sustain: (kind & 2 != 0).then_some((sustain, sustain)),
+        looped: (kind & 4 != 0).then_some((loop_start, loop_end)),
+    }))
+}
+
+/// Parse a FastTracker 2 module
+pub(crate) fn parse_xm(bytes: &[u8]) -> Result<Module, ModLoaderError> {
+    let version = u16_at(bytes, 58)?;
+    if version < 0x0104 {
+        return Err(ModLoaderError::UnsupportedFormat(format!(
+            "XM version {version:#06x}"
+        )));
+    }
+    let header_size = u32_at(bytes, 60)? as usize;
+    let song_length = (u16_at(bytes, 64)? as usize).min(256);
+    let restart = u16_at(bytes, 66)? as usize;
+    let channels = u16_at(bytes, 68)? as usize;
+    let pattern_count = u16_at(bytes, 70)? as usize;
+    let instrument_count = u16_at(bytes, 72)? as usize;
+    let linear_slides = u16_at(bytes, 74)? & 1 != 0;
+    let speed = u16_at(bytes, 76)?;
+    let tempo = u16_at(bytes, 78)?;
+    let orders = bytes_at(bytes, 80, song_length)?.to_vec();
+    if channels == 0 || channels > 64 {
+        return Err(ModLoaderError::InvalidFile("invalid number of channels"));
+    }
+
+    let mut offset = 60 + header_size;
+    let mut patterns = Vec::with_capacity(pattern_count);
+    for _ in 0..pattern_count {
+        let header_length = u32_at(bytes, offset)? as usize;
+        let rows = (u16_at(bytes, offset + 5)? as usize).clamp(1, 256);
+        let packed_size = u16_at(bytes, offset + 7)? as usize;
+        offset += header_length;
+        let mut data = bytes_at(bytes, offset, packed_size)?.iter().copied();
+        offset += packed_size;
+        let mut notes = vec![Note::default(); rows * channels];
+        for note in notes.iter_mut() {
+            let Some(first) = data.next() else {
+                break;
+            };
+            let (flags, key) = if first & 0x80 != 0 {
+                (first, None)
+            } else {
+                (0x1e, Some(first))
+            };
+            let mut next = |flag: u8| -> Result<u8, ModLoaderError> {
+                if flags & flag != 0 {
+                    data.next().ok_or_else(truncated)
+                } else {
+                    Ok(0)
+                }
+            };
+            let key = match key {
+                Some(key) => key,
+                None => next(0x01)?,
+            };
+            let instrument = next(0x02)?;
+            let volume = next(0x04)?;
+            let effect = next(0x08)?;
+            let param = next(0x10)?;
+            *note = Note {
+                pitch: match key {
+                    // FastTracker 2 plays C-4 at the C-5 speed
+                    1..=96 => Pitch::Key(key + 11),
+                    97 => Pitch::Off,
+                    _ => Pitch::None,
+                },
+                instrument,
+                volume: xm_volume(volume),
+                effect: Effect::protracker(effect, param),
+            };
+        }
+        patterns.push(Pattern { rows, notes });
+    }
+
+    let mut samples = vec![];
+    let mut instruments = Vec::with_capacity(instrument_count);
+    for _ in 0..instrument_count {
+        let size = u32_at(bytes, offset)? as usize;
+        let sample_count = u16_at(bytes, offset + 27)? as usize;
+        let header = bytes_at(bytes, offset, size.max(29))?;
+        offset += size;
+        if sample_count == 0 {
+            instruments.push(Instrument::sample(0));
+            continue;
+        }
+        let sample_header_size = u32_at(header, 29)? as usize;
+        let first_sample = samples.len();
+        let keymap = bytes_at(header, 33, 96)?;
+        let fadeout = u16_at(header, 239)?;
+        let volume_envelope = xm_envelope(header)?;
+
+        let mut headers = Vec::with_capacity(sample_count);
+        for _ in 0..sample_count {
+            headers.push(bytes_at(bytes, offset, 40)?);
+            offset += sample_header_size;
+        }
+        for header in headers {
+            let wide = header[14] & 0x10 != 0;
+            let width = if wide { 2 } else { 1 };
+            let byte_length = u32_at(header, 0)? as usize;
+            let data = bytes
+                .get(offset..offset.saturating_add(byte_length).min(bytes.len()))
+                .unwrap_or_default();
+            offset = offset.saturating_add(byte_length);
+            if header[17] == 0xad {
+                return Err(ModLoaderError::UnsupportedFormat(
+                    "ADPCM compressed XM samples".to_string(),
+                ));
+            }
+            // samples are stored as differences to the previous sample
+            let mut data = decode_samples(data, byte_length / width, wide, true);
+            let range = if wide { 65536. } else { 256. };
+            let mut last = 0.;
+            for sample in data.iter_mut() {
+                last = (last + *sample) % 2.;
+                last = if last >= 1. {
+                    last - 2.
+                } else if last < -1. {
+                    last + 2.
+                } else {
+                    last
+                };
+                *sample = (last * range / 2.).round() / (range / 2.);
+            }
+            let loop_start = u32_at(header, 4)? as usize / width;
+            let loop_length = u32_at(header, 8)? as usize / width;
+            let loop_kind = header[14] & 0x03;
+            let (loop_start, loop_length) = if loop_kind != 0 {
+                loop_bounds(&data, loop_start, loop_start.saturating_add(loop_length))
+            } else {
+                (0, 0)
+            };
+            let relative_note = header[16] as i8 as f64;
+            let finetune = header[13] as i8 as f64 / 128.;
+            let mut sample = ModuleSample {
+                data,
+                c5_speed: DEFAULT_C5_SPEED * 2f64.powf((relative_note + finetune) / 12.),
+                finetune: 0,
+                volume: header[12].min(64),
+                global_volume: 64,
+                panning: Some(header[15]),
+                loop_start,
+                loop_length,
+            };
+            if loop_kind == 2 {
+                sample.unroll_ping_pong_loop();
+            }
+            samples.push(sample);
+        }
+        instruments.push(Instrument {
+            // keys of FastTracker 2 start at C-1
+            keymap: (0..KEYS as u8)
+                .map(|key| {
+                    let sample = key
+                        .checked_sub(12)
+                        .and_then(|index| keymap.get(index as usize))
+                        .filter(|sample| (**sample as usize) < sample_count)
+                        .map_or(0, |sample| (first_sample + *sample as usize + 1) as u8);
+                    (key, sample)
+                })
+                .collect(),
+            volume_envelope,
+            fadeout: fadeout as f32 / 32768.,
+            global_volume: 1.,
+        });
+    }
+    if samples.len() > u8::MAX as usize {
+        return Err(ModLoaderError::UnsupportedFormat(
+            "more than 255 samples".to_string(),
+        ));
+    }
+
+    Ok(Module {
+        channels,
+        samples,
+        instruments,
+        orders,
+        restart,
+        patterns,
+        speed: if speed > 0 { speed as u32 } else { 6 },
+        tempo: if tempo >= 32 { tempo as u32 } else { 125 },
+        global_volume: 128,
+        panning: vec![0.5; channels],
+        linear_slides,
+        effect_memory: true,
+        period_range: key_period_range(),
+    })
+}
+
+/// Convert a value of the volume column of an Impulse Tracker module
+fn it_volume(value: u8) -> VolumeCommand {
+    match value {
+        0..=64 => VolumeCommand::Set(value),
+        65..=74 => VolumeCommand::FineSlideUp(value - 65),
+        75..=84 => VolumeCommand::FineSlideDown(value - 75),
+        85..=94 => VolumeCommand::SlideUp(value - 85),
+        95..=104 => VolumeCommand::SlideDown(value - 95),
+        128..=192 => VolumeCommand::Panning(((value - 128) as u16 * 255 / 64) as u8),
+        _ => VolumeCommand::None,
+    }
+}
+
+/// Parse the volume envelope of an Impulse Tracker instrument
+fn it_envelope(header: &[u8]) -> Result<Option<Envelope>, ModLoaderError> {
+    let flags = u8_at(header, 304)?;
+    if flags & 1 == 0 {
+        return Ok(None);
+    }
+    let count = (u8_at(header, 305)? as usize).min(25);
+    let points = (0..count)
+        .map(|index| {
+            Ok((
+                u16_at(header, 311 + index * 3)?,
+                u8_at(header, 310 + index * 3)?.min(64),
+            ))
+        })
+        .collect::<Result<Vec<_>, ModLoaderError>>()?;
+    let range = |start: usize| -> Result<(usize, usize), ModLoaderError> {
+        Ok((
+            u8_at(header, start)? as usize,
+            u8_at(header, start + 1)? as usize,
+        ))
+    };
+
+    Ok(Some(Envelope {
+        points,
+        sustain: (flags & 4 != 0).then(|| range(308)).transpose()?,
+        looped: (flags & 2 != 0).then(|| range(306)).transpose()?,
+    }))
+}
+
+/// Reads bits of compressed Impulse Tracker samples, starting with the lowest bit
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, bits: u8) -> u32 {
+        let mut value = 0;
+        for bit in 0..bits as usize {
+            let position = self.position + bit;
+            let set = self
+                .bytes
+                .get(position / 8)
+                .is_some_and(|byte| byte >> (position % 8) & 1 != 0);
+            value |= (set as u32) << bit;
+        }
+        self.position += bits as usize;
+
+        value
+    }
+}
+
+/// Decompress a sample packed by Impulse Tracker 2.14 or 2.15
+///
+/// Fails if a block switches to a bit width outside of the range of the sample format.
+fn it_decompress(
+    data: &[u8],
+    length: usize,
+    wide: bool,
+    it215: bool,
+) -> Result<Vec<f32>, ModLoaderError> {
+    let (block_length, max_width, mode_bits) = if wide {
+        (0x4000, 17, 4)
+    } else {
+        (0x8000, 9, 3)
+    };
+    let sample_bits = max_width - 1;
+    let valid_width = |width: u8| {
+        if (1..=max_width).contains(&width) {
+            Ok(width)
+        } else {
+            Err(ModLoaderError::InvalidFile("corrupt compressed sample"))
+        }
+    };
+    let mut samples = Vec::with_capacity(length);
+    let mut offset = 0;
+    while samples.len() < length {
+        let Some(packed_length) = data
+            .get(offset..offset + 2)
+            .map(|length| u16::from_le_bytes([length[0], length[1]]) as usize)
+        else {
+            break;
+        };
+        let block = data
+            .get(offset + 2..(offset + 2 + packed_length).min(data.len()))
+            .unwrap_or_default();
+        offset += 2 + packed_length;
+        let mut reader = BitReader {
+            bytes: block,
+            position: 0,
+        };
+        let mut width = max_width;
+        let (mut last, mut last_delta) = (0i32, 0i32);
+        let count = block_length.min(length - samples.len());
+        let mut read = 0;
+        while read < count {
+            if reader.position > block.len() * 8 {
+                break;
+            }
+            let value = reader.read(width);
+            // values at the edge of the current width change the width
+            if width < 7 {
+                if value == 1 << (width - 1) {
+                    let new_width = reader.read(mode_bits) as u8 + 1;
+                    width = valid_width(if new_width < width {
+                        new_width
+                    } else {
+                        new_width + 1
+                    })?;
+                    continue;
+                }
+            } else if width < max_width {
+                let border = (u32::MAX >> (32 - sample_bits)) >> (max_width - width);
+                let (low, high) = (border - (max_width - 1) as u32 / 2 - 4, border + 4);
+                let (low, high) = if wide {
+                    (border.wrapping_sub(8), border + 8)
+                } else {
+                    (low.max(border.wrapping_sub(4)), high)
+                };
+                if value > low && value <= high {
+                    let new_width = (value - low) as u8;
+                    width = valid_width(if new_width < width {
+                        new_width
+                    } else {
+                        new_width + 1
+                    })?;
+                    continue;
+                }
+            } else {
+                if value & (1 << sample_bits) != 0 {
+                    width = valid_width((value as u8).wrapping_add(1))?;
+                    continue;
+                }
+            }
+            // sign extend the value to the sample width
+            let shift = 32 - width.min(sample_bits) as u32;
+            let delta = ((value << shift) as i32) >> shift;
+            last = last.wrapping_add(delta);
+            last_delta = last_delta.wrapping_add(last);
+            let sample = if it215 { last_delta } else { last };
+            samples.push(if wide {
+                sample as i16 as f32 / 32768.
+            } else {
+                sample as i8 as f32 / 128.
+            });
+            read += 1;
+        }
+        if read < count {
+            break;
+        }
+    }
+
+    Ok(samples)
+}
+
+/// Parse an Impulse Tracker module
+pub(crate) fn parse_it(bytes: &[u8]) -> Result<Module, ModLoaderError> {
+    let order_count = u16_at(bytes, 32)? as usize;
+    let instrument_count = u16_at(bytes, 34)? as usize;
+    let sample_count = u16_at(bytes, 36)? as usize;
+    let pattern_count = u16_at(bytes, 38)? as usize;
+    let compatible_version = u16_at(bytes, 42)?;
+    let flags = u16_at(bytes, 44)?;
+    let stereo = flags & 1 != 0;
+    let use_instruments = flags & 4 != 0;
+    let linear_slides = flags & 8 != 0;
+    let global_volume = u8_at(bytes, 48)?.min(128);
+    let speed = u8_at(bytes, 50)?;
+    let tempo = u8_at(bytes, 51)?;
+    let channel_panning = bytes_at(bytes, 64, 64)?;
+    let orders = scream_tracker_orders(bytes_at(bytes, 192, order_count)?);
+    let pointers_offset = 192 + order_count;
+    let pointer = |index: usize| -> Result<usize, ModLoaderError> {
+        Ok(u32_at(bytes, pointers_offset + index * 4)? as usize)
+    };
+    if use_instruments && compatible_version < 0x200 {
+        return Err(ModLoaderError::UnsupportedFormat(
+            "instruments of Impulse Tracker versions before 2.0".to_string(),
+        ));
+    }
+    if sample_count > u8::MAX as usize {
+        return Err(ModLoaderError::UnsupportedFormat(
+            "more than 255 samples".to_string(),
+        ));
+    }
+
+    let mut samples = Vec::with_capacity(sample_count);
+    for index in 0..sample_count {
+        let offset = pointer(instrument_count + index)?;
+        let header = bytes_at(bytes, offset, 80)?;
+        if &header[0..4] != b"IMPS" {
+            return Err(ModLoaderError::InvalidFile("invalid sample header"));
+        }
+        let flags = header[18];
+        if flags & 1 == 0 {
+            samples.push(ModuleSample::default());
+            continue;
+        }
+        let wide = flags & 2 != 0;
+        let conversion = header[46];
+        let length = u32_at(header, 48)? as usize;
+        let data_offset = u32_at(header, 72)? as usize;
+        let data = bytes.get(data_offset..).unwrap_or_default();
+        let data = if flags & 8 != 0 {
+            it_decompress(data, length, wide, conversion & 4 != 0)?
+        } else {
+            decode_samples(data, length, wide, conversion & 1 != 0)
+        };
+        // sustain loops are played as normal loops
+        let (loop_flag, ping_pong_flag, loop_offset) = if flags & 0x10 != 0 {
+            (true, flags & 0x40 != 0, 52)
+        } else {
+            (flags & 0x20 != 0, flags & 0x80 != 0, 64)
+        };
+        let (loop_start, loop_length) = if loop_flag {
+            loop_bounds(
+                &data,
+                u32_at(header, loop_offset)? as usize,
+                u32_at(header, loop_offset + 4)? as usize,
+            )
+        } else {
+            (0, 0)
+        };
+        let c5_speed = u32_at(header, 60)?;
+        let panning = header[47];
+        let mut sample = ModuleSample {
+            data,
+            c5_speed: if c5_speed > 0 {
+                c5_speed as f64
+            } else {
+                DEFAULT_C5_SPEED
+            },
+            finetune: 0,
+            volume: header[19].min(64),
+            global_volume: header[17].min(64),
+            panning: (panning & 0x80 != 0)
+                .then(|| ((panning & 0x7f).min(64) as u16 * 255 / 64) as u8),
+            loop_start,
+            loop_length,
+        };
+        if ping_pong_flag {
+            sample.unroll_ping_pong_loop();
+        }
+        samples.push(sample);
+    }
+
+    let instruments = if use_instruments {
+        (0..instrument_count)
+            .map(|index| {
+                let header = bytes_at(bytes, pointer(index)?, 554)?;
+                if &header[0..4] != b"IMPI" {
+                    return Err(ModLoaderError::InvalidFile("invalid instrument header"));
+                }
+                let keymap = bytes_at(header, 64, KEYS * 2)?;
+                Ok(Instrument {
+                    keymap: keymap
+                        .chunks_exact(2)
+                        .map(|entry| {
+                            let sample = if (entry[1] as usize) <= sample_count {
+                                entry[1]
+                            } else {
+                                0
+                            };
+                            (entry[0].min(KEYS as u8 - 1), sample)
+                        })
+                        .collect(),
+                    volume_envelope: it_envelope(header)?,
+                    fadeout: u16_at(header, 20)?.min(1024) as f32 / 1024.,
+                    global_volume: header[24].min(128) as f32 / 128.,
+                })
+            })
+            .collect::<Result<Vec<_>, ModLoaderError>>()?
+    } else {
+        (1..=samples.len() as u8).map(Instrument::sample).collect()
+    };
+
+    // the number of channels is the highest channel used in any pattern
+    let mut channels = 1;
+    let mut patterns = Vec::with_capacity(pattern_count);
+    for index in 0..pattern_count {
+        let offset = pointer(instrument_count + sample_count + index)?;
+        if offset == 0 {
+            patterns.push((64, vec![]));
+            continue;
+        }
+        let length = u16_at(bytes, offset)? as usize;
+        let rows = (u16_at(bytes, offset + 2)? as usize).clamp(1, 200);
+        let mut data = bytes_at(bytes, offset + 8, length)?.iter().copied();
+        let mut notes = vec![];
+        let mut masks = [0u8; 64];
+        let mut last = [Note::default(); 64];
+        let mut row = 0;
+        while row < rows {
+            let Some(channel_variable) = data.next() else {
+                break;
+            };
+            if channel_variable == 0 {
+                row += 1;
+                continue;
+            }
+            let channel = ((channel_variable - 1) & 63) as usize;
+            channels = channels.max(channel + 1);
+            if channel_variable & 0x80 != 0 {
+                masks[channel] = data.next().ok_or_else(truncated)?;
+            }
+            let mask = masks[channel];
+            let mut note = Note::default();
+            if mask & 0x01 != 0 {
+                last[channel].pitch = match data.next().ok_or_else(truncated)? {
+                    key @ 0..=119 => Pitch::Key(key),
+                    255 => Pitch::Off,
+                    254 => Pitch::Cut,
+                    _ => Pitch::Fade,
+                };
+            }
+            if mask & 0x02 != 0 {
+                last[channel].instrument = data.next().ok_or_else(truncated)?;
+            }
+            if mask & 0x04 != 0 {
+                last[channel].volume = it_volume(data.next().ok_or_else(truncated)?);
+            }
+            if mask & 0x08 != 0 {
+                let command = data.next().ok_or_else(truncated)?;
+                let param = data.next().ok_or_else(truncated)?;
+                last[channel].effect = Effect::scream_tracker(command, param, true);
+            }
+            if mask & 0x11 != 0 {
+                note.pitch = last[channel].pitch;
+            }
+            if mask & 0x22 != 0 {
+                note.instrument = last[channel].instrument;
+            }
+            if mask & 0x44 != 0 {
+                note.volume = last[channel].volume;
+            }
+            if mask & 0x88 != 0 {
+                note.effect = last[channel].effect;
+            }
+            notes.push((row, channel, note));
+        }
+        patterns.push((rows, notes));
+    }
+    let patterns = patterns
+        .into_iter()
+        .map(|(rows, notes)| {
+            let mut pattern = vec![Note::default(); rows * channels];
+            for (row, channel, note) in notes {
+                pattern[row * channels + channel] = note;
+            }
+            Pattern {
+                rows,
+                notes: pattern,
+            }
+        })
+        .collect();
+
+    Ok(Module {
+        channels,
+        samples,
+        instruments,
+        orders,
+        restart: 0,
+        patterns,
+        speed: if speed > 0 { speed as u32 } else { 6 },
+        tempo: if tempo >= 32 { tempo as u32 } else { 125 },
+        global_volume,
+        panning: channel_panning[..channels]
+            .iter()
+            .map(|panning| match panning {
+                // surround channels are played in the center
+                _ if !stereo => 0.5,
+                0..=64 => *panning as f32 / 64.,
+                _ => 0.5,
+            })
+            .collect(),
+        linear_slides,
+        effect_memory: true,
+        period_range: key_period_range(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn put(bytes: &mut [u8], offset: usize, data: &[u8]) {
+        bytes[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    #[test]
+    fn parses_scream_tracker_modules() {
+        let mut bytes = vec![0; 224];
+        put(&mut bytes, 32, &[2, 0, 1, 0, 1, 0]);
+        put(&mut bytes, 42, &[2, 0]);
+        put(&mut bytes, 44, b"SCRM");
+        put(&mut bytes, 48, &[64, 6, 125, 0x80]);
+        // only the first channel is enabled
+        bytes[64..96].fill(255);
+        bytes[64] = 0;
+        put(&mut bytes, 96, &[0, 255]);
+        // instrument at 112 and pattern at 208
+        put(&mut bytes, 98, &[7, 0, 13, 0]);
+        // sample at 192 with 16 bytes, looped over its full length
+        put(&mut bytes, 112, &[1]);
+        put(&mut bytes, 125, &[0, 12, 0, 16, 0, 0, 0, 0, 0, 0, 0, 16]);
+        put(&mut bytes, 140, &[64, 0, 0, 1, 0xab, 0x20]);
+        put(&mut bytes, 188, b"SCRS");
+        put(&mut bytes, 192, &[0x80 + 64; 16]);
+        // C-4 with instrument 1, volume 32, and A03
+        put(&mut bytes, 208, &[8, 0, 0xe0, 0x40, 1, 32, 1, 3, 0]);
+
+        let module = Module::parse(&bytes).unwrap();
+
+        assert_eq!(module.channels, 1);
+        assert_eq!(module.orders, vec![0]);
+        assert_eq!(module.samples[0].data, vec![0.5; 16]);
+        assert_eq!(module.samples[0].c5_speed, 8363.);
+        assert_eq!(module.samples[0].loop_length, 16);
+        assert_eq!(
+            module.patterns[0].notes[0],
+            Note {
+                pitch: Pitch::Key(60),
+                instrument: 1,
+                volume: VolumeCommand::Set(32),
+                effect: Effect::SetSpeed(3)
+            }
+        );
+    }
+
+    #[test]
+    fn parses_fasttracker_modules() {
+        let mut bytes = vec![0; 336];
+        put(&mut bytes, 0, b"Extended Module: ");
+        put(&mut bytes, 58, &[4, 1, 20, 1, 0, 0]);
+        put(
+            &mut bytes,
+            64,
+            &[1, 0, 0, 0, 2, 0, 1, 0, 1, 0, 1, 0, 6, 0, 125, 0],
+        );
+        // pattern with two rows: C-4 with instrument 1 and volume 32, then a key off
+        bytes.extend_from_slice(&[9, 0, 0, 0, 0, 2, 0, 11, 0]);
+        bytes.extend_from_slice(&[0x87, 49, 1, 0x30, 0x80, 97, 0, 0, 0, 0, 0x80]);
+        let mut instrument = vec![0; 263];
+        put(&mut instrument, 0, &[7, 1, 0, 0]);
+        put(&mut instrument, 27, &[1, 0, 40, 0, 0, 0]);
+        put(&mut instrument, 129, &[0, 0, 64, 0, 10, 0, 0, 0]);
+        put(&mut instrument, 225, &[2, 0, 0]);
+        put(&mut instrument, 233, &[3]);
+        put(&mut instrument, 239, &[0, 4]);
+        bytes.extend_from_slice(&instrument);
+        // forward looped sample with four delta encoded bytes
+        let mut sample = vec![0; 40];
+        put(&mut sample, 0, &[4, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0]);
+        put(&mut sample, 12, &[64, 0, 1, 128, 12]);
+        bytes.extend_from_slice(&sample);
+        bytes.extend_from_slice(&[10, 10, 0xec, 0]);
+
+        let module = Module::parse(&bytes).unwrap();
+
+        assert_eq!(module.channels, 2);
+        assert!(module.linear_slides);
+        assert_eq!(module.patterns[0].rows, 2);
+        assert_eq!(
+            module.patterns[0].notes[0],
+            Note {
+                pitch: Pitch::Key(60),
+                instrument: 1,
+                volume: VolumeCommand::Set(32),
+                effect: Effect::None
+            }
+        );
+        assert_eq!(module.patterns[0].notes[2].pitch, Pitch::Off);
+        assert_eq!(module.samples[0].data, vec![10. / 128., 20. / 128., 0., 0.]);
+        assert_eq!(module.samples[0].c5_speed, 2. * DEFAULT_C5_SPEED);
+        assert_eq!(module.samples[0].panning, Some(128));
+        let instrument = &module.instruments[0];
+        assert_eq!(instrument.keymap[60], (60, 1));
+        assert_eq!(instrument.fadeout, 1024. / 32768.);
+        let envelope = instrument.volume_envelope.as_ref().unwrap();
+        assert_eq!(envelope.points, vec![(0, 64), (10, 0)]);
+        assert_eq!(envelope.sustain, Some((0, 0)));
+        assert_eq!(envelope.looped, None);
+    }
+
+    #[test]
+    fn parses_impulse_tracker_modules() {
+        let mut bytes = vec![0; 288];
+        put(&mut bytes, 0, b"IMPM");
+        put(&mut bytes, 32, &[2, 0, 0, 0, 1, 0, 1, 0]);
+        put(&mut bytes, 42, &[0x14, 0x02, 9, 0]);
+        put(&mut bytes, 48, &[128, 48, 6, 125]);
+        bytes[64..128].fill(32);
+        put(&mut bytes, 192, &[0, 255]);
+        // sample at 208 and pattern at 292
+        put(&mut bytes, 194, &[208, 0, 0, 0, 36, 1, 0, 0]);
+        put(&mut bytes, 208, b"IMPS");
+        put(&mut bytes, 225, &[64, 1, 64]);
+        put(&mut bytes, 254, &[1, 0, 4, 0, 0, 0]);
+        put(&mut bytes, 268, &[0xab, 0x20, 0, 0]);
+        put(&mut bytes, 280, &[32, 1, 0, 0]);
+        bytes.extend_from_slice(&[0x40, 0, 0xc0, 0]);
+        // C-5 with instrument 1, a note off, and A03 on the third channel
+        let pattern = [0x81, 0x03, 60, 1, 0, 0x01, 255, 1, 0, 0x83, 0x08, 1, 3, 0];
+        bytes.extend_from_slice(&[pattern.len() as u8, 0, 4, 0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&pattern);
+
+        let module = Module::parse(&bytes).unwrap();
+
+        assert_eq!(module.channels, 3);
+        assert!(module.linear_slides);
+        assert_eq!(module.panning, vec![0.5; 3]);
+        assert_eq!(module.samples[0].data, vec![0.5, 0., -0.5, 0.]);
+        assert_eq!(module.samples[0].c5_speed, 8363.);
+        let notes = &module.patterns[0].notes;
+        assert_eq!(notes[0].pitch, Pitch::Key(60));
+        assert_eq!(notes[0].instrument, 1);
+        assert_eq!(notes[3].pitch, Pitch::Off);
+        assert_eq!(notes[3].instrument, 1);
+        assert_eq!(notes[8].effect, Effect::SetSpeed(3));
+    }
+
+    #[test]
+    fn rejects_compressed_samples_with_invalid_bit_widths() {
+        // a 9 bit value with the highest bit set switches the width to its low byte plus one
+        for block in [[0xff, 0x01], [0x0a, 0x01]] {
+            let data = [2, 0, block[0], block[1]];
+
+            assert!(matches!(
+                it_decompress(&data, 4, false, false),
+                Err(ModLoaderError::InvalidFile(_))
+            ));
+        }
+    }
+}
@@ -8,8 +8,11 @@ use std::io::Cursor;
 use thiserror::Error;
 
 use crate::source::AudioSource;
+use crate::source::loop_points::wav_loop_region;
 
 /// Asset loader for WAV files.
+///
+/// The first loop of a `smpl` chunk sets the loop region of the loaded sound.
 #[derive(Default, TypePath)]
 pub struct WavLoader;
 
@@ -38,8 +41,9 @@ impl AssetLoader for WavLoader {
     ) -> Result<Self::Asset, Self::Error> {
         let mut sound_bytes = vec![];
         reader.read_to_end(&mut sound_bytes).await?;
+        let loop_region = wav_loop_region(&sound_bytes);
         let sound = StaticSoundData::from_cursor(Cursor::new(sound_bytes))?;
-        Ok(AudioSource { sound })
+        Ok(AudioSource { sound, loop_region })
     }
     fn extensions(&self) -> &[&str] {
         &["wav"]
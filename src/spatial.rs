@@ -1,17 +1,35 @@
+mod occlusion;
+mod reverb;
+mod shape;
+
+pub use occlusion::{
+    SpatialAudioApp, SpatialOcclusion, SpatialOcclusionInput, SpatialOcclusionSettings,
+};
+pub(crate) use reverb::SpatialReverbParameters;
+pub use reverb::{ReverbZone, ReverbZoneShape};
+pub use shape::SpatialAudioEmitterShape;
+
+use crate::audio_output::{AudioOutput, OPEN_LOW_PASS_CUTOFF};
+use crate::parameters::AudioParameters;
 use crate::{AudioInstance, AudioSystemSet, AudioTween};
 use bevy::app::{App, Plugin, PostUpdate, PreUpdate};
 use bevy::asset::{Assets, Handle};
 use bevy::ecs::component::Component;
 use bevy::ecs::{
-    change_detection::{Res, ResMut},
-    query::With,
+    change_detection::{NonSendMut, Res, ResMut},
+    entity::Entity,
+    query::{Added, With},
     resource::Resource,
     schedule::IntoScheduleConfigs,
-    system::Query,
+    system::{Query, SystemParam},
 };
-use bevy::math::Vec3;
-use bevy::prelude::{Curve, EaseFunction, EasingCurve};
+use bevy::math::{Vec2, Vec3};
+use bevy::prelude::{Curve, EaseFunction, EasingCurve, RemovedComponents};
+use bevy::time::Time;
 use bevy::transform::components::{GlobalTransform, Transform};
+use occlusion::update_spatial_occlusion;
+use reverb::update_reverb_zones;
+use shape::emitter_position;
 use std::f32::consts::PI;
 
 /// This plugin adds basic spatial audio.
@@ -19,17 +37,50 @@ use std::f32::consts::PI;
 /// Add `SpatialAudioEmitter` components to entities that emit spacial audio.
 /// One entity, usually the "Player" or the Camera should get the `SpatialAudioReceiver` component.
 ///
+/// Sounds played with [`PlayAudioCommand::with_emitter`](crate::PlayAudioCommand::with_emitter)
+/// are automatically added to the emitter and routed through their own track. This allows
+/// filtering them, for example with the low-pass of a [`SpatialAudioCone`].
+///
+/// Occlusion of emitters, for example by walls, can be configured with
+/// [`SpatialAudioApp::set_spatial_occlusion`]. Add [`ReverbZone`]s for rooms or caves.
+/// Ambient sources like rivers can be given a [`SpatialAudioEmitterShape`].
+///
 /// See the `spacial` example of `bevy_kira_audio`.
 pub struct SpatialAudioPlugin;
 
 impl Plugin for SpatialAudioPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<DefaultSpatialRadius>()
+            .init_resource::<SpatialAudioMode>()
+            .init_resource::<SpatialVirtualisation>()
+            .init_resource::<DefaultSpatialLowPass>()
+            .init_resource::<SpatialOcclusionSettings>()
             .add_systems(
                 PreUpdate,
                 cleanup_stopped_spatial_instances.in_set(AudioSystemSet::InstanceCleanup),
             )
-            .add_systems(PostUpdate, run_spatial_audio);
+            .add_systems(
+                PreUpdate,
+                remove_emitter_tracks.in_set(AudioSystemSet::InstanceCleanup),
+            )
+            .add_systems(
+                PostUpdate,
+                add_spatial_emitters
+                    .before(AudioSystemSet::PlayTypedChannels)
+                    .before(AudioSystemSet::PlayDynamicChannels),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    update_spatial_occlusion,
+                    run_spatial_audio
+                        .after(AudioSystemSet::PlayTypedChannels)
+                        .after(AudioSystemSet::PlayDynamicChannels)
+                        .after(AudioSystemSet::UpdateParameters),
+                )
+                    .chain(),
+            )
+            .add_systems(PostUpdate, update_reverb_zones.after(run_spatial_audio));
     }
 }
 
@@ -79,6 +130,181 @@ pub struct SpatialRadius {
     pub radius: f32,
 }
 
+/// Configuration resource for how positions are interpreted in spatial audio
+///
+/// This resource will be initialized by the `SpatialAudioPlugin` in three-dimensional mode.
+#[derive(Resource, Clone, Debug, Default)]
+pub enum SpatialAudioMode {
+    /// Emitters are attenuated by their distance in 3D space and panned based on the angle
+    /// between the sound path and the right ear of the receiver
+    #[default]
+    ThreeDimensional,
+    /// The Z coordinate is ignored
+    ///
+    /// Emitters are attenuated by their distance on the XY plane and panned by their horizontal
+    /// offset to the receiver. This works well with 2D cameras, which usually have a Z offset
+    /// to the rest of the scene.
+    TwoDimensional {
+        /// Horizontal distance between hard left and hard right panning
+        ///
+        /// Usually, this is the width of the screen in world units.
+        stereo_width: f32,
+    },
+}
+
+impl SpatialAudioMode {
+    fn project(&self, sound_path: Vec3) -> Vec3 {
+        match self {
+            SpatialAudioMode::ThreeDimensional => sound_path,
+            SpatialAudioMode::TwoDimensional { .. } => sound_path.with_z(0.),
+        }
+    }
+
+    fn panning(&self, receiver_right: Vec3, sound_path: Vec3) -> f32 {
+        match self {
+            SpatialAudioMode::ThreeDimensional => {
+                let right_ear_angle = if sound_path == Vec3::ZERO {
+                    PI / 2.
+                } else {
+                    receiver_right.angle_between(sound_path)
+                };
+                right_ear_angle.cos()
+            }
+            SpatialAudioMode::TwoDimensional { stereo_width } => {
+                if *stereo_width <= 0. {
+                    return 0.;
+                }
+                let right = receiver_right.truncate().normalize_or(Vec2::X);
+                (sound_path.truncate().dot(right) / (stereo_width / 2.)).clamp(-1., 1.)
+            }
+        }
+    }
+}
+
+/// Configuration resource for virtualisation of inaudible spatial audio instances
+///
+/// Virtualised instances are stopped on the audio thread, so they do not count towards the
+/// [`sound_capacity`](crate::AudioSettings::sound_capacity) and are not updated every frame.
+/// Their playback position keeps advancing and they are restarted at the correct position
+/// once they become audible again.
+///
+/// This resource will be initialized by the `SpatialAudioPlugin` with virtualisation disabled.
+#[derive(Resource, Clone, Debug)]
+pub struct SpatialVirtualisation {
+    /// Whether inaudible instances should be virtualised
+    pub enabled: bool,
+    /// Instances of emitters with a spatial volume at or below this value in decibels are virtualised
+    ///
+    /// The default of `-60` virtualises emitters beyond their spatial radius.
+    pub audibility_threshold: f32,
+}
+
+impl Default for SpatialVirtualisation {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            audibility_threshold: -60.,
+        }
+    }
+}
+
+/// Configuration resource for the global distance-based low-pass filter
+///
+/// This resource will be initialized by the `SpatialAudioPlugin` without a low-pass filter.
+/// If an emitter does not have a [`SpatialLowPass`], the filter configured here is used.
+#[derive(Resource, Default)]
+pub struct DefaultSpatialLowPass {
+    /// Low-pass filter for emitters without their own [`SpatialLowPass`]
+    ///
+    /// `None` disables distance-based filtering for those emitters.
+    pub low_pass: Option<SpatialLowPass>,
+}
+
+/// Distance-based low-pass filter simulating air absorption
+///
+/// Distant sounds lose their high frequencies. The cutoff frequency of the filter changes from
+/// "open" at distance `0` to `far_cutoff` at the spatial radius of the emitter, following `curve`.
+/// This only affects sounds played with [`PlayAudioCommand::with_emitter`](crate::PlayAudioCommand::with_emitter).
+///
+/// Emitters without this component use the [`DefaultSpatialLowPass`].
+#[derive(Component, Clone, Copy, Debug)]
+pub struct SpatialLowPass {
+    /// Cutoff frequency in Hz at the spatial radius of the emitter
+    pub far_cutoff: f64,
+    /// Curve used to move the cutoff frequency from "open" to `far_cutoff` over the spatial radius
+    pub curve: EaseFunction,
+}
+
+impl Default for SpatialLowPass {
+    fn default() -> Self {
+        Self {
+            far_cutoff: 2_000.,
+            curve: EaseFunction::Linear,
+        }
+    }
+}
+
+impl SpatialLowPass {
+    fn cutoff(&self, progress: f32) -> f64 {
+        let progress = EasingCurve::new(0., 1., self.curve)
+            .sample_unchecked(progress)
+            .clamp(0., 1.);
+        interpolate_cutoff(self.far_cutoff, progress)
+    }
+}
+
+/// Interpolate exponentially between an open filter and the given cutoff,
+/// since we hear frequencies logarithmically
+fn interpolate_cutoff(cutoff: f64, progress: f32) -> f64 {
+    OPEN_LOW_PASS_CUTOFF * (cutoff / OPEN_LOW_PASS_CUTOFF).powf(progress as f64)
+}
+
+/// Component for directional audio emitters
+///
+/// Sounds are played at full volume inside the inner cone around the emitter's forward direction
+/// ([`GlobalTransform::forward`]). Between the inner and the outer cone, the volume and the
+/// optional low-pass filter are interpolated towards their values outside of the outer cone.
+#[derive(Component, Clone, Debug)]
+pub struct SpatialAudioCone {
+    /// Full angle of the inner cone in radians
+    pub inner_angle: f32,
+    /// Full angle of the outer cone in radians
+    pub outer_angle: f32,
+    /// Volume change in decibels for receivers outside of the outer cone
+    pub outer_gain: f32,
+    /// Cutoff frequency in Hz of a low-pass filter for receivers outside of the outer cone
+    ///
+    /// This only affects sounds played with [`PlayAudioCommand::with_emitter`](crate::PlayAudioCommand::with_emitter).
+    pub outer_low_pass: Option<f64>,
+}
+
+impl SpatialAudioCone {
+    /// How far the receiver is outside of the inner cone
+    ///
+    /// `0` means the receiver is inside the inner cone and `1` that it is outside of the outer cone.
+    fn progress(&self, forward: Vec3, to_receiver: Vec3) -> f32 {
+        if to_receiver == Vec3::ZERO {
+            return 0.;
+        }
+        let angle = forward.angle_between(to_receiver);
+        let inner = self.inner_angle / 2.;
+        let outer = (self.outer_angle / 2.).max(inner);
+        if angle <= inner {
+            0.
+        } else if angle >= outer {
+            1.
+        } else {
+            (angle - inner) / (outer - inner)
+        }
+    }
+
+    fn low_pass_cutoff(&self, progress: f32) -> f64 {
+        self.outer_low_pass.map_or(OPEN_LOW_PASS_CUTOFF, |cutoff| {
+            interpolate_cutoff(cutoff, progress)
+        })
+    }
+}
+
 #[derive(Component)]
 struct SpatialDampingCurve(EaseFunction);
 
@@ -88,39 +314,138 @@ impl Default for SpatialDampingCurve {
     }
 }
 
+/// Settings of the spatial audio plugin
+#[derive(SystemParam)]
+struct SpatialSettings<'w> {
+    radius: Res<'w, DefaultSpatialRadius>,
+    low_pass: Res<'w, DefaultSpatialLowPass>,
+    occlusion: Res<'w, SpatialOcclusionSettings>,
+    mode: Res<'w, SpatialAudioMode>,
+    virtualisation: Res<'w, SpatialVirtualisation>,
+}
+
+type EmitterQueryData<'a> = (
+    Entity,
+    &'a GlobalTransform,
+    &'a mut SpatialAudioEmitter,
+    &'a SpatialDampingCurve,
+    Option<&'a SpatialRadius>,
+    Option<&'a SpatialAudioCone>,
+    Option<&'a SpatialLowPass>,
+    Option<&'a mut SpatialOcclusion>,
+    Option<&'a SpatialAudioEmitterShape>,
+    Option<&'a AudioParameters>,
+);
+
 fn run_spatial_audio(
-    spatial_audio: Res<DefaultSpatialRadius>,
+    settings: SpatialSettings,
+    time: Res<Time>,
+    mut audio_output: NonSendMut<AudioOutput>,
     receiver: Query<&GlobalTransform, With<SpatialAudioReceiver>>,
-    emitters: Query<(
-        &GlobalTransform,
-        &SpatialAudioEmitter,
-        &SpatialDampingCurve,
-        Option<&SpatialRadius>,
-    )>,
+    mut emitters: Query<EmitterQueryData>,
     mut audio_instances: ResMut<Assets<AudioInstance>>,
 ) {
+    let SpatialSettings {
+        radius: spatial_audio,
+        low_pass: default_low_pass,
+        occlusion: occlusion_settings,
+        mode,
+        virtualisation,
+    } = settings;
+    for (entity, _, mut emitter, .., occlusion, _, parameters) in emitters.iter_mut() {
+        let new_instances = audio_output.take_new_emitter_instances(entity);
+        // mapped playback rates are applied to existing instances when they change
+        if let Some(playback_rate) =
+            parameters.and_then(|parameters| parameters.emitter.playback_rate)
+        {
+            for instance in new_instances.iter() {
+                if let Some(mut instance) = audio_instances.get_mut(instance) {
+                    instance.set_playback_rate(playback_rate, AudioTween::default());
+                }
+            }
+        }
+        if !new_instances.is_empty() {
+            emitter.instances.extend(new_instances);
+        }
+        if let Some(mut occlusion) = occlusion {
+            occlusion.smooth(time.delta(), occlusion_settings.smoothing);
+        }
+        for instance in emitter.instances.iter() {
+            if let Some(mut instance) = audio_instances.get_mut(instance)
+                && instance.is_virtualised()
+            {
+                instance.advance_virtual_playback(time.delta_secs_f64());
+            }
+        }
+    }
     if let Ok(receiver_transform) = receiver.single() {
-        for (emitter_transform, emitter, damping_curve, range) in emitters.iter() {
-            let sound_path = emitter_transform.translation() - receiver_transform.translation();
+        for (
+            entity,
+            emitter_transform,
+            emitter,
+            damping_curve,
+            range,
+            cone,
+            low_pass,
+            occlusion,
+            shape,
+            parameters,
+        ) in emitters.iter()
+        {
+            let receiver_position = receiver_transform.translation();
+            let sound_path = mode.project(
+                emitter_position(emitter_transform, shape, receiver_position) - receiver_position,
+            );
             let progress = (sound_path.length() / range.map_or(spatial_audio.radius, |r| r.radius))
                 .clamp(0., 1.);
-            let volume: f32 = EasingCurve::new(0., -60., damping_curve.0)
+            let mut volume: f32 = EasingCurve::new(0., -60., damping_curve.0)
                 .sample_unchecked(progress)
                 .clamp(-60., 0.);
+            let mut low_pass_cutoff = low_pass
+                .or(default_low_pass.low_pass.as_ref())
+                .map_or(OPEN_LOW_PASS_CUTOFF, |low_pass| low_pass.cutoff(progress));
+            if let Some(cone) = cone {
+                let cone_progress = cone.progress(*emitter_transform.forward(), -sound_path);
+                volume = (volume + cone.outer_gain * cone_progress).clamp(-60., 0.);
+                low_pass_cutoff = low_pass_cutoff.min(cone.low_pass_cutoff(cone_progress));
+            }
+            if let Some(occlusion) = occlusion {
+                let occlusion = occlusion.current();
+                volume = (volume + occlusion_settings.attenuation * occlusion).clamp(-60., 0.);
+                low_pass_cutoff = low_pass_cutoff.min(interpolate_cutoff(
+                    occlusion_settings.low_pass_cutoff,
+                    occlusion,
+                ));
+            }
 
-            let right_ear_angle = if sound_path == Vec3::ZERO {
-                PI / 2.
-            } else {
-                receiver_transform.right().angle_between(sound_path)
-            };
-            let panning = right_ear_angle.cos();
+            if let Some(parameters) = parameters {
+                volume = (volume + parameters.emitter.volume).clamp(-60., 0.);
+                if let Some(cutoff) = parameters.emitter.low_pass_cutoff {
+                    low_pass_cutoff = low_pass_cutoff.min(cutoff);
+                }
+            }
+
+            let panning = mode.panning(*receiver_transform.right(), sound_path);
 
+            let audible = !virtualisation.enabled || volume > virtualisation.audibility_threshold;
             for instance in emitter.instances.iter() {
                 if let Some(mut instance) = audio_instances.get_mut(instance) {
+                    instance.voice.distance = sound_path.length();
+                    if !audible {
+                        instance.virtualise();
+                    } else if instance.is_virtualised() {
+                        audio_output.restart_virtualised(&mut instance);
+                    }
+                    if instance.is_virtualised() {
+                        continue;
+                    }
                     instance.set_decibels(volume, AudioTween::default());
                     instance.set_panning(panning, AudioTween::default());
                 }
             }
+            if let Some(track) = audio_output.existing_emitter_track(entity) {
+                track.set_low_pass_cutoff(low_pass_cutoff, AudioTween::default());
+            }
         }
     }
 }
@@ -131,9 +456,94 @@ fn cleanup_stopped_spatial_instances(
 ) {
     emitters.iter_mut().for_each(|mut emitter| {
         emitter.instances.retain(|handle| {
-            instances.get(handle).is_none_or(|instance| {
-                !matches!(instance.handle.state(), kira::sound::PlaybackState::Stopped)
-            })
+            instances
+                .get(handle)
+                .is_none_or(|instance| !instance.is_stopped())
         });
     });
 }
+
+fn add_spatial_emitters(
+    mut audio_output: NonSendMut<AudioOutput>,
+    new_emitters: Query<Entity, Added<SpatialAudioEmitter>>,
+) {
+    for emitter in new_emitters.iter() {
+        audio_output.add_spatial_emitter(emitter);
+    }
+}
+
+fn remove_emitter_tracks(
+    mut audio_output: NonSendMut<AudioOutput>,
+    mut removed_emitters: RemovedComponents<SpatialAudioEmitter>,
+) {
+    for emitter in removed_emitters.read() {
+        audio_output.remove_emitter_track(emitter);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn cone() -> SpatialAudioCone {
+        SpatialAudioCone {
+            inner_angle: FRAC_PI_2,
+            outer_angle: PI,
+            outer_gain: -12.,
+            outer_low_pass: Some(1_000.),
+        }
+    }
+
+    #[test]
+    fn cone_has_no_effect_in_front_of_emitter() {
+        let cone = cone();
+        let progress = cone.progress(Vec3::NEG_Z, Vec3::new(0.1, 0., -1.));
+
+        assert_eq!(progress, 0.);
+        assert_eq!(cone.low_pass_cutoff(progress), OPEN_LOW_PASS_CUTOFF);
+    }
+
+    #[test]
+    fn cone_fully_applies_behind_emitter() {
+        let cone = cone();
+        let progress = cone.progress(Vec3::NEG_Z, Vec3::Z);
+
+        assert_eq!(progress, 1.);
+        assert!((cone.low_pass_cutoff(progress) - 1_000.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn cone_interpolates_between_inner_and_outer_angle() {
+        let cone = cone();
+        let progress = cone.progress(Vec3::NEG_Z, Vec3::X);
+
+        assert!((progress - 1.).abs() < 1e-6);
+        let angle = 3. * PI / 8.;
+        let halfway = cone.progress(Vec3::NEG_Z, Vec3::new(angle.sin(), 0., -angle.cos()));
+        assert!((halfway - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn two_dimensional_mode_ignores_z() {
+        let mode = SpatialAudioMode::TwoDimensional { stereo_width: 100. };
+        let sound_path = mode.project(Vec3::new(25., 0., -1000.));
+
+        assert_eq!(sound_path, Vec3::new(25., 0., 0.));
+        assert_eq!(mode.panning(Vec3::X, sound_path), 0.5);
+        assert_eq!(mode.panning(Vec3::X, Vec3::new(-80., 3., 0.)), -1.);
+    }
+
+    #[test]
+    fn low_pass_closes_over_distance() {
+        let low_pass = SpatialLowPass {
+            far_cutoff: 500.,
+            curve: EaseFunction::Linear,
+        };
+
+        assert_eq!(low_pass.cutoff(0.), OPEN_LOW_PASS_CUTOFF);
+        assert!(low_pass.cutoff(0.5) < OPEN_LOW_PASS_CUTOFF);
+        assert!(low_pass.cutoff(0.5) > 500.);
+        assert!((low_pass.cutoff(1.) - 500.).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,227 @@
+//! Occlusion and obstruction of spatial audio emitters
+
+use crate::spatial::shape::emitter_position;
+use crate::spatial::{SpatialAudioEmitter, SpatialAudioEmitterShape, SpatialAudioReceiver};
+use bevy::app::App;
+use bevy::ecs::component::Component;
+use bevy::ecs::entity::Entity;
+use bevy::ecs::query::With;
+use bevy::ecs::resource::Resource;
+use bevy::ecs::system::{In, IntoSystem, SystemId};
+use bevy::ecs::world::World;
+use bevy::log::warn;
+use bevy::math::Vec3;
+use bevy::transform::components::GlobalTransform;
+use std::time::Duration;
+
+/// Input of the occlusion system registered with [`SpatialAudioApp::set_spatial_occlusion`]
+#[derive(Clone, Copy, Debug)]
+pub struct SpatialOcclusionInput {
+    /// The entity with the [`SpatialAudioEmitter`]
+    pub emitter: Entity,
+    /// Global position of the emitter
+    ///
+    /// For emitters with a [`SpatialAudioEmitterShape`], this is the point of the shape closest to the receiver.
+    pub emitter_position: Vec3,
+    /// The entity with the [`SpatialAudioReceiver`]
+    pub receiver: Entity,
+    /// Global position of the receiver
+    pub receiver_position: Vec3,
+}
+
+/// Extension trait to configure spatial audio
+pub trait SpatialAudioApp {
+    /// Set the system used to calculate the occlusion of spatial audio emitters
+    ///
+    /// The system is run once per emitter and frame. It should return how much the sound path between
+    /// emitter and receiver is blocked, from `0` (free) to `1` (fully occluded). This is usually done
+    /// with raycasts against the level geometry. The result is stored in the [`SpatialOcclusion`]
+    /// component of the emitter and mapped to attenuation and a low-pass filter as configured in
+    /// [`SpatialOcclusionSettings`].
+    ///
+    /// ```
+    /// # use bevy::prelude::*;
+    /// # use bevy_kira_audio::prelude::*;
+    /// # use bevy_kira_audio::SpatialOcclusionInput;
+    /// fn occlusion(In(input): In<SpatialOcclusionInput>, walls: Query<&GlobalTransform, With<Wall>>) -> f32 {
+    ///     // Raycast from `input.emitter_position` to `input.receiver_position` here
+    ///     if walls.is_empty() { 0. } else { 1. }
+    /// }
+    ///
+    /// # #[derive(Component)]
+    /// # struct Wall;
+    /// # fn build(app: &mut App) {
+    /// app.add_plugins(SpatialAudioPlugin)
+    ///     .set_spatial_occlusion(occlusion);
+    /// # }
+    /// ```
+    fn set_spatial_occlusion<M>(
+        &mut self,
+        system: impl IntoSystem<In<SpatialOcclusionInput>, f32, M> + 'static,
+    ) -> &mut Self;
+}
+
+impl SpatialAudioApp for App {
+    fn set_spatial_occlusion<M>(
+        &mut self,
+        system: impl IntoSystem<In<SpatialOcclusionInput>, f32, M> + 'static,
+    ) -> &mut Self {
+        let world = self.world_mut();
+        if let Some(previous) = world.remove_resource::<SpatialOcclusionSystem>() {
+            let _ = world.unregister_system(previous.0);
+        }
+        let system = world.register_system(system);
+        world.insert_resource(SpatialOcclusionSystem(system));
+
+        self
+    }
+}
+
+#[derive(Resource)]
+pub(crate) struct SpatialOcclusionSystem(SystemId<In<SpatialOcclusionInput>, f32>);
+
+/// Configuration resource for mapping occlusion to audio properties
+///
+/// This resource will be initialized by the `SpatialAudioPlugin`.
+#[derive(Resource, Clone, Debug)]
+pub struct SpatialOcclusionSettings {
+    /// Volume change in decibels for fully occluded emitters
+    pub attenuation: f32,
+    /// Cutoff frequency in Hz of a low-pass filter for fully occluded emitters
+    ///
+    /// This only affects sounds played with [`PlayAudioCommand::with_emitter`](crate::PlayAudioCommand::with_emitter).
+    pub low_pass_cutoff: f64,
+    /// Time it roughly takes for a change in occlusion to be applied
+    ///
+    /// Smoothing prevents audible steps ("zipper noise") when occlusion changes abruptly.
+    pub smoothing: Duration,
+}
+
+impl Default for SpatialOcclusionSettings {
+    fn default() -> Self {
+        Self {
+            attenuation: -12.,
+            low_pass_cutoff: 800.,
+            smoothing: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Occlusion of a spatial audio emitter
+///
+/// This component is inserted and updated by the occlusion system set with
+/// [`SpatialAudioApp::set_spatial_occlusion`]. You can also add it yourself and set
+/// the `target` from your own systems.
+#[derive(Component, Clone, Debug, Default)]
+pub struct SpatialOcclusion {
+    /// Occlusion factor from `0` (free) to `1` (fully occluded)
+    pub target: f32,
+    current: f32,
+}
+
+impl SpatialOcclusion {
+    /// Create occlusion that is already at the given factor without smoothing
+    pub fn new(occlusion: f32) -> Self {
+        let occlusion = occlusion.clamp(0., 1.);
+        Self {
+            target: occlusion,
+            current: occlusion,
+        }
+    }
+
+    /// The smoothed occlusion factor currently applied to the emitter
+    pub fn current(&self) -> f32 {
+        self.current
+    }
+
+    pub(crate) fn smooth(&mut self, delta: Duration, smoothing: Duration) {
+        let target = self.target.clamp(0., 1.);
+        if smoothing.is_zero() {
+            self.current = target;
+            return;
+        }
+        let factor = 1. - (-delta.as_secs_f32() / smoothing.as_secs_f32()).exp();
+        self.current += (target - self.current) * factor;
+    }
+}
+
+pub(crate) fn update_spatial_occlusion(world: &mut World) {
+    let Some(system) = world
+        .get_resource::<SpatialOcclusionSystem>()
+        .map(|system| system.0)
+    else {
+        return;
+    };
+    let Ok((receiver, receiver_position)) = world
+        .query_filtered::<(Entity, &GlobalTransform), With<SpatialAudioReceiver>>()
+        .single(world)
+        .map(|(entity, transform)| (entity, transform.translation()))
+    else {
+        return;
+    };
+    let emitters: Vec<(Entity, Vec3)> = world
+        .query_filtered::<
+            (Entity, &GlobalTransform, Option<&SpatialAudioEmitterShape>),
+            With<SpatialAudioEmitter>,
+        >()
+        .iter(world)
+        .map(|(entity, transform, shape)| {
+            (
+                entity,
+                emitter_position(transform, shape, receiver_position),
+            )
+        })
+        .collect();
+
+    for (emitter, emitter_position) in emitters {
+        let input = SpatialOcclusionInput {
+            emitter,
+            emitter_position,
+            receiver,
+            receiver_position,
+        };
+        let occlusion = match world.run_system_with(system, input) {
+            Ok(occlusion) => occlusion.clamp(0., 1.),
+            Err(error) => {
+                warn!("Failed to run spatial occlusion system: {error}");
+                return;
+            }
+        };
+        let Ok(mut emitter) = world.get_entity_mut(emitter) else {
+            continue;
+        };
+        if let Some(mut spatial_occlusion) = emitter.get_mut::<SpatialOcclusion>() {
+            spatial_occlusion.target = occlusion;
+        } else {
+            emitter.insert(SpatialOcclusion::new(occlusion));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn occlusion_is_smoothed() {
+        let mut occlusion = SpatialOcclusion {
+            target: 1.,
+            ..Default::default()
+        };
+        occlusion.smooth(Duration::from_millis(16), Duration::from_millis(100));
+
+        assert!(occlusion.current() > 0.);
+        assert!(occlusion.current() < 1.);
+    }
+
+    #[test]
+    fn occlusion_without_smoothing_is_applied_directly() {
+        let mut occlusion = SpatialOcclusion {
+            target: 0.7,
+            ..Default::default()
+        };
+        occlusion.smooth(Duration::from_millis(16), Duration::ZERO);
+
+        assert_eq!(occlusion.current(), 0.7);
+    }
+}
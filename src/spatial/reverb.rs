@@ -0,0 +1,191 @@
+//! Reverb zones for spatial audio
+
+use crate::audio_output::AudioOutput;
+use crate::spatial::SpatialAudioReceiver;
+use bevy::ecs::change_detection::NonSendMut;
+use bevy::ecs::component::Component;
+use bevy::ecs::query::With;
+use bevy::ecs::system::Query;
+use bevy::math::Vec3;
+use bevy::transform::components::{GlobalTransform, Transform};
+
+/// Component for zones with reverb, like caves or halls
+///
+/// While the [`SpatialAudioReceiver`] is inside a zone, sounds played with
+/// [`PlayAudioCommand::with_emitter`](crate::PlayAudioCommand::with_emitter) get reverb with the
+/// parameters of the zone. The reverb fades in over `fade_distance` from the boundary of the zone.
+/// If the receiver is inside multiple zones, their parameters are blended.
+#[derive(Component, Clone, Debug)]
+#[require(Transform)]
+pub struct ReverbZone {
+    /// Shape of the zone in the local space of the entity
+    pub shape: ReverbZoneShape,
+    /// Distance from the boundary of the zone over which the reverb fades in
+    pub fade_distance: f32,
+    /// Size of the simulated room from `0` to `1`. Bigger rooms have longer reverb tails.
+    pub room_size: f64,
+    /// How quickly high frequencies decay, from `0` to `1`
+    pub damping: f64,
+    /// Volume of the reverb in decibels
+    pub wet_level: f32,
+}
+
+impl Default for ReverbZone {
+    fn default() -> Self {
+        Self {
+            shape: ReverbZoneShape::Sphere { radius: 10. },
+            fade_distance: 2.,
+            room_size: 0.8,
+            damping: 0.5,
+            wet_level: -6.,
+        }
+    }
+}
+
+/// Shape of a [`ReverbZone`]
+#[derive(Clone, Debug)]
+pub enum ReverbZoneShape {
+    /// A sphere around the origin of the entity
+    Sphere {
+        /// Radius of the sphere
+        radius: f32,
+    },
+    /// A box around the origin of the entity
+    Box {
+        /// Half of the size of the box along each axis
+        half_size: Vec3,
+    },
+}
+
+impl ReverbZoneShape {
+    /// Distance of the given local position to the boundary of the shape
+    ///
+    /// Positive values are inside of the shape.
+    fn depth(&self, position: Vec3) -> f32 {
+        match self {
+            ReverbZoneShape::Sphere { radius } => radius - position.length(),
+            ReverbZoneShape::Box { half_size } => (*half_size - position.abs()).min_element(),
+        }
+    }
+}
+
+impl ReverbZone {
+    /// How much the zone contributes to the reverb at the given local position
+    fn weight(&self, position: Vec3) -> f32 {
+        let depth = self.shape.depth(position);
+        if depth <= 0. {
+            0.
+        } else if self.fade_distance <= 0. {
+            1.
+        } else {
+            (depth / self.fade_distance).min(1.)
+        }
+    }
+}
+
+/// Blended parameters of all reverb zones the receiver is in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct SpatialReverbParameters {
+    pub(crate) volume: f32,
+    pub(crate) feedback: f64,
+    pub(crate) damping: f64,
+}
+
+impl SpatialReverbParameters {
+    pub(crate) const SILENT: SpatialReverbParameters = SpatialReverbParameters {
+        volume: -60.,
+        feedback: 0.8,
+        damping: 0.5,
+    };
+}
+
+fn blend<'a>(zones: impl Iterator<Item = (&'a ReverbZone, f32)>) -> SpatialReverbParameters {
+    let mut total_weight = 0.;
+    let mut amplitude = 0.;
+    let mut feedback = 0.;
+    let mut damping = 0.;
+    for (zone, weight) in zones.filter(|(_, weight)| *weight > 0.) {
+        total_weight += weight;
+        amplitude += weight * 10f32.powf(zone.wet_level / 20.);
+        feedback += weight as f64 * zone.room_size;
+        damping += weight as f64 * zone.damping;
+    }
+    if total_weight <= 0. {
+        return SpatialReverbParameters::SILENT;
+    }
+    let amplitude = amplitude / total_weight.max(1.);
+
+    SpatialReverbParameters {
+        volume: (20. * amplitude.log10()).max(-60.),
+        feedback: (feedback / total_weight as f64).clamp(0., 1.),
+        damping: (damping / total_weight as f64).clamp(0., 1.),
+    }
+}
+
+pub(crate) fn update_reverb_zones(
+    mut audio_output: NonSendMut<AudioOutput>,
+    receiver: Query<&GlobalTransform, With<SpatialAudioReceiver>>,
+    zones: Query<(&GlobalTransform, &ReverbZone)>,
+) {
+    let parameters = if let Ok(receiver_transform) = receiver.single() {
+        let receiver_position = receiver_transform.translation();
+        blend(zones.iter().map(|(zone_transform, zone)| {
+            let local_position = zone_transform
+                .affine()
+                .inverse()
+                .transform_point3(receiver_position);
+            (zone, zone.weight(local_position))
+        }))
+    } else {
+        SpatialReverbParameters::SILENT
+    };
+    audio_output.set_spatial_reverb(parameters);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reverb_fades_in_at_zone_boundary() {
+        let zone = ReverbZone {
+            shape: ReverbZoneShape::Box {
+                half_size: Vec3::splat(5.),
+            },
+            fade_distance: 2.,
+            ..Default::default()
+        };
+
+        assert_eq!(zone.weight(Vec3::new(6., 0., 0.)), 0.);
+        assert_eq!(zone.weight(Vec3::new(4., 0., 0.)), 0.5);
+        assert_eq!(zone.weight(Vec3::ZERO), 1.);
+    }
+
+    #[test]
+    fn blends_overlapping_zones() {
+        let small = ReverbZone {
+            room_size: 0.2,
+            wet_level: -6.,
+            ..Default::default()
+        };
+        let large = ReverbZone {
+            room_size: 0.8,
+            wet_level: -6.,
+            ..Default::default()
+        };
+        let parameters = blend([(&small, 1.), (&large, 1.)].into_iter());
+
+        assert!((parameters.feedback - 0.5).abs() < 1e-6);
+        assert!((parameters.volume + 6.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn silent_outside_of_zones() {
+        let zone = ReverbZone::default();
+
+        assert_eq!(
+            blend([(&zone, 0.)].into_iter()),
+            SpatialReverbParameters::SILENT
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Shapes for spatial audio emitters that are not point sources
+
+use bevy::ecs::component::Component;
+use bevy::math::Vec3;
+use bevy::transform::components::GlobalTransform;
+
+/// Shape of a spatial audio emitter
+///
+/// By default, emitters are point sources at their [`GlobalTransform`]. With a shape, the sound
+/// comes from the point of the shape closest to the receiver. For volumes, the sound plays at full
+/// volume and without panning while the receiver is inside. This is useful for ambient sources like
+/// rivers, shorelines, or crowds.
+///
+/// The shape is defined in the local space of the emitter.
+#[derive(Component, Clone, Debug)]
+pub enum SpatialAudioEmitterShape {
+    /// A sphere around the origin of the emitter
+    Sphere {
+        /// Radius of the sphere
+        radius: f32,
+    },
+    /// A box around the origin of the emitter
+    Box {
+        /// Half of the size of the box along each axis
+        half_size: Vec3,
+    },
+    /// A line through the given points
+    ///
+    /// Splines can be used by sampling them into points, for example with Bevy's curve API.
+    Polyline {
+        /// Points of the line
+        points: Vec<Vec3>,
+    },
+}
+
+impl SpatialAudioEmitterShape {
+    /// The point of the shape closest to the given local position
+    fn closest_local_point(&self, position: Vec3) -> Vec3 {
+        match self {
+            SpatialAudioEmitterShape::Sphere { radius } => {
+                if position.length() <= *radius {
+                    position
+                } else {
+                    position.normalize() * *radius
+                }
+            }
+            SpatialAudioEmitterShape::Box { half_size } => position.clamp(-*half_size, *half_size),
+            SpatialAudioEmitterShape::Polyline { points } => match points.as_slice() {
+                [] => Vec3::ZERO,
+                [point] => *point,
+                points => points
+                    .windows(2)
+                    .map(|segment| closest_point_on_segment(segment[0], segment[1], position))
+                    .min_by(|a, b| {
+                        a.distance_squared(position)
+                            .total_cmp(&b.distance_squared(position))
+                    })
+                    .unwrap_or(Vec3::ZERO),
+            },
+        }
+    }
+}
+
+fn closest_point_on_segment(start: Vec3, end: Vec3, position: Vec3) -> Vec3 {
+    let segment = end - start;
+    let length_squared = segment.length_squared();
+    if length_squared == 0. {
+        return start;
+    }
+    let progress = ((position - start).dot(segment) / length_squared).clamp(0., 1.);
+    start + segment * progress
+}
+
+/// The position sound of the given emitter is coming from, as heard by a receiver at `receiver_position`
+pub(crate) fn emitter_position(
+    emitter_transform: &GlobalTransform,
+    shape: Option<&SpatialAudioEmitterShape>,
+    receiver_position: Vec3,
+) -> Vec3 {
+    let Some(shape) = shape else {
+        return emitter_transform.translation();
+    };
+    let affine = emitter_transform.affine();
+    let local_receiver = affine.inverse().transform_point3(receiver_position);
+    affine.transform_point3(shape.closest_local_point(local_receiver))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn receiver_inside_volume_hears_sound_at_own_position() {
+        let shape = SpatialAudioEmitterShape::Box {
+            half_size: Vec3::new(5., 1., 5.),
+        };
+        let transform = GlobalTransform::from_xyz(10., 0., 0.);
+        let receiver = Vec3::new(12., 0.5, -3.);
+
+        assert_eq!(
+            emitter_position(&transform, Some(&shape), receiver),
+            receiver
+        );
+    }
+
+    #[test]
+    fn sphere_uses_closest_point_on_surface() {
+        let shape = SpatialAudioEmitterShape::Sphere { radius: 2. };
+        let transform = GlobalTransform::IDENTITY;
+
+        assert_eq!(
+            emitter_position(&transform, Some(&shape), Vec3::new(0., 0., 10.)),
+            Vec3::new(0., 0., 2.)
+        );
+    }
+
+    #[test]
+    fn polyline_follows_receiver() {
+        let shape = SpatialAudioEmitterShape::Polyline {
+            points: vec![Vec3::ZERO, Vec3::new(10., 0., 0.), Vec3::new(10., 0., 10.)],
+        };
+        let transform = GlobalTransform::IDENTITY;
+
+        assert_eq!(
+            emitter_position(&transform, Some(&shape), Vec3::new(4., 0., -3.)),
+            Vec3::new(4., 0., 0.)
+        );
+        assert_eq!(
+            emitter_position(&transform, Some(&shape), Vec3::new(15., 0., 6.)),
+            Vec3::new(10., 0., 6.)
+        );
+    }
+}
@@ -0,0 +1,396 @@
+//! Voice limiting, stealing and debouncing
+
+use crate::source::AudioSource;
+use crate::source::custom::CustomAudioSource;
+use bevy::asset::AssetId;
+use bevy::ecs::resource::Resource;
+use bevy::math::curve::Curve;
+use bevy::platform::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Limit for the number of sounds playing at the same time
+///
+/// Voice limits can be set per channel with [`AudioControl::set_voice_limit`](crate::AudioControl::set_voice_limit)
+/// and per audio source in the [`AudioSourcePolicies`] resource.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoiceLimit {
+    /// Maximum number of sounds playing or paused at the same time
+    pub max_voices: usize,
+    /// Which sound to stop if a new sound would exceed the limit
+    pub stealing: VoiceStealing,
+}
+
+impl VoiceLimit {
+    /// Create a new voice limit
+    pub const fn new(max_voices: usize, stealing: VoiceStealing) -> Self {
+        Self {
+            max_voices,
+            stealing,
+        }
+    }
+}
+
+/// What happens when a new sound would exceed a [`VoiceLimit`]
+///
+/// Sounds with a higher priority than the new sound are never stopped
+/// (see [`PlayAudioCommand::with_priority`](crate::PlayAudioCommand::with_priority)).
+/// If there is no sound that can be stopped, the new sound is rejected.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VoiceStealing {
+    /// Stop the sound that started playing first
+    #[default]
+    Oldest,
+    /// Stop the sound with the lowest volume
+    Quietest,
+    /// Stop the sound with the largest distance to the spatial audio receiver
+    Farthest,
+    /// Stop the sound with the lowest priority, the oldest one if there are multiple
+    LowestPriority,
+    /// Do not play the new sound, unless there is a sound with a lower priority
+    RejectNew,
+}
+
+/// Resource to configure playback policies per audio source
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_kira_audio::prelude::*;
+/// fn limit_explosions(asset_server: Res<AssetServer>, mut policies: ResMut<AudioSourcePolicies>) {
+///     policies.set_voice_limit(
+///         &asset_server.load::<AudioSource>("sounds/explosion.ogg"),
+///         VoiceLimit::new(4, VoiceStealing::Oldest),
+///     );
+/// }
+/// ```
+#[derive(Resource, Default, Clone, Debug)]
+pub struct AudioSourcePolicies {
+    voice_limits: HashMap<AssetId<AudioSource>, VoiceLimit>,
+    debounces: HashMap<AssetId<AudioSource>, AudioDebounce>,
+}
+
+impl AudioSourcePolicies {
+    /// Limit the number of instances of the given source playing at the same time over all channels
+    pub fn set_voice_limit(
+        &mut self,
+        source: impl Into<AssetId<AudioSource>>,
+        limit: VoiceLimit,
+    ) -> &mut Self {
+        self.voice_limits.insert(source.into(), limit);
+
+        self
+    }
+
+    /// Remove the voice limit of the given source
+    pub fn remove_voice_limit(&mut self, source: impl Into<AssetId<AudioSource>>) -> &mut Self {
+        self.voice_limits.remove(&source.into());
+
+        self
+    }
+
+    /// Get the voice limit of the given source
+    pub fn voice_limit(&self, source: impl Into<AssetId<AudioSource>>) -> Option<&VoiceLimit> {
+        self.voice_limits.get(&source.into())
+    }
+
+    /// Debounce plays of the given source over all channels
+    pub fn set_debounce(
+        &mut self,
+        source: impl Into<AssetId<AudioSource>>,
+        debounce: AudioDebounce,
+    ) -> &mut Self {
+        self.debounces.insert(source.into(), debounce);
+
+        self
+    }
+
+    /// Remove the debounce of the given source
+    pub fn remove_debounce(&mut self, source: impl Into<AssetId<AudioSource>>) -> &mut Self {
+        self.debounces.remove(&source.into());
+
+        self
+    }
+
+    /// Get the debounce of the given source
+    pub fn debounce(&self, source: impl Into<AssetId<AudioSource>>) -> Option<&AudioDebounce> {
+        self.debounces.get(&source.into())
+    }
+}
+
+/// Policy to merge plays of the same audio source that happen in quick succession
+///
+/// Without debouncing, ten enemies dying in the same frame play the same sound ten times
+/// on top of each other. A debounced source only plays once and can get louder instead.
+/// Merged plays do not create an audio instance, so their handles report
+/// [`PlaybackState::Stopped`](crate::PlaybackState::Stopped).
+///
+/// Debouncing can be set per channel with [`AudioControl::set_debounce`](crate::AudioControl::set_debounce)
+/// and per audio source in the [`AudioSourcePolicies`] resource.
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_kira_audio::prelude::*;
+/// # use bevy::math::curve::{FunctionCurve, Interval};
+/// # use std::time::Duration;
+/// fn debounce_hits(audio: Res<Audio>) {
+///     audio.set_debounce(Some(
+///         AudioDebounce::new(Duration::from_millis(30))
+///             .with_min_retrigger_interval(Duration::from_millis(80))
+///             .with_volume_boost(FunctionCurve::new(Interval::EVERYWHERE, |merged: f32| {
+///                 (merged * 1.5).min(6.)
+///             })),
+///     ));
+/// }
+/// ```
+#[derive(Clone)]
+pub struct AudioDebounce {
+    /// Plays within this time after the last started play are merged into it
+    pub merge_window: Duration,
+    /// Plays within this time after the last started play are dropped
+    ///
+    /// Plays that are not merged, but still within this interval, do not change the playing sound.
+    pub min_retrigger_interval: Duration,
+    /// Volume increase in decibels of the playing sound by the number of plays merged into it
+    pub volume_boost: Option<Arc<dyn Curve<f32> + Send + Sync>>,
+}
+
+impl AudioDebounce {
+    /// Merge plays within the given time window
+    pub fn new(merge_window: Duration) -> Self {
+        Self {
+            merge_window,
+            min_retrigger_interval: merge_window,
+            volume_boost: None,
+        }
+    }
+
+    /// Drop plays within the given time after the last started play
+    pub fn with_min_retrigger_interval(mut self, min_retrigger_interval: Duration) -> Self {
+        self.min_retrigger_interval = min_retrigger_interval;
+
+        self
+    }
+
+    /// Make the playing sound louder for every play that is merged into it
+    ///
+    /// The curve is sampled with the number of merged plays and returns the volume increase in decibels.
+    pub fn with_volume_boost(mut self, curve: impl Curve<f32> + Send + Sync + 'static) -> Self {
+        self.volume_boost = Some(Arc::new(curve));
+
+        self
+    }
+
+    /// How long the last play is relevant for debouncing
+    pub(crate) fn duration(&self) -> Duration {
+        self.merge_window.max(self.min_retrigger_interval)
+    }
+
+    pub(crate) fn action(&self, since_last_play: Duration) -> Option<DebounceAction> {
+        if since_last_play <= self.merge_window {
+            Some(DebounceAction::Merge)
+        } else if since_last_play < self.min_retrigger_interval {
+            Some(DebounceAction::Drop)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn volume_boost(&self, merged_plays: u32) -> f32 {
+        self.volume_boost
+            .as_ref()
+            .map_or(0., |curve| curve.sample_clamped(merged_plays as f32))
+    }
+}
+
+impl Debug for AudioDebounce {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AudioDebounce")
+            .field("merge_window", &self.merge_window)
+            .field("min_retrigger_interval", &self.min_retrigger_interval)
+            .field("volume_boost", &self.volume_boost.is_some())
+            .finish()
+    }
+}
+
+/// What to do with a play that is debounced
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum DebounceAction {
+    Merge,
+    Drop,
+}
+
+/// The source a voice was played from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum VoiceSource {
+    Audio(AssetId<AudioSource>),
+    Custom(AssetId<CustomAudioSource>),
+}
+
+impl VoiceSource {
+    /// The audio source, if the voice was not played from a custom source
+    pub(crate) fn audio_source(self) -> Option<AssetId<AudioSource>> {
+        match self {
+            VoiceSource::Audio(source) => Some(source),
+            VoiceSource::Custom(_) => None,
+        }
+    }
+}
+
+/// Information about a playing sound used to decide which sound to stop
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Voice {
+    pub(crate) source: VoiceSource,
+    pub(crate) priority: i32,
+    /// Increases with every played sound
+    pub(crate) sequence: u64,
+    /// Last known volume in decibels
+    pub(crate) volume: f32,
+    /// Last known distance to the spatial audio receiver
+    pub(crate) distance: f32,
+}
+
+/// Select the voices to stop before a new sound with the given priority can play
+///
+/// Returns `None` if the new sound should be rejected.
+pub(crate) fn select_victims(
+    voices: &[Voice],
+    limit: &VoiceLimit,
+    priority: i32,
+) -> Option<Vec<usize>> {
+    if voices.len() < limit.max_voices {
+        return Some(vec![]);
+    }
+    let needed = voices.len() + 1 - limit.max_voices;
+    let mut candidates: Vec<usize> = (0..voices.len())
+        .filter(|&index| match limit.stealing {
+            VoiceStealing::RejectNew => voices[index].priority < priority,
+            _ => voices[index].priority <= priority,
+        })
+        .collect();
+    if candidates.len() < needed {
+        return None;
+    }
+    candidates.sort_by(|&a, &b| {
+        let (a, b) = (&voices[a], &voices[b]);
+        match limit.stealing {
+            VoiceStealing::Oldest => a.sequence.cmp(&b.sequence),
+            VoiceStealing::Quietest => a
+                .volume
+                .total_cmp(&b.volume)
+                .then(a.sequence.cmp(&b.sequence)),
+            VoiceStealing::Farthest => b
+                .distance
+                .total_cmp(&a.distance)
+                .then(a.sequence.cmp(&b.sequence)),
+            VoiceStealing::LowestPriority | VoiceStealing::RejectNew => a
+                .priority
+                .cmp(&b.priority)
+                .then(a.sequence.cmp(&b.sequence)),
+        }
+    });
+    candidates.truncate(needed);
+
+    Some(candidates)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bevy::math::curve::{FunctionCurve, Interval};
+
+    fn voice(sequence: u64, priority: i32, volume: f32, distance: f32) -> Voice {
+        Voice {
+            source: VoiceSource::Audio(AssetId::default()),
+            priority,
+            sequence,
+            volume,
+            distance,
+        }
+    }
+
+    fn voices() -> Vec<Voice> {
+        vec![
+            voice(0, 1, -3., 5.),
+            voice(1, 0, -12., 2.),
+            voice(2, 0, -6., 20.),
+        ]
+    }
+
+    #[test]
+    fn plays_without_stealing_below_limit() {
+        let limit = VoiceLimit::new(4, VoiceStealing::Oldest);
+
+        assert_eq!(select_victims(&voices(), &limit, 0), Some(vec![]));
+    }
+
+    #[test]
+    fn steals_according_to_policy() {
+        let voices = voices();
+
+        assert_eq!(
+            select_victims(&voices, &VoiceLimit::new(3, VoiceStealing::Oldest), 1),
+            Some(vec![0])
+        );
+        assert_eq!(
+            select_victims(&voices, &VoiceLimit::new(3, VoiceStealing::Quietest), 1),
+            Some(vec![1])
+        );
+        assert_eq!(
+            select_victims(&voices, &VoiceLimit::new(3, VoiceStealing::Farthest), 1),
+            Some(vec![2])
+        );
+        assert_eq!(
+            select_victims(
+                &voices,
+                &VoiceLimit::new(3, VoiceStealing::LowestPriority),
+                1
+            ),
+            Some(vec![1])
+        );
+    }
+
+    #[test]
+    fn never_steals_higher_priority_voices() {
+        let voices = voices();
+        let limit = VoiceLimit::new(3, VoiceStealing::Oldest);
+
+        assert_eq!(select_victims(&voices, &limit, 0), Some(vec![1]));
+        assert_eq!(select_victims(&voices, &limit, -1), None);
+    }
+
+    #[test]
+    fn reject_new_only_steals_lower_priority_voices() {
+        let voices = voices();
+        let limit = VoiceLimit::new(3, VoiceStealing::RejectNew);
+
+        assert_eq!(select_victims(&voices, &limit, 0), None);
+        assert_eq!(select_victims(&voices, &limit, 1), Some(vec![1]));
+    }
+
+    #[test]
+    fn debounce_merges_and_drops_plays() {
+        let debounce = AudioDebounce::new(Duration::from_millis(20))
+            .with_min_retrigger_interval(Duration::from_millis(50));
+
+        assert_eq!(debounce.action(Duration::ZERO), Some(DebounceAction::Merge));
+        assert_eq!(
+            debounce.action(Duration::from_millis(30)),
+            Some(DebounceAction::Drop)
+        );
+        assert_eq!(debounce.action(Duration::from_millis(50)), None);
+    }
+
+    #[test]
+    fn debounce_boosts_volume_by_merged_plays() {
+        let debounce =
+            AudioDebounce::new(Duration::from_millis(20))
+                .with_volume_boost(FunctionCurve::new(Interval::EVERYWHERE, |merged: f32| {
+                    (merged * 2.).min(5.)
+                }));
+
+        assert_eq!(debounce.volume_boost(1), 2.);
+        assert_eq!(debounce.volume_boost(4), 5.);
+        assert_eq!(AudioDebounce::new(Duration::ZERO).volume_boost(3), 0.);
+    }
+}